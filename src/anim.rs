@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::decoder::{QOIHeader, Seed};
+use crate::encoder::ImageEncoder;
+use crate::stream::DELTA_MAGIC;
+
+/* Animations are a small header followed by plain QOI images:
+ *
+ *   "qoia"                 magic
+ *   u32 frame count        big endian, like the QOI header
+ *   u32 delay per frame    milliseconds each frame stays on screen
+ *   QOI images             one per frame, back to back
+ *
+ * So everything past the header is a valid stream for `--stream`, which may
+ * also be a delta stream */
+pub const MAGIC: &[u8; 4] = b"qoia";
+pub const EXTENSION: &str = "qoia";
+
+pub const DEFAULT_DELAY_MS: u32 = 100;
+
+pub fn is_animation(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION))
+}
+
+/* The frame delays if the source starts with an animation header, which is
+ * then consumed. Plain QOI streams are left untouched */
+pub fn read_header<R: BufRead>(source: &mut R) -> Result<Option<Vec<Duration>>, String> {
+    let is_animation = source
+        .fill_buf()
+        .map_err(|err| format!("Failed to read: {}", err))?
+        .starts_with(MAGIC);
+
+    if !is_animation {
+        return Ok(None);
+    }
+
+    let mut word = [0_u8; 4];
+    let mut read_u32 = |source: &mut R| {
+        source
+            .read_exact(&mut word)
+            .map(|_| u32::from_be_bytes(word))
+            .map_err(|err| format!("Truncated animation header: {}", err))
+    };
+
+    read_u32(source)?; // The magic
+    let count = read_u32(source)?;
+
+    // Not trusting the count with an allocation before the delays are actually there
+    let mut delays = Vec::new();
+
+    for _ in 0..count {
+        delays.push(Duration::from_millis(read_u32(source)? as u64));
+    }
+
+    Ok(Some(delays))
+}
+
+/* Encodes every input as a frame of a new animation, returning the frame
+ * count. Frames are decoded one at a time, so they never all sit in memory */
+pub fn create(out: &Path, inputs: &[&Path], delay_ms: u32, delta: bool, options: &LoadOptions) -> Result<u32, String> {
+    if inputs.is_empty() {
+        return Err("No frames to animate".to_string());
+    }
+
+    let result = write_frames(out, inputs, delay_ms, delta, options);
+
+    // Half written animations are useless
+    if result.is_err() {
+        let _ = std::fs::remove_file(out);
+    }
+
+    result.map(|()| inputs.len() as u32)
+}
+
+fn write_frames(out: &Path, inputs: &[&Path], delay_ms: u32, delta: bool, options: &LoadOptions) -> Result<(), String> {
+    let write_error = |err: &dyn std::fmt::Display| format!("Failed to write \"{}\": {}", out.display(), err);
+
+    let file = File::create(out).map_err(|err| write_error(&err))?;
+    let mut sink = BufWriter::new(file);
+
+    sink.write_all(MAGIC).map_err(|err| write_error(&err))?;
+    sink.write_all(&(inputs.len() as u32).to_be_bytes()).map_err(|err| write_error(&err))?;
+
+    for _ in inputs {
+        sink.write_all(&delay_ms.to_be_bytes()).map_err(|err| write_error(&err))?;
+    }
+
+    if delta {
+        sink.write_all(DELTA_MAGIC).map_err(|err| write_error(&err))?;
+    }
+
+    let mut seed = Seed::default();
+
+    for path in inputs {
+        let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options)?;
+
+        if let Some(stop) = &bitmap.stopped {
+            return Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message));
+        }
+
+        let header = QOIHeader {
+            width: bitmap.width,
+            height: bitmap.height,
+            channels: bitmap.channels,
+            colorspace: bitmap.colorspace,
+        };
+
+        ImageEncoder::seeded(&mut sink, &header, seed)
+            .encode_from_iter(bitmap.pixels.iter().copied())
+            .map_err(|err| write_error(&err))?;
+
+        seed = match delta {
+            true => Seed::after(&bitmap.pixels),
+            false => Seed::default(),
+        };
+    }
+
+    sink.flush().map_err(|err| write_error(&err))
+}
@@ -0,0 +1,1477 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{KeyboardState, Scancode};
+use sdl2::mouse::{Cursor, MouseButton, MouseState, SystemCursor};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::sys::SDL_WindowFlags;
+use sdl2::video::WindowContext;
+
+use crate::backdrop::{self, BackdropFit};
+use crate::bindings::{Action, KeyCombo};
+use crate::bitmap::{Bitmap, DecodeStop};
+use crate::cache::ImageCache;
+use crate::clipboard::{Clipboard, ClipboardWatch};
+use crate::config::Config;
+use crate::decoder::Pixel;
+use crate::diffmap::Comparison;
+use crate::display;
+use crate::document::{self, Document};
+use crate::filelist::{FileList, SortOrder};
+use crate::filter::{self, Filter, Kernel};
+use crate::gallery::Gallery;
+use crate::help;
+use crate::hud;
+use crate::launch;
+use crate::loupe;
+use crate::minimap;
+use crate::opmap;
+use crate::record::Recorder;
+use crate::overlay;
+use crate::ruler::Ruler;
+use crate::stream::{FrameStream, StreamEvent};
+use crate::tiling::{self, Tiling};
+use crate::ui;
+use crate::view::{Glide, Scaling, Transition, View};
+use crate::wallpaper;
+use crate::watch::{DirWatcher, FileWatcher};
+
+pub const WINDOW_TITLE: &str = "QOI Viewer";
+
+const CACHE_CAPACITY: usize = 5;
+
+const DELETE_TINT: Color = Color::RGBA(255, 0, 0, 48);
+
+const UNDECODED_TINT: Color = Color::RGBA(255, 0, 0, 64);
+const STOP_MARKER_COLOR: Color = Color::RGBA(255, 0, 255, 255);
+const STOP_MARKER_SIZE: u32 = 15;
+
+const ZOOM_STEP: f32 = 1.25;
+
+// Zoom factor per unit of pinch, which is the diagonal of the touchpad
+const PINCH_GAIN: f32 = 4.0;
+
+// Pinch that makes one zoom step when the scaling only has whole steps
+const PINCH_STEP: f32 = 0.05;
+
+const DIVIDER_COLOR: Color = Color::RGBA(255, 255, 255, 160);
+const DIVIDER_WIDTH: u32 = 2;
+
+/* What left clicks on the image do */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Tool {
+    Inspect,
+    Picker,  // Copies the hovered pixel to the clipboard
+    Measure, // Drags out a ruler
+    Crop,    // Drags out a selection that Enter saves as a new file
+}
+
+impl Tool {
+    fn cursor(self) -> SystemCursor {
+        match self {
+            Tool::Inspect => SystemCursor::Arrow,
+            Tool::Picker | Tool::Measure | Tool::Crop => SystemCursor::Crosshair,
+        }
+    }
+}
+
+/* The viewer window and everything shown in it */
+pub struct App<'a> {
+    canvas: WindowCanvas,
+    crt: &'a TextureCreator<WindowContext>,
+    config: Config,
+
+    files: FileList,
+    cache: ImageCache,
+    doc: Document<'a>,
+
+    // Shown right of the document with the same view, when comparing two images
+    compare: Option<Document<'a>>,
+
+    // Replaces the compared image while shown
+    comparison: Option<(Comparison, Texture<'a>)>,
+
+    // Drawn behind the image instead of the background, see --backdrop
+    backdrop: Option<(Texture<'a>, BackdropFit)>,
+
+    // Set once the image is zoomed or panned, the scaling mode decides otherwise
+    zoomed: Option<View>,
+    transition: Option<Transition>, // Toward `zoomed`, while zooming
+    pan_from: Option<(i32, i32)>, // Where the last drag step ended, while panning
+    glide: Option<Glide>, // Follows the drag, then keeps the view moving once let go of
+    gesture_at: Option<(f32, f32)>, // Center of the fingers after the last gesture step, from 0 to 1
+    pinch: f32, // Pinched since the last zoom step, with integer scaling
+    pointer: (i32, i32),
+
+    // Reloads the document whenever its file changes on disk
+    watcher: FileWatcher,
+    dir_watcher: Option<DirWatcher>, // Jumps to files as they appear with --watch-dir
+
+    tool: Tool,
+    cursor: Cursor, // The cursor stays active only as long as it is alive
+    ruler: Ruler,
+
+    // Created the first time it is shown, so thumbnails are only decoded on demand
+    gallery: Option<Gallery<'a>>,
+    show_gallery: bool,
+
+    show_histogram: bool,
+
+    tiling: Tiling,
+
+    // Text shown in the status bar: what last happened, and the hovered pixel
+    status: String,
+    inspect: String,
+    show_status_bar: bool,
+
+    // Lists the key bindings over everything else until any key is pressed
+    show_help: bool,
+
+    // Shows which chunk op produced each pixel instead of the image
+    show_ops: bool,
+
+    // Applied to every image on display, until cycled back off
+    filter: Option<Arc<dyn Filter>>,
+    filters: Vec<Arc<dyn Filter>>, // Cycled through in this order
+
+    // Kept alive so that copied images stay available
+    clipboard: Option<Clipboard>,
+    clipboard_watch: Option<ClipboardWatch>, // Shows images as they are copied, with --watch-clipboard
+
+    // Set while waiting for the user to confirm moving the file to the trash
+    confirm_delete: bool,
+
+    // Taken at the end of the frame, once everything has been drawn
+    screenshot_requested: bool,
+
+    // Captures every presented frame while recording
+    recorder: Option<Recorder>,
+
+    // When the slideshow last advanced, while it is running
+    slideshow: Option<Instant>,
+
+    // Set while the document is a preview of an image still decoding
+    full_pending: bool,
+
+    // Frames replacing the document as they come due, when playing a stream
+    stream: Option<FrameStream>,
+    show_hud: bool,
+
+    pub running: bool,
+}
+
+impl<'a> App<'a> {
+    pub fn new(
+        canvas: WindowCanvas,
+        crt: &'a TextureCreator<WindowContext>,
+        config: Config,
+        files: FileList,
+    ) -> Result<Self, String> {
+        let mut cache = ImageCache::new(CACHE_CAPACITY, config.decode);
+        let doc = open_current(crt, &files, &mut cache)?;
+
+        let mut app = Self::with_document(canvas, crt, config, files, cache, doc);
+
+        let notes = decode_notes(&app.doc.bitmap);
+        if !notes.is_empty() {
+            app.set_status(&(list_status(&app.files) + notes.as_str()));
+        }
+
+        Ok(app)
+    }
+
+    /* Shows `bitmap` through the transparent parts of every image */
+    pub fn set_backdrop(&mut self, bitmap: &Bitmap, fit: BackdropFit) -> Result<(), String> {
+        self.backdrop = Some((document::gen_texture(self.crt, bitmap)?, fit));
+        Ok(())
+    }
+
+    /* Adds the files that appear in a directory to the list and shows each
+     * as it comes */
+    pub fn watch_dir(&mut self, watcher: DirWatcher) {
+        self.dir_watcher = Some(watcher);
+    }
+
+    /* Shows each image copied to the clipboard from now on, saving it into
+     * `save_dir` if given */
+    pub fn watch_clipboard(&mut self, save_dir: Option<PathBuf>) -> Result<(), String> {
+        let clipboard = self.clipboard.as_mut().ok_or_else(|| arboard::Error::ClipboardNotSupported.to_string())?;
+
+        self.clipboard_watch = Some(ClipboardWatch::new(clipboard, save_dir));
+        Ok(())
+    }
+
+    /* Plays the frames of a stream, starting from its first one */
+    pub fn streaming(
+        canvas: WindowCanvas,
+        crt: &'a TextureCreator<WindowContext>,
+        config: Config,
+        files: FileList,
+        stream: FrameStream,
+        first: Arc<Bitmap>,
+    ) -> Result<Self, String> {
+        let cache = ImageCache::new(CACHE_CAPACITY, config.decode);
+
+        // Frames have no file of their own to reload, delete or save over
+        let path = files.current().unwrap().to_path_buf();
+        let mut doc = Document::new(crt, path, first)?;
+        doc.unsaved = true;
+
+        let mut app = Self::with_document(canvas, crt, config, files, cache, doc);
+
+        let status = format!("streaming {} at {}", app.doc.path.display(), stream.describe_speed());
+        app.set_status(&status);
+
+        app.stream = Some(stream);
+        Ok(app)
+    }
+
+    /* Shows the first two files of the list side by side */
+    pub fn comparing(
+        canvas: WindowCanvas,
+        crt: &'a TextureCreator<WindowContext>,
+        config: Config,
+        files: FileList,
+    ) -> Result<Self, String> {
+        let mut app = Self::new(canvas, crt, config, files)?;
+
+        let path = app.files.peek(1).unwrap().to_path_buf();
+        let bitmap = app.cache.load(&path)?;
+
+        let status = format!("comparing {} with {}", app.doc.path.display(), path.display());
+        app.set_status(&status);
+
+        app.compare = Some(Document::new(crt, path, bitmap)?);
+        Ok(app)
+    }
+
+    fn with_document(
+        canvas: WindowCanvas,
+        crt: &'a TextureCreator<WindowContext>,
+        config: Config,
+        files: FileList,
+        cache: ImageCache,
+        doc: Document<'a>,
+    ) -> Self {
+        let watcher = FileWatcher::new(&doc.path);
+        let full_pending = doc.is_preview();
+
+        let filters = filter::PRESETS
+            .iter()
+            .filter_map(|name| Kernel::preset(name))
+            .chain(config.kernel.clone())
+            .map(|kernel| Arc::new(kernel) as Arc<dyn Filter>)
+            .collect();
+
+        let tool = Tool::Inspect;
+        let cursor = Cursor::from_system(tool.cursor()).unwrap();
+        cursor.set();
+
+        Self {
+            canvas,
+            crt,
+            config,
+
+            files,
+            cache,
+            doc,
+            compare: None,
+            comparison: None,
+            backdrop: None,
+            watcher,
+            dir_watcher: None,
+
+            zoomed: None,
+            transition: None,
+            pan_from: None,
+            glide: None,
+            gesture_at: None,
+            pinch: 0.0,
+            pointer: (0, 0),
+
+            tool,
+            cursor,
+            ruler: Ruler::default(),
+
+            gallery: None,
+            show_gallery: false,
+
+            show_histogram: false,
+            tiling: Tiling::Off,
+
+            status: String::new(),
+            inspect: String::new(),
+            show_status_bar: true,
+            show_help: false,
+            show_ops: false,
+            filter: None,
+            filters,
+
+            clipboard: Clipboard::new().ok(),
+            clipboard_watch: None,
+
+            confirm_delete: false,
+            screenshot_requested: false,
+            recorder: None,
+            slideshow: None,
+            full_pending,
+            stream: None,
+            show_hud: false,
+
+            running: true,
+        }
+    }
+
+    fn view(&self) -> View {
+        let (pane, _) = self.panes();
+        self.zoomed.unwrap_or_else(|| View::new(self.config.scaling, self.doc.size(), pane.size()))
+    }
+
+    /* The view drawn this frame, on its way to `view` while a zoom eases in.
+     * Anything else that changed the view since cuts the transition short */
+    fn shown_view(&self) -> View {
+        self.transition
+            .filter(|transition| Some(transition.to) == self.zoomed)
+            .and_then(|transition| transition.at(Instant::now()))
+            .unwrap_or_else(|| self.view())
+    }
+
+    /* The part of the window the document is drawn in, and the one the
+     * compared image is drawn in */
+    fn panes(&self) -> (Rect, Option<Rect>) {
+        let (width, height) = self.canvas.output_size().unwrap();
+
+        if self.compare.is_none() {
+            return (Rect::new(0, 0, width, height), None);
+        }
+
+        let half = (width / 2).max(1);
+        let right = Rect::new(half as i32, 0, (width - half).max(1), height);
+
+        (Rect::new(0, 0, half, height), Some(right))
+    }
+
+    /* Window coordinates relative to the pane they fall in */
+    fn to_pane(&self, x: i32, y: i32) -> (i32, i32) {
+        match self.panes() {
+            (_, Some(right)) if x >= right.x() => (x - right.x(), y),
+            _ => (x, y),
+        }
+    }
+
+    /* Height of the window bottom that the status bar covers */
+    fn bottom_inset(&self) -> u32 {
+        if self.show_status_bar { ui::status_bar_height() } else { 0 }
+    }
+
+    /* The image point under a click on the minimap, while it is shown */
+    fn minimap_point(&self, x: i32, y: i32) -> Option<(f32, f32)> {
+        let (pane, _) = self.panes();
+        let image = self.doc.size();
+
+        minimap::needed(&self.view(), image, pane.size())
+            .then(|| minimap::image_point(image, pane.size(), self.bottom_inset(), x, y))
+            .flatten()
+    }
+
+    /* Moves the view on while a let go drag still glides. Resetting the zoom
+     * ends the glide */
+    fn advance_glide(&mut self) {
+        let mut glide = match self.glide.take().filter(|_| self.zoomed.is_some()) {
+            Some(glide) => glide,
+            None => return,
+        };
+
+        let step = match glide.step(Instant::now()) {
+            Some(step) => step,
+            None => return,
+        };
+
+        let (pane, _) = self.panes();
+        let (view, stopped) = self.view().glided(step, self.doc.size(), pane.size());
+
+        glide.stop(stopped);
+        self.glide = Some(glide);
+        self.zoomed = Some(view);
+    }
+
+    /* Steps are taken from where the last one is headed, so that quick
+     * repeated steps add up rather than being lost to the transition */
+    fn zoom_by(&mut self, factor: f32, at: (i32, i32)) {
+        let view = match self.config.scaling {
+            Scaling::Integer => self.view().stepped_at(factor > 1.0, at),
+            _ => self.view().zoomed_at(factor, at),
+        };
+        self.transition = Some(Transition::new(self.shown_view(), view));
+        self.zoomed = Some(view);
+
+        self.set_status(&format!("zoom {:.0}%", view.zoom * 100.0));
+    }
+
+    /* Zooms at the pointer like the wheel, which is where it is on a touchpad.
+     * With integer scaling small pinches add up until they make a step */
+    fn pinch_by(&mut self, d_dist: f32) {
+        let at = self.to_pane(self.pointer.0, self.pointer.1);
+
+        if self.config.scaling != Scaling::Integer {
+            return self.zoom_by(1.0 + d_dist * PINCH_GAIN, at);
+        }
+
+        self.pinch += d_dist;
+
+        if self.pinch.abs() >= PINCH_STEP {
+            self.zoom_by(if self.pinch > 0.0 { ZOOM_STEP } else { 1.0 / ZOOM_STEP }, at);
+            self.pinch = 0.0;
+        }
+    }
+
+    /* Shown in the status bar, while the title only names the document */
+    fn set_status(&mut self, status: &str) {
+        self.status = status.to_string();
+
+        let title = format!("{} - {}", WINDOW_TITLE, self.doc.path.display());
+        self.canvas.window_mut().set_title(&title).unwrap();
+    }
+
+    /* Keeps the old document on display when the new one fails to open */
+    fn open_current(&mut self) {
+        match open_current(self.crt, &self.files, &mut self.cache) {
+            Ok(doc) => {
+                self.full_pending = doc.is_preview();
+                self.doc = doc;
+            },
+            Err(err) => {
+                self.set_status(&err);
+                return;
+            },
+        }
+
+        self.ruler = Ruler::default();
+        self.zoomed = None;
+
+        let status = list_status(&self.files) + decode_notes(&self.doc.bitmap).as_str();
+        self.set_status(&status);
+    }
+
+    /* Reloads on file changes and advances the slideshow or stream */
+    pub fn update(&mut self) {
+        if self.pan_from.is_none() {
+            self.advance_glide();
+        }
+
+        if self.stream.is_some() {
+            self.advance_stream();
+            return;
+        }
+
+        if self.full_pending {
+            self.finish_preview();
+        }
+
+        if self.watcher.path() != self.doc.path {
+            self.watcher = FileWatcher::new(&self.doc.path);
+        }
+
+        if !self.doc.unsaved && self.watcher.changed() && self.reload().is_ok() {
+            self.watcher.acknowledge();
+
+            let status = format!("reloaded {}{}", self.doc.path.display(), decode_notes(&self.doc.bitmap));
+            self.set_status(&status);
+        }
+
+        self.advance_clipboard_watch();
+
+        let new = self.dir_watcher.as_mut().map(DirWatcher::poll).unwrap_or_default();
+
+        if let Some(position) = new.into_iter().map(|path| self.files.push(path)).last() {
+            // The gallery holds thumbnails of the old list only
+            self.gallery = None;
+            self.show_gallery = false;
+
+            self.files.set_position(position);
+            self.open_current();
+        }
+
+        if let Some(last) = self.slideshow {
+            if last.elapsed() >= self.config.slideshow_interval && !self.show_gallery {
+                self.slideshow = Some(Instant::now());
+                self.files.next();
+                self.open_current();
+            }
+        }
+    }
+
+    fn advance_clipboard_watch(&mut self) {
+        let (watch, clipboard) = match (self.clipboard_watch.as_mut(), self.clipboard.as_mut()) {
+            (Some(watch), Some(clipboard)) => (watch, clipboard),
+            _ => return,
+        };
+
+        let bitmap = match watch.poll(clipboard) {
+            Some(bitmap) => bitmap,
+            None => return,
+        };
+
+        let saved = watch.save(&bitmap);
+        let path = match &saved {
+            Some(Ok(path)) => path.clone(),
+            _ => clipboard_path(),
+        };
+
+        let status = match (self.show_pasted(path, bitmap), saved) {
+            (Err(err), _) => format!("failed to show clipboard image: {}", err),
+            (Ok(()), Some(Ok(path))) => {
+                self.doc.unsaved = false;
+                format!("saved clipboard image as {}", path.display())
+            },
+            (Ok(()), Some(Err(err))) => format!("failed to save clipboard image: {}", err),
+            (Ok(()), None) => format!("new clipboard image, Ctrl+S saves it as {}", self.doc.path.display()),
+        };
+        self.set_status(&status);
+    }
+
+    /* Shows an image that is not on disk (yet) as a document of its own */
+    fn show_pasted(&mut self, path: PathBuf, bitmap: Bitmap) -> Result<(), String> {
+        self.doc = Document::new(self.crt, path, Arc::new(bitmap))?;
+        self.doc.unsaved = true;
+        self.ruler = Ruler::default();
+        self.zoomed = None;
+
+        Ok(())
+    }
+
+    /* Swaps the full image in for the preview once it is decoded */
+    fn finish_preview(&mut self) {
+        let loaded = match self.cache.ready(&self.doc.path) {
+            Some(loaded) => loaded,
+            None => return,
+        };
+
+        self.full_pending = false;
+
+        let status = match loaded.and_then(|bitmap| self.doc.replace_bitmap(self.crt, bitmap)) {
+            Ok(()) => list_status(&self.files) + decode_notes(&self.doc.bitmap).as_str(),
+            Err(err) => err,
+        };
+        self.set_status(&status);
+    }
+
+    fn advance_stream(&mut self) {
+        if let Some(event) = self.stream.as_mut().and_then(FrameStream::poll) {
+            self.show_stream_event(event);
+        }
+    }
+
+    /* The last frame stays on screen once the stream is over */
+    fn show_stream_event(&mut self, event: StreamEvent) {
+        let decoded = self.stream.as_ref().map_or(0, FrameStream::decoded);
+
+        match event {
+            StreamEvent::Frame(frame) => {
+                if let Err(err) = self.doc.replace_bitmap(self.crt, frame) {
+                    self.set_status(&err);
+                }
+            },
+            StreamEvent::Ended(None) => {
+                self.set_status(&format!("end of stream after {} frames", decoded));
+            },
+            StreamEvent::Ended(Some(err)) => {
+                self.set_status(&format!("stream stopped after {} frames: {}", decoded, err));
+            },
+        }
+    }
+
+    fn control_stream(&mut self, action: Action) {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        let event = match action {
+            Action::PlayPause => {
+                stream.toggle_pause();
+                None
+            },
+            Action::StepForward => stream.step_forward(),
+            Action::StepBack => stream.step_back(),
+            Action::SpeedUp | Action::SpeedDown => {
+                stream.change_speed(action == Action::SpeedUp);
+                None
+            },
+            Action::ToggleLoop => {
+                stream.toggle_loop();
+                None
+            },
+            _ => None,
+        };
+
+        let ended = matches!(event, Some(StreamEvent::Ended(_)));
+
+        if let Some(event) = event {
+            self.show_stream_event(event);
+        }
+
+        // Keeps the end of the stream on display
+        if ended {
+            return;
+        }
+
+        let stream = self.stream.as_ref().unwrap();
+
+        let status = match action {
+            Action::ToggleLoop => format!("loop {}", if stream.looping { "on" } else { "off" }),
+            _ if stream.paused => format!("paused at frame {}", stream.frame_number()),
+            _ => format!("playing at {}", stream.describe_speed()),
+        };
+        self.set_status(&status);
+    }
+
+    /* When the next stream frame is due, so the main loop does not sleep past it */
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.stream.as_ref().and_then(FrameStream::deadline)
+    }
+
+    /* Matched against the window of events when there are several windows */
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /* The mouse state is of whichever window the pointer is over */
+    fn has_mouse(&self) -> bool {
+        self.canvas.window().window_flags() & SDL_WindowFlags::SDL_WINDOW_MOUSE_FOCUS as u32 != 0
+    }
+
+    /* The presets in order, then the configured kernel and any added
+     * filters, then off again */
+    fn next_filter(&self) -> Option<Arc<dyn Filter>> {
+        match &self.filter {
+            None => self.filters.first().cloned(),
+            Some(current) => self.filters.iter().skip_while(|f| !Arc::ptr_eq(f, current)).nth(1).cloned(),
+        }
+    }
+
+    /* Cycled through after the kernels, e.g. those of plugins */
+    pub fn add_filters(&mut self, filters: impl IntoIterator<Item = Arc<dyn Filter>>) {
+        self.filters.extend(filters);
+    }
+
+    /* Decodes the file behind the document again, keeping the old image if that fails */
+    fn reload(&mut self) -> Result<(), String> {
+        self.cache.invalidate(&self.doc.path);
+        let bitmap = self.cache.load(&self.doc.path)?;
+
+        self.doc.replace_bitmap(self.crt, bitmap)?;
+
+        // Built from the old pixels
+        self.comparison = None;
+
+        Ok(())
+    }
+
+    /* Shows `kind` in place of the compared image, or the image again if
+     * `kind` is already shown */
+    fn toggle_comparison(&mut self, kind: Comparison) {
+        let other = match &self.compare {
+            Some(other) => other,
+            None => return,
+        };
+
+        if let Some((shown, _)) = self.comparison.take() {
+            if shown == kind {
+                self.set_status("comparison off");
+                return;
+            }
+        }
+
+        let bitmap = kind.build(&self.doc.bitmap, &other.bitmap, self.config.diff_gain);
+
+        match document::gen_texture(self.crt, &bitmap) {
+            Ok(texture) => {
+                self.comparison = Some((kind, texture));
+                self.set_status(&kind.label(self.config.diff_gain));
+            },
+            Err(err) => self.set_status(&err),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: Event) {
+        let view = self.view();
+
+        if let Event::MouseMotion { x, y, .. } = event {
+            self.pointer = (x, y);
+        }
+
+        match event {
+            Event::Quit { .. } => self.running = false,
+            Event::Window { win_event: WindowEvent::Close, .. } => self.running = false,
+
+            Event::KeyDown { keycode: Some(key), keymod, repeat, .. } => {
+                let action = self.config.bindings.lookup(KeyCombo::from_event(key, keymod));
+
+                // Holding the key that opened the help keeps it open
+                if self.show_help {
+                    self.show_help = repeat;
+                } else if self.confirm_delete {
+                    self.confirm_delete = false;
+
+                    if action == Some(Action::Confirm) {
+                        self.delete_current();
+                    } else {
+                        self.set_status("kept file");
+                    }
+                } else if let Some(action) = action {
+                    self.handle_action(action);
+                }
+            },
+
+            Event::MouseMotion { .. } | Event::MouseButtonDown { .. } | Event::MouseButtonUp { .. }
+            | Event::MouseWheel { .. } | Event::MultiGesture { .. }
+                if self.show_gallery => {},
+
+            Event::MouseWheel { y, .. } if y != 0 => {
+                let factor = if y > 0 { ZOOM_STEP } else { 1.0 / ZOOM_STEP };
+                self.zoom_by(factor, self.to_pane(self.pointer.0, self.pointer.1));
+            },
+
+            // Fingers moving together pan, as dragging does, and spreading or
+            // pinching them zooms
+            Event::MultiGesture { d_dist, x, y, num_fingers: 2.., .. } => {
+                let (pane, _) = self.panes();
+
+                // Kept until the fingers have moved a whole pixel, so slow
+                // gestures still pan
+                let (from_x, from_y) = *self.gesture_at.get_or_insert((x, y));
+                let dx = ((x - from_x) * pane.width() as f32).round() as i32;
+                let dy = ((y - from_y) * pane.height() as f32).round() as i32;
+
+                if (dx, dy) != (0, 0) {
+                    self.zoomed = Some(view.panned(dx, dy));
+                    self.gesture_at = Some((x, y));
+                }
+
+                if d_dist != 0.0 {
+                    self.pinch_by(d_dist);
+                }
+            },
+            // The center jumps when a finger is added or lifted
+            Event::FingerDown { .. } | Event::FingerUp { .. } => {
+                self.gesture_at = None;
+                self.pinch = 0.0;
+            },
+
+            Event::MouseButtonDown { mouse_btn: MouseButton::Right, x, y, .. } => {
+                self.pan_from = Some((x, y));
+                self.glide = Some(Glide::start());
+            },
+            Event::MouseButtonUp { mouse_btn: MouseButton::Right, .. } => {
+                self.pan_from = None;
+                self.glide = self.glide.and_then(Glide::release);
+            },
+            Event::MouseMotion { x, y, .. } if self.pan_from.is_some() => {
+                let (from_x, from_y) = self.pan_from.replace((x, y)).unwrap();
+
+                if let Some(glide) = self.glide.as_mut() {
+                    glide.track(x - from_x, y - from_y);
+                }
+                self.zoomed = Some(view.panned(x - from_x, y - from_y));
+            },
+
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } if self.minimap_point(x, y).is_some() => {
+                let point = self.minimap_point(x, y).unwrap();
+                self.zoomed = Some(view.centered_on(point, self.panes().0.size()).aligned());
+            },
+            Event::MouseMotion { x, y, .. }
+                if matches!(self.tool, Tool::Measure | Tool::Crop) && self.ruler.measurement.is_some() =>
+            {
+                let (x, y) = self.to_pane(x, y);
+                self.ruler.drag(view.clamped_pixel_under(x, y, self.doc.size()));
+
+                let status = self.ruler.measurement.unwrap().describe();
+                self.set_status(&status);
+            },
+            Event::MouseMotion { x, y, .. } => {
+                let (x, y) = self.to_pane(x, y);
+
+                self.inspect = match &self.compare {
+                    _ if self.doc.is_preview() => "decoding...".to_string(),
+                    Some(other) => describe_pixels(&self.doc.bitmap, &other.bitmap, &view, x, y),
+                    None => describe_pixel(&self.doc.bitmap, &view, x, y),
+                };
+            },
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. }
+                if matches!(self.tool, Tool::Measure | Tool::Crop) =>
+            {
+                let (x, y) = self.to_pane(x, y);
+
+                if let Some(pixel) = view.pixel_under(x, y, self.doc.size()) {
+                    self.ruler.begin(pixel);
+                }
+            },
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. }
+                if matches!(self.tool, Tool::Measure | Tool::Crop) =>
+            {
+                self.ruler.end();
+            },
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. }
+                if self.tool == Tool::Picker && !self.doc.is_preview() =>
+            {
+                let (x, y) = self.to_pane(x, y);
+
+                let picked = view
+                    .pixel_under(x, y, self.doc.bitmap.size())
+                    .and_then(|(px, py)| self.doc.bitmap.pixel_at(px, py));
+
+                if let Some(p) = picked {
+                    let hex = p.to_hex();
+
+                    let copied = self.canvas.window().subsystem().clipboard().set_clipboard_text(&hex);
+
+                    match copied {
+                        Ok(()) => self.set_status(&format!("copied {}", hex)),
+                        Err(err) => {
+                            tracing::warn!("Failed to set clipboard text: {}", err);
+                            self.set_status("copy failed");
+                        },
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.running = false,
+
+            // A stream is a single document that only the stream itself changes
+            Action::NextImage | Action::PrevImage | Action::ToggleGallery | Action::Slideshow
+            | Action::Shuffle | Action::CycleSort | Action::SaveImage
+                if self.stream.is_some() => {},
+
+            // These work on the pixels, and only a preview has been decoded so far
+            Action::CopyImage | Action::SaveImage | Action::SetWallpaper | Action::ToggleDiff
+            | Action::ToggleOnionSkin | Action::Confirm | Action::ConfirmAlt
+                if self.doc.is_preview() && !self.show_gallery => self.set_status("still decoding the full image"),
+
+            // A comparison stays on the two images it was opened with
+            Action::NextImage | Action::PrevImage | Action::ToggleGallery | Action::Slideshow
+            | Action::Delete | Action::PasteImage
+                if self.compare.is_some() => {},
+
+            Action::NextImage | Action::PrevImage | Action::SelectUp | Action::SelectDown
+                if self.show_gallery =>
+            {
+                let (dx, dy) = match action {
+                    Action::PrevImage => (-1, 0),
+                    Action::NextImage => (1, 0),
+                    Action::SelectUp => (0, -1),
+                    _ => (0, 1),
+                };
+
+                let (window_width, _) = self.canvas.output_size().unwrap();
+                if let Some(gallery) = self.gallery.as_mut() {
+                    gallery.move_selection(dx, dy, self.files.len(), window_width);
+                }
+            },
+            Action::Confirm if self.show_gallery => {
+                if let Some(gallery) = self.gallery.as_ref() {
+                    self.files.set_position(gallery.selected);
+                }
+
+                self.show_gallery = false;
+                self.open_current();
+            },
+
+            Action::NextImage | Action::PrevImage => {
+                if action == Action::NextImage {
+                    self.files.next();
+                } else {
+                    self.files.prev();
+                }
+
+                self.open_current();
+            },
+            Action::SelectUp | Action::SelectDown => {},
+
+            Action::Confirm | Action::ConfirmAlt => {
+                if let (Tool::Crop, Some(selection)) = (self.tool, self.ruler.measurement) {
+                    let extension = if action == Action::ConfirmAlt { "png" } else { "qoi" };
+
+                    let (x, y, w, h) = selection.bounds();
+                    let out = crop_path(&self.doc.path, (x, y, w, h), extension);
+
+                    let status = match self.doc.bitmap.crop(x, y, w, h).save(&out) {
+                        Ok(()) => format!("saved {}", out.display()),
+                        Err(err) => format!("failed to save {}: {}", out.display(), err),
+                    };
+                    self.set_status(&status);
+                }
+            },
+
+            Action::ToggleHistogram => self.show_histogram = !self.show_histogram,
+
+            Action::ZoomIn | Action::ZoomOut => {
+                let center = self.panes().0.center();
+                let factor = if action == Action::ZoomIn { ZOOM_STEP } else { 1.0 / ZOOM_STEP };
+
+                self.zoom_by(factor, (center.x(), center.y()));
+            },
+            Action::ResetZoom => {
+                self.zoomed = None;
+                self.set_status("zoom reset");
+            },
+            Action::ToggleStatusBar => self.show_status_bar = !self.show_status_bar,
+            Action::Help => self.show_help = true,
+            Action::SetWallpaper => {
+                let status = match wallpaper::set(&self.doc.bitmap) {
+                    Ok(path) => format!("set as wallpaper, saved to {}", path.display()),
+                    Err(err) => format!("failed to set wallpaper: {}", err),
+                };
+                self.set_status(&status);
+            },
+            Action::NextMonitor => {
+                let status = match display::move_to_next(self.canvas.window_mut()) {
+                    Ok((_, 1)) => "only one monitor".to_string(),
+                    Ok((monitor, count)) => format!("monitor {} of {}", monitor, count),
+                    Err(err) => format!("failed to move the window: {}", err),
+                };
+                self.set_status(&status);
+            },
+            Action::CycleFilter => {
+                self.filter = self.next_filter();
+
+                let status = match self.doc.filter(self.crt, self.filter.as_ref()) {
+                    Ok(()) => match &self.filter {
+                        Some(filter) => format!("filter: {}", filter),
+                        None => "filter off".to_string(),
+                    },
+                    Err(err) => {
+                        self.filter = None;
+                        err
+                    },
+                };
+                self.set_status(&status);
+            },
+            Action::CycleTiling => {
+                self.tiling = self.tiling.cycle();
+                self.set_status(self.tiling.label());
+            },
+            Action::ToggleDiff => self.toggle_comparison(Comparison::Heatmap),
+            Action::ToggleOnionSkin => self.toggle_comparison(Comparison::OnionSkin),
+
+            Action::PlayPause | Action::StepForward | Action::StepBack
+            | Action::SpeedUp | Action::SpeedDown | Action::ToggleLoop => self.control_stream(action),
+            Action::ToggleHud => self.show_hud = !self.show_hud && self.stream.is_some(),
+
+            Action::ToggleOpView => {
+                self.show_ops = !self.show_ops;
+
+                let status = if !self.show_ops {
+                    "op view off".to_string()
+                } else {
+                    match self.doc.build_op_view(self.crt) {
+                        Ok(()) => format!("op view: {}", opmap::LEGEND),
+                        Err(err) => err,
+                    }
+                };
+                self.set_status(&status);
+            },
+            Action::ToggleGallery => {
+                self.show_gallery = !self.show_gallery;
+
+                let (files, options) = (&self.files, self.config.decode);
+                let gallery = self.gallery.get_or_insert_with(|| Gallery::new(files.iter(), files.position(), options));
+                gallery.selected = files.position();
+            },
+
+            Action::PickerTool | Action::MeasureTool | Action::CropTool => {
+                let selected = match action {
+                    Action::PickerTool => Tool::Picker,
+                    Action::MeasureTool => Tool::Measure,
+                    _ => Tool::Crop,
+                };
+                self.tool = if self.tool == selected { Tool::Inspect } else { selected };
+
+                self.ruler = Ruler::default();
+
+                self.cursor = Cursor::from_system(self.tool.cursor()).unwrap();
+                self.cursor.set();
+            },
+
+            // Handled while rendering, for as long as the key is held
+            Action::Loupe => {},
+
+            Action::Screenshot => self.screenshot_requested = true,
+            Action::ToggleRecording => {
+                let status = match self.recorder.take() {
+                    Some(recorder) => {
+                        let (path, frames) = (recorder.path.clone(), recorder.frames);
+
+                        match recorder.finish() {
+                            Ok(()) => format!("recorded {} frames to {}", frames, path.display()),
+                            Err(err) => format!("failed to record {}: {}", path.display(), err),
+                        }
+                    },
+                    None => {
+                        let out = recording_path(&self.doc.path);
+
+                        match Recorder::start(&out, self.config.record_delta) {
+                            Ok(recorder) => {
+                                self.recorder = Some(recorder);
+                                format!("recording to {}", out.display())
+                            },
+                            Err(err) => format!("failed to record: {}", err),
+                        }
+                    },
+                };
+                self.set_status(&status);
+            },
+
+            Action::CopyImage => {
+                let result = match self.clipboard.as_mut() {
+                    Some(clipboard) => clipboard.copy_bitmap(&self.doc.bitmap),
+                    None => Err(arboard::Error::ClipboardNotSupported),
+                };
+
+                if let (Ok(()), Some(watch)) = (&result, self.clipboard_watch.as_mut()) {
+                    watch.ignore(&self.doc.bitmap);
+                }
+
+                let status = match result {
+                    Ok(()) => "copied image to clipboard".to_string(),
+                    Err(err) => format!("failed to copy image: {}", err),
+                };
+                self.set_status(&status);
+            },
+            Action::PasteImage => {
+                let result = match self.clipboard.as_mut() {
+                    Some(clipboard) => clipboard.paste_bitmap(),
+                    None => Err(arboard::Error::ClipboardNotSupported),
+                };
+
+                let result = result
+                    .map_err(|err| err.to_string())
+                    .and_then(|bitmap| self.show_pasted(clipboard_path(), bitmap));
+
+                match result {
+                    Ok(()) => {
+                        let status = format!("pasted image, Ctrl+S saves it as {}", self.doc.path.display());
+                        self.set_status(&status);
+                    },
+                    Err(err) => self.set_status(&format!("failed to paste image: {}", err)),
+                }
+            },
+            Action::SaveImage if self.doc.unsaved => {
+                let status = match self.doc.bitmap.save(&self.doc.path) {
+                    Ok(()) => {
+                        self.doc.unsaved = false;
+                        format!("saved {}", self.doc.path.display())
+                    },
+                    Err(err) => format!("failed to save {}: {}", self.doc.path.display(), err),
+                };
+                self.set_status(&status);
+            },
+            Action::SaveImage => {},
+
+            Action::Shuffle => {
+                self.files.toggle_shuffle();
+
+                let status = list_status(&self.files);
+                self.set_status(&status);
+            },
+            Action::CycleSort => {
+                let sort = self.files.sort_order().map_or(SortOrder::Name, SortOrder::cycle);
+                self.files.sort_by(sort);
+
+                let status = list_status(&self.files);
+                self.set_status(&status);
+            },
+
+            Action::Reload if !self.doc.unsaved => {
+                let status = match self.reload() {
+                    Ok(()) => format!("reloaded {}{}", self.doc.path.display(), decode_notes(&self.doc.bitmap)),
+                    Err(err) => format!("failed to reload: {}", err),
+                };
+                self.set_status(&status);
+            },
+            Action::Delete if !self.doc.unsaved && !self.show_gallery => {
+                self.confirm_delete = true;
+
+                let status = format!(
+                    "move {} to trash? Enter/Y to confirm, any other key to keep it",
+                    self.doc.path.display()
+                );
+                self.set_status(&status);
+            },
+            Action::OpenWith if !self.doc.unsaved => {
+                let status = match self.config.open_with.as_deref() {
+                    Some(command) => match launch::open_with(command, &self.doc.path) {
+                        Ok(()) => format!("opened {} with {}", self.doc.path.display(), command),
+                        Err(err) => format!("failed to run {}: {}", command, err),
+                    },
+                    None => format!("no editor configured, set ${} or pass --open-with", launch::EDITOR_VAR),
+                };
+                self.set_status(&status);
+            },
+            Action::Reload | Action::Delete | Action::OpenWith => {},
+
+            Action::Slideshow => {
+                self.slideshow = match self.slideshow {
+                    Some(_) => None,
+                    None => Some(Instant::now()),
+                };
+
+                let status = if self.slideshow.is_some() {
+                    format!("slideshow every {:.1}s", self.config.slideshow_interval.as_secs_f32())
+                } else {
+                    "slideshow stopped".to_string()
+                };
+                self.set_status(&status);
+            },
+        }
+    }
+
+    fn delete_current(&mut self) {
+        if let Err(err) = trash::delete(&self.doc.path) {
+            self.set_status(&format!("failed to move to trash: {}", err));
+            return;
+        }
+
+        let removed = self.files.remove_current().unwrap();
+        self.cache.invalidate(&removed);
+
+        if self.files.is_empty() {
+            self.running = false;
+            return;
+        }
+
+        self.open_current();
+
+        let status = format!("moved {} to trash. {}", removed.display(), list_status(&self.files));
+        self.set_status(&status);
+    }
+
+    /* Whether any of the keys bound to `action` is held down */
+    fn held(&self, keyboard: &KeyboardState, action: Action) -> bool {
+        self.config.bindings
+            .keys_for(action)
+            .filter_map(|combo| Scancode::from_keycode(combo.key))
+            .any(|scancode| keyboard.is_scancode_pressed(scancode))
+    }
+
+    pub fn render(&mut self, keyboard: &KeyboardState, mouse: &MouseState) {
+        let view = self.shown_view();
+
+        self.canvas.set_draw_color(self.config.background);
+        self.canvas.clear();
+
+        if self.show_gallery {
+            if let Some(gallery) = self.gallery.as_mut() {
+                gallery.receive(self.crt);
+                gallery.draw(&mut self.canvas, self.files.iter());
+            }
+        } else {
+            // Errors were reported when the filter was picked, and large
+            // images then show unfiltered
+            let _ = self.doc.filter(self.crt, self.filter.as_ref());
+
+            if let Some(other) = self.compare.as_mut() {
+                let _ = other.filter(self.crt, self.filter.as_ref());
+            }
+
+            self.doc.build_mips(self.crt, view.zoom);
+
+            if let Some(other) = self.compare.as_mut() {
+                other.build_mips(self.crt, view.zoom);
+            }
+
+            // Files the op view cannot be built for are shown as they are
+            if self.show_ops {
+                let _ = self.doc.build_op_view(self.crt);
+
+                if let Some(other) = self.compare.as_mut() {
+                    let _ = other.build_op_view(self.crt);
+                }
+            }
+
+            let (left, right) = self.panes();
+            let panes = std::iter::once((left, &self.doc)).chain(right.zip(self.compare.as_ref()));
+
+            // Every pane draws in its own coordinates, with the same view
+            for (i, (pane, doc)) in panes.enumerate() {
+                self.canvas.set_viewport(pane);
+
+                let comparison = self.comparison.as_ref().filter(|_| i == 1);
+
+                let texture = match comparison {
+                    Some((_, texture)) => texture,
+                    None => doc.display_texture(self.show_ops, view.zoom),
+                };
+
+                // Mip levels are smaller than the image they stand in for
+                let size = match comparison {
+                    Some(_) => (texture.query().width, texture.query().height),
+                    None => doc.size(),
+                };
+                let dest = view.dest_rect(size);
+
+                if let Some((backdrop, fit)) = &self.backdrop {
+                    backdrop::draw(&mut self.canvas, backdrop, dest, view.zoom, *fit);
+                }
+
+                match self.tiling {
+                    Tiling::Off => self.canvas.copy(texture, None, dest).unwrap(),
+                    tiling => tiling::draw(&mut self.canvas, texture, dest, tiling),
+                }
+
+                if let Some(stop) = &doc.bitmap.stopped {
+                    draw_decode_stop(&mut self.canvas, &view, doc.bitmap.size(), stop);
+                }
+
+                self.ruler.draw(&mut self.canvas, &view);
+            }
+
+            self.canvas.set_viewport(None);
+
+            if minimap::needed(&view, self.doc.size(), left.size()) {
+                let zoom = minimap::scale(self.doc.size());
+                self.doc.build_mips(self.crt, zoom);
+
+                let texture = self.doc.display_texture(self.show_ops, zoom);
+                let inset = self.bottom_inset();
+                minimap::draw(&mut self.canvas, texture, &view, self.doc.size(), left.size(), inset);
+            }
+
+            if let Some(right) = right {
+                let divider = Rect::new(right.x() - DIVIDER_WIDTH as i32 / 2, 0, DIVIDER_WIDTH, right.height());
+                overlay::fill_rect(&mut self.canvas, divider, DIVIDER_COLOR);
+            }
+        }
+
+        if self.confirm_delete {
+            let (width, height) = self.canvas.output_size().unwrap();
+            overlay::fill_rect(&mut self.canvas, Rect::new(0, 0, width, height), DELETE_TINT);
+        }
+
+        if self.show_histogram && !self.show_gallery {
+            self.doc.histogram().draw(&mut self.canvas);
+        }
+
+        // A preview has fewer pixels than the loupe would magnify
+        if !self.show_gallery && !self.doc.is_preview() && self.has_mouse() && self.held(keyboard, Action::Loupe) {
+            let (left, right) = self.panes();
+            let over_right = right.filter(|right| mouse.x() >= right.x());
+
+            let doc = match (over_right, &self.compare) {
+                (Some(_), Some(other)) => other,
+                _ => &self.doc,
+            };
+
+            self.canvas.set_viewport(over_right.unwrap_or(left));
+
+            let (x, y) = self.to_pane(mouse.x(), mouse.y());
+            loupe::draw(&mut self.canvas, doc.display_texture(self.show_ops, 1.0), &view, (x, y));
+
+            self.canvas.set_viewport(None);
+        }
+
+        if self.show_status_bar {
+            ui::status_bar(&mut self.canvas, &self.status, &self.inspect);
+        }
+
+        if let (true, Some(stream)) = (self.show_hud, &self.stream) {
+            hud::draw(&mut self.canvas, stream);
+        }
+
+        if self.show_help {
+            help::draw(&mut self.canvas, &self.config.bindings);
+        }
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+
+            let status = match save_screenshot(&self.canvas, &self.doc.path) {
+                Ok(out) => format!("saved {}", out.display()),
+                Err(err) => format!("failed to save screenshot: {}", err),
+            };
+            self.set_status(&status);
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(err) = recorder.capture(&self.canvas) {
+                let status = format!("recording stopped: {}", err);
+                self.recorder = None;
+                self.set_status(&status);
+            }
+        }
+
+        let _span = tracing::info_span!("present").entered();
+        self.canvas.present();
+    }
+}
+
+/* The pixel under the cursor, if any */
+fn describe_pixel(bitmap: &Bitmap, view: &View, x: i32, y: i32) -> String {
+    match view
+        .pixel_under(x, y, bitmap.size())
+        .and_then(|(px, py)| bitmap.pixel_at(px, py).map(|p| (px, py, p)))
+    {
+        Some((px, py, p)) => format!(
+            "({}, {})  rgba({}, {}, {}, {})  {}",
+            px, py, p.r, p.g, p.b, p.a, p.to_hex()
+        ),
+        None => String::new()
+    }
+}
+
+/* The pixels under the cursor in both compared images */
+fn describe_pixels(left: &Bitmap, right: &Bitmap, view: &View, x: i32, y: i32) -> String {
+    let size = (left.width.max(right.width), left.height.max(right.height));
+
+    let (px, py) = match view.pixel_under(x, y, size) {
+        Some(pixel) => pixel,
+        None => return String::new(),
+    };
+
+    let (a, b) = (left.pixel_at(px, py), right.pixel_at(px, py));
+    let describe = |p: Option<Pixel>| p.map_or("-".to_string(), Pixel::to_hex);
+
+    format!(
+        "({}, {})  {} | {}{}",
+        px, py, describe(a), describe(b), if a == b { "" } else { "  differ" }
+    )
+}
+
+/* Path next to the source that a cropped region is saved to */
+fn crop_path(source: &Path, (x, y, w, h): (u32, u32, u32, u32), extension: &str) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image");
+
+    source.with_file_name(format!("{}_crop_{}_{}_{}x{}.{}", stem, x, y, w, h, extension))
+}
+
+/* Where a pasted image is saved unless told otherwise */
+fn clipboard_path() -> PathBuf {
+    PathBuf::from(format!("clipboard_{}.qoi", unix_timestamp()))
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/* Appended to the status of a document that did not decode cleanly */
+fn decode_notes(bitmap: &Bitmap) -> String {
+    if let Some(stop) = &bitmap.stopped {
+        let width = bitmap.width.max(1) as usize;
+
+        format!(
+            " - decoding stopped at ({}, {}), byte {}: {}",
+            stop.pixel % width, stop.pixel / width, stop.offset, stop.message
+        )
+    } else if bitmap.recovered > 0 {
+        format!(" (recovered from {} decode errors)", bitmap.recovered)
+    } else {
+        String::new()
+    }
+}
+
+/* Shades the part of the image that was never decoded and outlines the pixel where decoding stopped */
+fn draw_decode_stop(canvas: &mut WindowCanvas, view: &View, (width, height): (u32, u32), stop: &DecodeStop) {
+    let (x, y) = ((stop.pixel % width.max(1) as usize) as u32, (stop.pixel / width.max(1) as usize) as u32);
+
+    let rect_between = |(x0, y0): (u32, u32), (x1, y1): (u32, u32)| {
+        let (left, top) = view.image_to_window(x0 as f32, y0 as f32);
+        let (right, bottom) = view.image_to_window(x1 as f32, y1 as f32);
+
+        Rect::new(left, top, (right - left).max(1) as u32, (bottom - top).max(1) as u32)
+    };
+
+    // Rest of the row the failure is in, then every row below it
+    if y < height {
+        overlay::fill_rect(canvas, rect_between((x, y), (width, y + 1)), UNDECODED_TINT);
+    }
+    if y + 1 < height {
+        overlay::fill_rect(canvas, rect_between((0, y + 1), (width, height)), UNDECODED_TINT);
+    }
+
+    let pixel = rect_between((x, y), (x + 1, y + 1));
+    let marker = Rect::from_center(pixel.center(), pixel.width().max(STOP_MARKER_SIZE), pixel.height().max(STOP_MARKER_SIZE));
+    overlay::draw_rect(canvas, marker, STOP_MARKER_COLOR);
+}
+
+/* Opens the current file of the list and starts decoding its neighbours */
+fn open_current<'a, T: 'a>(crt: &'a TextureCreator<T>, files: &FileList, cache: &mut ImageCache) -> Result<Document<'a>, String> {
+    let path = files.current().unwrap();
+
+    // Large images show a preview right away, the neighbours then queue up
+    // behind the full image
+    let doc = match cache.preview(path) {
+        Some((preview, full_size)) => Document::preview(crt, path.to_path_buf(), preview, full_size),
+        None => cache.load(path).and_then(|bitmap| Document::new(crt, path.to_path_buf(), bitmap)),
+    };
+
+    for offset in [1, -1] {
+        if let Some(neighbour) = files.peek(offset) {
+            cache.prefetch(neighbour);
+        }
+    }
+
+    doc
+}
+
+fn list_status(files: &FileList) -> String {
+    let order = if files.is_shuffled() {
+        ", shuffled".to_string()
+    } else {
+        files.sort_order()
+            .map(|sort| format!(", by {}", sort.label()))
+            .unwrap_or_default()
+    };
+
+    format!(
+        "[{}/{}{}] {}",
+        files.position() + 1,
+        files.len(),
+        order,
+        files.current().map(|p| p.display().to_string()).unwrap_or_default()
+    )
+}
+
+/* Next to the source, like screenshots */
+fn recording_path(source: &Path) -> PathBuf {
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image");
+
+    source.with_file_name(format!("{}_recording_{}.qoi", stem, unix_timestamp()))
+}
+
+/* Captures exactly what has been rendered so far in the current frame */
+fn save_screenshot(canvas: &WindowCanvas, source: &Path) -> std::io::Result<PathBuf> {
+    let (width, height) = canvas.output_size().unwrap();
+
+    let bytes = canvas
+        .read_pixels(None, PixelFormatEnum::RGB24)
+        .map_err(std::io::Error::other)?;
+
+    let stem = source
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("image");
+
+    let out = source.with_file_name(format!("{}_screenshot_{}.qoi", stem, unix_timestamp()));
+
+    Bitmap::from_bytes(width, height, 3, &bytes).save(&out)?;
+
+    Ok(out)
+}
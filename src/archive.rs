@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/* Images inside .zip and .tar files are browsed like those in a directory:
+ * "pack.zip/tiles/grass.qoi" stands for the entry "tiles/grass.qoi" of
+ * "pack.zip". Entries are read out of the archive whenever they are loaded */
+const EXTENSIONS: &[&str] = &["zip", "tar"];
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)))
+}
+
+/* The archive a path points into and the name of the entry inside it */
+pub fn split(path: &Path) -> Option<(&Path, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive(ancestor) && ancestor.is_file())?;
+
+    Some((archive, entry_name(path.strip_prefix(archive).ok()?)))
+}
+
+pub fn is_entry(path: &Path) -> bool {
+    split(path).is_some()
+}
+
+/* Paths of the files in the archive, in the order they are stored */
+pub fn list(archive: &Path) -> io::Result<Vec<PathBuf>> {
+    let file = File::open(archive)?;
+
+    let names = if is_zip(archive) {
+        let zip = zip::ZipArchive::new(file)?;
+
+        zip.file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    } else {
+        let mut names = Vec::new();
+
+        for entry in tar::Archive::new(file).entries()? {
+            let entry = entry?;
+
+            if entry.header().entry_type().is_file() {
+                names.push(entry_name(&entry.path()?));
+            }
+        }
+
+        names
+    };
+
+    Ok(names.into_iter().map(|name| archive.join(name)).collect())
+}
+
+/* Like `std::fs::read` for a path inside an archive */
+pub fn read(archive: &Path, entry: &str) -> io::Result<Vec<u8>> {
+    let file = File::open(archive)?;
+    let mut data = Vec::new();
+
+    if is_zip(archive) {
+        zip::ZipArchive::new(file)?.by_name(entry)?.read_to_end(&mut data)?;
+        return Ok(data);
+    }
+
+    for found in tar::Archive::new(file).entries()? {
+        let mut found = found?;
+
+        if entry_name(&found.path()?) == entry {
+            found.read_to_end(&mut data)?;
+            return Ok(data);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("No entry \"{}\" in \"{}\"", entry, archive.display())
+    ))
+}
+
+/* Separated by "/" on every platform, without any "./" that tar may store */
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
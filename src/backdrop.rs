@@ -0,0 +1,47 @@
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+
+// Tiles are never drawn smaller than this, so that far zoomed out views do
+// not take thousands of copies
+const MIN_TILE_SIZE: u32 = 8;
+
+/* How a backdrop image covers the area of the image shown over it */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BackdropFit {
+    Tile,    // Repeated at the zoom of the image, so pixels of both match in size
+    Stretch, // Scaled to the image
+}
+
+/* Draws the backdrop where the image is about to be drawn at `dest`, with
+ * tiles starting at its top left corner */
+pub fn draw(canvas: &mut WindowCanvas, texture: &Texture, dest: Rect, zoom: f32, fit: BackdropFit) {
+    if fit == BackdropFit::Stretch {
+        canvas.copy(texture, None, dest).unwrap();
+        return;
+    }
+
+    let viewport = canvas.viewport();
+    let visible = match dest.intersection(Rect::new(0, 0, viewport.width(), viewport.height())) {
+        Some(visible) => visible,
+        None => return,
+    };
+
+    let query = texture.query();
+    let w = ((query.width as f32 * zoom).round() as u32).max(MIN_TILE_SIZE);
+    let h = ((query.height as f32 * zoom).round() as u32).max(MIN_TILE_SIZE);
+
+    // The first tile that reaches into view, then the rest up to its end
+    let first_x = dest.x() + (visible.x() - dest.x()) / w as i32 * w as i32;
+    let first_y = dest.y() + (visible.y() - dest.y()) / h as i32 * h as i32;
+
+    let prev_clip = canvas.clip_rect();
+    canvas.set_clip_rect(visible);
+
+    for y in (first_y..visible.bottom()).step_by(h as usize) {
+        for x in (first_x..visible.right()).step_by(w as usize) {
+            canvas.copy(texture, None, Rect::new(x, y, w, h)).unwrap();
+        }
+    }
+
+    canvas.set_clip_rect(prev_clip);
+}
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::decoder::{DecodeChunks, ImageDecoder, Pixel, SliceSink};
+use crate::input;
+
+pub const DEFAULT_ITERATIONS: u32 = 10;
+pub const DEFAULT_WARMUP: u32 = 2;
+
+/* Where the decoder reads the file from */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Source {
+    Read,  // A buffered reader over the file, opened once and rewound for every decode
+    Slice, // The whole file read into memory once
+    Mmap,  // The file mapped into memory
+}
+
+impl Source {
+    pub const ALL: [Source; 3] = [Source::Read, Source::Slice, Source::Mmap];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Source::Read => "read",
+            Source::Slice => "slice",
+            Source::Mmap => "mmap",
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Source::ALL
+            .iter()
+            .copied()
+            .find(|source| source.label() == s)
+            .ok_or_else(|| format!("Unknown decode source: {}", s))
+    }
+}
+
+/* Decodes the image the reader holds into `pixels`, which is only
+ * allocated the first time so that allocating is not part of the timing.
+ * Returns the pixel count */
+fn decode<R: Read>(source: R, pixels: &mut Vec<Pixel>) -> Result<usize, String> {
+    let dec = ImageDecoder::new(source).map_err(|err| err.to_string())?;
+
+    decode_chunks(&mut dec.chunks_iter(), pixels)
+}
+
+fn decode_chunks<R: Read>(chunks: &mut DecodeChunks<R>, pixels: &mut Vec<Pixel>) -> Result<usize, String> {
+    let count = chunks.header().width as usize * chunks.header().height as usize;
+    pixels.resize(count, Pixel::zero());
+
+    chunks.decode_into(&mut SliceSink::new(pixels), count as u64)?;
+
+    Ok(count)
+}
+
+/* Timings of one source over all iterations */
+struct Samples {
+    times: Vec<Duration>, // Sorted, fastest first
+    pixels: usize,
+}
+
+impl Samples {
+    /* Nearest rank percentile */
+    fn percentile(&self, p: f64) -> Duration {
+        let rank = ((p / 100.0) * self.times.len() as f64).ceil() as usize;
+        self.times[rank.clamp(1, self.times.len()) - 1]
+    }
+}
+
+fn run(path: &Path, source: Source, warmup: u32, iterations: u32) -> Result<Samples, String> {
+    // Standard input is read once up front, there is no file to reopen or map
+    if input::is_stdin(path) && source != Source::Slice {
+        return Err(format!("Standard input can only be benchmarked as a {}", Source::Slice.label()));
+    }
+
+    let data = input::read(path).map_err(|err| err.to_string())?;
+
+    let mmap = match source {
+        Source::Mmap => {
+            let file = File::open(path).map_err(|err| err.to_string())?;
+
+            // Safety: the mapping is only read, and only while benchmarking
+            Some(unsafe { memmap2::Mmap::map(&file) }.map_err(|err| err.to_string())?)
+        },
+        _ => None,
+    };
+
+    let mut reader = match source {
+        Source::Read => {
+            let file = File::open(path).map_err(|err| err.to_string())?;
+            Some(ImageDecoder::new(BufReader::new(file)).map_err(|err| err.to_string())?.chunks_iter())
+        },
+        _ => None,
+    };
+
+    let mut buffer = Vec::new();
+
+    let mut decode_once = || match (source, &mmap, &mut reader) {
+        (Source::Read, _, Some(chunks)) => {
+            chunks.rewind().map_err(|err| err.to_string())?;
+            decode_chunks(chunks, &mut buffer)
+        },
+        (Source::Mmap, Some(mmap), _) => decode(&mmap[..], &mut buffer),
+        _ => decode(&data[..], &mut buffer),
+    };
+
+    for _ in 0..warmup {
+        decode_once()?;
+    }
+
+    let mut times = Vec::with_capacity(iterations as usize);
+    let mut pixels = 0;
+
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        pixels = decode_once()?;
+        times.push(start.elapsed());
+    }
+
+    times.sort();
+
+    Ok(Samples { times, pixels })
+}
+
+/* Decodes the file `warmup` times untimed, then `iterations` times timed, for
+ * every source. Throughput is based on the median */
+pub fn bench(path: &Path, sources: &[Source], warmup: u32, iterations: u32) -> Result<String, String> {
+    let size = input::size(path)
+        .map_err(|err| format!("Failed to read \"{}\": {}", path.display(), err))?;
+
+    let mut lines = vec![format!(
+        "{}: {} bytes, {} warmup, {} timed iterations\n  {:<6} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        path.display(), size, warmup, iterations.max(1),
+        "source", "min ms", "median ms", "p95 ms", "MB/s", "MP/s"
+    )];
+
+    for &source in sources {
+        let samples = run(path, source, warmup, iterations)
+            .map_err(|err| format!("Failed to decode \"{}\": {}", path.display(), err))?;
+
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let median = samples.percentile(50.0).as_secs_f64();
+
+        lines.push(format!(
+            "  {:<6} {:>10.3} {:>10.3} {:>10.3} {:>10.1} {:>10.1}",
+            source.label(),
+            ms(samples.times[0]),
+            median * 1000.0,
+            ms(samples.percentile(95.0)),
+            size as f64 / 1e6 / median,
+            samples.pixels as f64 / 1e6 / median,
+        ));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+pub fn bench_all<'p>(paths: impl Iterator<Item = &'p Path>, sources: &[Source], warmup: u32, iterations: u32) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        match bench(path, sources, warmup, iterations) {
+            Ok(report) => println!("{}", report),
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
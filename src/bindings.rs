@@ -0,0 +1,313 @@
+use std::fmt;
+
+use sdl2::keyboard::{Keycode, Mod};
+
+/* Everything the viewer can be told to do from the keyboard */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Quit,
+    NextImage,
+    PrevImage,
+    SelectUp,   // Moves the gallery selection a row up
+    SelectDown, // Moves the gallery selection a row down
+    Confirm,    // Opens the gallery selection, saves a crop, confirms a delete
+    ConfirmAlt, // Saves a crop as PNG
+    ToggleHistogram,
+    ToggleGallery,
+    PickerTool,
+    MeasureTool,
+    CropTool,
+    Loupe, // Active while held
+    Screenshot,
+    CopyImage,
+    PasteImage,
+    SaveImage,
+    Shuffle,
+    CycleSort,
+    Reload,
+    Delete,
+    OpenWith,
+    Slideshow,
+    ToggleOpView, // Colors pixels by the chunk op that produced them
+    ZoomIn,
+    ZoomOut,
+    ResetZoom, // Back to the scaling mode
+    ToggleDiff, // Heatmap of the differences, when comparing
+    ToggleOnionSkin, // The compared image faded over the first
+    CycleTiling, // Repeats the image, then also outlines the seams
+    ToggleStatusBar,
+    Help, // Lists the keys of every action
+    SetWallpaper,
+    NextMonitor, // Moves the window on to the next display
+    CycleFilter, // Blurs or sharpens the image on display
+    PlayPause,    // The rest only apply while playing a stream
+    StepForward,
+    StepBack,
+    SpeedUp,
+    SpeedDown,
+    ToggleLoop,
+    ToggleHud, // Playback statistics
+    ToggleRecording,
+}
+
+impl Action {
+    pub const ALL: [Action; 43] = [
+        Action::Quit,
+        Action::NextImage,
+        Action::PrevImage,
+        Action::SelectUp,
+        Action::SelectDown,
+        Action::Confirm,
+        Action::ConfirmAlt,
+        Action::ToggleHistogram,
+        Action::ToggleGallery,
+        Action::PickerTool,
+        Action::MeasureTool,
+        Action::CropTool,
+        Action::Loupe,
+        Action::Screenshot,
+        Action::CopyImage,
+        Action::PasteImage,
+        Action::SaveImage,
+        Action::Shuffle,
+        Action::CycleSort,
+        Action::Reload,
+        Action::Delete,
+        Action::OpenWith,
+        Action::Slideshow,
+        Action::ToggleOpView,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ResetZoom,
+        Action::ToggleDiff,
+        Action::ToggleOnionSkin,
+        Action::CycleTiling,
+        Action::ToggleStatusBar,
+        Action::Help,
+        Action::SetWallpaper,
+        Action::NextMonitor,
+        Action::CycleFilter,
+        Action::PlayPause,
+        Action::StepForward,
+        Action::StepBack,
+        Action::SpeedUp,
+        Action::SpeedDown,
+        Action::ToggleLoop,
+        Action::ToggleHud,
+        Action::ToggleRecording,
+    ];
+
+    /* Name used in the [keys] table of the config file */
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::NextImage => "next_image",
+            Action::PrevImage => "prev_image",
+            Action::SelectUp => "select_up",
+            Action::SelectDown => "select_down",
+            Action::Confirm => "confirm",
+            Action::ConfirmAlt => "confirm_alt",
+            Action::ToggleHistogram => "toggle_histogram",
+            Action::ToggleGallery => "toggle_gallery",
+            Action::PickerTool => "picker_tool",
+            Action::MeasureTool => "measure_tool",
+            Action::CropTool => "crop_tool",
+            Action::Loupe => "loupe",
+            Action::Screenshot => "screenshot",
+            Action::CopyImage => "copy_image",
+            Action::PasteImage => "paste_image",
+            Action::SaveImage => "save_image",
+            Action::Shuffle => "shuffle",
+            Action::CycleSort => "cycle_sort",
+            Action::Reload => "reload",
+            Action::Delete => "delete",
+            Action::OpenWith => "open_with",
+            Action::Slideshow => "slideshow",
+            Action::ToggleOpView => "toggle_op_view",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::ResetZoom => "reset_zoom",
+            Action::ToggleDiff => "toggle_diff",
+            Action::ToggleOnionSkin => "toggle_onion_skin",
+            Action::CycleTiling => "cycle_tiling",
+            Action::ToggleStatusBar => "toggle_status_bar",
+            Action::Help => "help",
+            Action::SetWallpaper => "set_wallpaper",
+            Action::NextMonitor => "next_monitor",
+            Action::CycleFilter => "cycle_filter",
+            Action::PlayPause => "play_pause",
+            Action::StepForward => "step_forward",
+            Action::StepBack => "step_back",
+            Action::SpeedUp => "speed_up",
+            Action::SpeedDown => "speed_down",
+            Action::ToggleLoop => "toggle_loop",
+            Action::ToggleHud => "toggle_hud",
+            Action::ToggleRecording => "toggle_recording",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/* A key together with the modifiers that have to be held for it */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct KeyCombo {
+    pub key: Keycode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl KeyCombo {
+    pub fn from_event(key: Keycode, keymod: Mod) -> Self {
+        Self {
+            key,
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        }
+    }
+
+    /* Parses names like "Q", "F12" or "Ctrl+Shift+S" */
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key_name = parts.pop().filter(|k| !k.is_empty())
+            .ok_or_else(|| format!("Missing key in \"{}\"", s))?;
+
+        let key = Keycode::from_name(key_name)
+            .ok_or_else(|| format!("Unknown key \"{}\" in \"{}\"", key_name, s))?;
+
+        let mut combo = Self { key, ctrl: false, shift: false, alt: false };
+
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => combo.ctrl = true,
+                "shift" => combo.shift = true,
+                "alt" => combo.alt = true,
+                _ => return Err(format!("Unknown modifier \"{}\" in \"{}\"", modifier, s)),
+            }
+        }
+
+        Ok(combo)
+    }
+
+    fn plain(key: Keycode) -> Self {
+        Self { key, ctrl: false, shift: false, alt: false }
+    }
+
+    fn ctrl(key: Keycode) -> Self {
+        Self { ctrl: true, ..Self::plain(key) }
+    }
+
+    fn shift(key: Keycode) -> Self {
+        Self { shift: true, ..Self::plain(key) }
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    /* In the form `parse` accepts */
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+
+        write!(f, "{}", self.key.name())
+    }
+}
+
+/* Maps keys to actions. Every key handled by the viewer goes through here */
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    table: Vec<(KeyCombo, Action)>,
+}
+
+impl Bindings {
+    pub fn lookup(&self, combo: KeyCombo) -> Option<Action> {
+        self.table
+            .iter()
+            .find(|(c, _)| *c == combo)
+            .map(|&(_, action)| action)
+    }
+
+    pub fn keys_for(&self, action: Action) -> impl Iterator<Item = KeyCombo> + '_ {
+        self.table
+            .iter()
+            .filter(move |(_, a)| *a == action)
+            .map(|&(combo, _)| combo)
+    }
+
+    /* Replaces all keys of `action` */
+    pub fn rebind(&mut self, action: Action, combos: Vec<KeyCombo>) {
+        self.table.retain(|&(c, a)| a != action && !combos.contains(&c));
+        self.table.extend(combos.into_iter().map(|c| (c, action)));
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+
+        let table = vec![
+            (KeyCombo::plain(Keycode::Escape), Quit),
+            (KeyCombo::plain(Keycode::Q), Quit),
+            (KeyCombo::plain(Keycode::Right), NextImage),
+            (KeyCombo::plain(Keycode::Space), NextImage),
+            (KeyCombo::plain(Keycode::Left), PrevImage),
+            (KeyCombo::plain(Keycode::Backspace), PrevImage),
+            (KeyCombo::plain(Keycode::Up), SelectUp),
+            (KeyCombo::plain(Keycode::Down), SelectDown),
+            (KeyCombo::plain(Keycode::Return), Confirm),
+            (KeyCombo::plain(Keycode::Y), Confirm),
+            (KeyCombo::shift(Keycode::Return), ConfirmAlt),
+            (KeyCombo::plain(Keycode::H), ToggleHistogram),
+            (KeyCombo::plain(Keycode::T), ToggleGallery),
+            (KeyCombo::plain(Keycode::P), PickerTool),
+            (KeyCombo::plain(Keycode::M), MeasureTool),
+            (KeyCombo::plain(Keycode::C), CropTool),
+            (KeyCombo::plain(Keycode::L), Loupe),
+            (KeyCombo::plain(Keycode::F12), Screenshot),
+            (KeyCombo::ctrl(Keycode::C), CopyImage),
+            (KeyCombo::ctrl(Keycode::V), PasteImage),
+            (KeyCombo::ctrl(Keycode::S), SaveImage),
+            (KeyCombo::plain(Keycode::S), Shuffle),
+            (KeyCombo::plain(Keycode::O), CycleSort),
+            (KeyCombo::plain(Keycode::R), Reload),
+            (KeyCombo::plain(Keycode::Delete), Delete),
+            (KeyCombo::plain(Keycode::E), OpenWith),
+            (KeyCombo::plain(Keycode::F5), Slideshow),
+            (KeyCombo::plain(Keycode::D), ToggleOpView),
+            (KeyCombo::plain(Keycode::Equals), ZoomIn),
+            (KeyCombo::plain(Keycode::KpPlus), ZoomIn),
+            (KeyCombo::plain(Keycode::Minus), ZoomOut),
+            (KeyCombo::plain(Keycode::KpMinus), ZoomOut),
+            (KeyCombo::plain(Keycode::Num0), ResetZoom),
+            (KeyCombo::plain(Keycode::X), ToggleDiff),
+            (KeyCombo::shift(Keycode::X), ToggleOnionSkin),
+            (KeyCombo::plain(Keycode::W), CycleTiling),
+            (KeyCombo::plain(Keycode::B), ToggleStatusBar),
+            (KeyCombo::shift(Keycode::Slash), Help),
+            (KeyCombo::plain(Keycode::F1), Help),
+            (KeyCombo::ctrl(Keycode::B), SetWallpaper),
+            (KeyCombo::shift(Keycode::M), NextMonitor),
+            (KeyCombo::plain(Keycode::F), CycleFilter),
+            (KeyCombo::plain(Keycode::K), PlayPause),
+            (KeyCombo::plain(Keycode::Period), StepForward),
+            (KeyCombo::plain(Keycode::Comma), StepBack),
+            (KeyCombo::plain(Keycode::RightBracket), SpeedUp),
+            (KeyCombo::plain(Keycode::LeftBracket), SpeedDown),
+            (KeyCombo::plain(Keycode::Backslash), ToggleLoop),
+            (KeyCombo::plain(Keycode::F3), ToggleHud),
+            (KeyCombo::plain(Keycode::F9), ToggleRecording),
+        ];
+
+        Self { table }
+    }
+}
@@ -0,0 +1,575 @@
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+
+use image::DynamicImage;
+
+use crate::decoder::{EvaluatedChunk, ImageDecoder, Limits, Pixel, PixelLayout, PixelSink, QOIHeader};
+use crate::encoder;
+use crate::archive;
+use crate::input;
+use crate::metadata::Metadata;
+#[cfg(feature = "qoi16")]
+use crate::qoi16;
+use crate::resample::Resampler;
+
+/* A fully decoded image, kept around so that overlays and tools can
+ * inspect the pixels after the texture has been uploaded */
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub colorspace: u8,
+    pub pixels: Vec<Pixel>,
+
+    // Number of decode errors papered over by a lenient load
+    pub recovered: usize,
+
+    // Set when strict decoding failed part way through the file
+    pub stopped: Option<DecodeStop>,
+}
+
+/* Where and why decoding of a file gave up */
+#[derive(Debug, Clone)]
+pub struct DecodeStop {
+    pub pixel: usize, // Index of the first pixel that could not be decoded
+    pub offset: u64,  // Byte offset of the chunk that failed
+    pub message: String,
+}
+
+/* How files are decoded */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadOptions {
+    pub lenient: bool,   // Keep going past corrupt chunks
+    pub untrusted: bool, // Only QOI files, decoded with a pixel budget
+    pub limits: Limits,
+}
+
+/* Stands in for pixels that could not be decoded in lenient mode */
+const ERROR_PIXEL: Pixel = Pixel { r: 255, g: 0, b: 255, a: 255 };
+
+/* Images of at least this many pixels are first shown as a preview */
+const PREVIEW_PIXELS: u64 = 16 << 20;
+
+// Longest side of such a preview
+const PREVIEW_SIDE: u32 = 1024;
+
+/* Keeps every `step`th pixel of every `step`th row */
+struct Subsample {
+    width: u32,
+    step: u32,
+    position: (u32, u32), // Of the next pixel
+    pixels: Vec<Pixel>,
+}
+
+impl PixelSink for Subsample {
+    fn put_pixel(&mut self, px: Pixel) {
+        let (x, y) = self.position;
+
+        if x % self.step == 0 && y % self.step == 0 {
+            self.pixels.push(px);
+        }
+
+        self.position = match x + 1 == self.width {
+            true => (0, y + 1),
+            false => (x + 1, y),
+        };
+    }
+}
+
+impl Bitmap {
+    /* QOI files go through our own decoder, anything else through `image` */
+    pub fn load(path: &str, options: &LoadOptions) -> Result<Self, String> {
+        let is_qoi = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"));
+
+        #[cfg(feature = "qoi16")]
+        if qoi16::is_qoi16(Path::new(path)) && !input::is_stdin(Path::new(path)) && !archive::is_entry(Path::new(path)) {
+            let data = std::fs::read(path)
+                .map_err(|err| format!("Failed to open file: \"{}\": {}", path, err))?;
+
+            return qoi16::to_bitmap(path, &data, options);
+        }
+
+        if input::is_stdin(Path::new(path)) || archive::is_entry(Path::new(path)) {
+            Self::load_in_memory(path, options)
+        } else if is_qoi {
+            Self::load_qoi(path, options)
+        } else if options.untrusted {
+            Err(format!("Refusing to decode \"{}\": only QOI files are opened in untrusted mode", path))
+        } else {
+            Self::load_other(path, &options.limits)
+        }
+    }
+
+    /* Every Nth pixel of every Nth row of a large QOI file on disk, decoded
+     * without holding on to the rest, to show while the full image decodes.
+     * None for smaller images, other formats, or files that fail to decode.
+     * Returns the size of the full image as well */
+    pub fn load_preview(path: &Path, options: &LoadOptions) -> Option<(Self, (u32, u32))> {
+        let is_qoi = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"));
+
+        if !is_qoi || input::is_stdin(path) || archive::is_entry(path) {
+            return None;
+        }
+
+        let file = File::open(path).ok()?;
+        let dec = ImageDecoder::with_limits(BufReader::new(file), options.limits).ok()?;
+
+        let &QOIHeader { width, height, channels, colorspace } = dec.header();
+
+        if (width as u64 * height as u64) < PREVIEW_PIXELS {
+            return None;
+        }
+
+        let _span = tracing::info_span!("load_preview", ?path).entered();
+
+        let step = width.max(height).div_ceil(PREVIEW_SIDE);
+
+        let mut sink = Subsample {
+            width,
+            step,
+            position: (0, 0),
+            pixels: Vec::with_capacity((width.div_ceil(step) * height.div_ceil(step)) as usize),
+        };
+
+        dec.chunks_iter_bounded().decode_into(&mut sink, width as u64 * height as u64).ok()?;
+
+        let preview = Self {
+            width: width.div_ceil(step),
+            height: height.div_ceil(step),
+            channels,
+            colorspace,
+            pixels: sink.pixels,
+            recovered: 0,
+            stopped: None,
+        };
+
+        Some((preview, (width, height)))
+    }
+
+    fn load_other(path: &str, limits: &Limits) -> Result<Self, String> {
+        let _span = tracing::info_span!("load_other", path).entered();
+
+        let (width, height) = image::image_dimensions(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path, err))?;
+
+        limits
+            .check(width, height)
+            .map_err(|err| format!("Failed to decode \"{}\": {}", path, err))?;
+
+        let img = image::open(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path, err))?;
+
+        Ok(Self::from_image(&img))
+    }
+
+    /* Whatever was piped in or sits in an archive, told apart by its magic
+     * since piped data has no extension */
+    fn load_in_memory(path: &str, options: &LoadOptions) -> Result<Self, String> {
+        let name = match input::is_stdin(Path::new(path)) {
+            true => "standard input".to_string(),
+            false => format!("\"{}\"", path),
+        };
+
+        let data = input::read(Path::new(path))
+            .map_err(|err| format!("Failed to read {}: {}", name, err))?;
+
+        if data.starts_with(b"qoif") {
+            return Self::decode_qoi(path, &data, options);
+        }
+
+        #[cfg(feature = "qoi16")]
+        if data.starts_with(&qoi16::MAGIC) {
+            return qoi16::to_bitmap(path, &data, options);
+        }
+
+        if options.untrusted {
+            return Err(format!("Refusing to decode {}: only QOI files are opened in untrusted mode", name));
+        }
+
+        let reader = || image::io::Reader::new(Cursor::new(&data[..])).with_guessed_format();
+
+        let (width, height) = reader()
+            .map_err(|err| err.to_string())
+            .and_then(|r| r.into_dimensions().map_err(|err| err.to_string()))
+            .map_err(|err| format!("Failed to decode {}: {}", name, err))?;
+
+        options.limits
+            .check(width, height)
+            .map_err(|err| format!("Failed to decode {}: {}", name, err))?;
+
+        let img = reader()
+            .map_err(|err| err.to_string())
+            .and_then(|r| r.decode().map_err(|err| err.to_string()))
+            .map_err(|err| format!("Failed to decode {}: {}", name, err))?;
+
+        Ok(Self::from_image(&img))
+    }
+
+    fn from_image(img: &DynamicImage) -> Self {
+        let (width, height) = (img.width(), img.height());
+
+        if img.color().has_alpha() {
+            Self::from_bytes(width, height, 4, img.to_rgba8().as_raw())
+        } else {
+            Self::from_bytes(width, height, 3, img.to_rgb8().as_raw())
+        }
+    }
+
+    fn load_qoi(path: &str, options: &LoadOptions) -> Result<Self, String> {
+        let data = std::fs::read(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path, err))?;
+
+        Self::decode_qoi(path, &data, options)
+    }
+
+    /* Decodes until the pixel count of the header is reached. When strict
+     * decoding fails part way, the pixels decoded so far are kept and the rest
+     * left transparent, with `stopped` telling where it happened. A lenient
+     * load instead substitutes ERROR_PIXEL for every chunk that fails to
+     * decode and for whatever is missing at the end */
+    fn decode_qoi(path: &str, data: &[u8], options: &LoadOptions) -> Result<Self, String> {
+        let _span = tracing::info_span!("load_qoi", path).entered();
+
+        let lenient = options.lenient;
+
+        let dec = ImageDecoder::with_limits(data, options.limits)
+            .map_err(|err| format!("Failed to decode \"{}\": {}", path, err))?;
+
+        let &QOIHeader { width, height, channels, colorspace } = dec.header();
+        let count = width as usize * height as usize;
+
+        let mut pixels = Vec::with_capacity(count);
+        let mut errors = Vec::new();
+        let mut stopped = None;
+        let decode_span = tracing::info_span!("decode_chunks", count).entered();
+
+        let mut chunks = if options.untrusted {
+            dec.chunks_iter_bounded()
+        } else {
+            dec.chunks_iter()
+        };
+
+        while pixels.len() < count {
+            match chunks.next_chunk() {
+                EvaluatedChunk::Ok(p) => {
+                    if chunks.starts_chunk() {
+                        if let Some(chunk) = chunks.last_chunk() {
+                            tracing::debug!("Pixel {}: {:?} ending at byte {}", pixels.len(), chunk, chunks.position());
+                        }
+                    }
+
+                    pixels.push(p);
+                },
+                EvaluatedChunk::EndMarker => break,
+                EvaluatedChunk::Faulty(err) if lenient && !chunks.is_exhausted() => {
+                    errors.push(err);
+                    pixels.push(ERROR_PIXEL);
+                    chunks.skip_bytes(1);
+                },
+                EvaluatedChunk::Faulty(err) => {
+                    stopped = Some(DecodeStop { pixel: pixels.len(), offset: chunks.position(), message: err });
+                    break;
+                },
+            }
+        }
+
+        decode_span.exit();
+
+        if pixels.len() < count && stopped.is_none() {
+            stopped = Some(DecodeStop {
+                pixel: pixels.len(),
+                offset: chunks.position(),
+                message: format!("End marker after {} of {} pixels", pixels.len(), count),
+            });
+        } else if pixels.len() == count {
+            // Everything was decoded, so only the end marker should be left
+            match chunks.next_chunk() {
+                EvaluatedChunk::EndMarker => {
+                    let end = chunks.position();
+                    let trailing = chunks.into_source().len();
+
+                    if trailing > 0 && Metadata::trailing(data, trailing).is_some() {
+                        tracing::debug!("\"{}\": {} bytes of metadata after the end marker at byte {}", path, trailing, end);
+                    } else if trailing > 0 {
+                        tracing::warn!("\"{}\": {} bytes of trailing data after the end marker at byte {}", path, trailing, end);
+                    }
+                },
+                _ => tracing::warn!("\"{}\": no end marker after the last pixel", path),
+            }
+        }
+
+        if lenient {
+            errors.extend(stopped.take().map(|stop| stop.message));
+            pixels.resize(count, ERROR_PIXEL);
+
+            if let Some(first) = errors.first() {
+                tracing::warn!("\"{}\": recovered from {} decode errors, the first: {}", path, errors.len(), first);
+            }
+        } else {
+            pixels.resize(count, Pixel::zero());
+
+            if let Some(stop) = &stopped {
+                tracing::warn!("\"{}\": {}, showing the pixels before it", path, stop.message);
+            }
+        }
+
+        Ok(Self { width, height, channels, colorspace, pixels, recovered: errors.len(), stopped })
+    }
+
+    /* Builds a bitmap from tightly packed RGB24 or RGBA32 bytes */
+    pub fn from_bytes(width: u32, height: u32, channels: u8, bytes: &[u8]) -> Self {
+        let pixels = bytes
+            .chunks_exact(channels as usize)
+            .map(|c| Pixel::new(c[0], c[1], c[2], if channels == 4 { c[3] } else { 255 }))
+            .collect();
+
+        Self { width, height, channels, colorspace: 0, pixels, recovered: 0, stopped: None }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn pixel_at(&self, x: u32, y: u32) -> Option<Pixel> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.pixels
+            .get((y as usize) * (self.width as usize) + (x as usize))
+            .copied()
+    }
+
+    /* Lanczos filtered copy that fits inside a `max_side` square, sharper
+     * than the renderer scaling down the full size texture */
+    pub fn thumbnail(&self, max_side: u32) -> Bitmap {
+        let (width, height) = self.fitted_size(max_side, max_side);
+        Resampler::Lanczos3.resize(self, width, height)
+    }
+
+    /* Largest size no larger than `max_width` by `max_height` with the same
+     * aspect ratio. Images that already fit are never enlarged */
+    fn fitted_size(&self, max_width: u32, max_height: u32) -> (u32, u32) {
+        let scale = (max_width as f32 / self.width.max(1) as f32)
+            .min(max_height as f32 / self.height.max(1) as f32)
+            .min(1.0);
+
+        (((self.width as f32 * scale).round() as u32).max(1), ((self.height as f32 * scale).round() as u32).max(1))
+    }
+
+    /* Box filtered copy that fits within `max_width` by `max_height`. Images
+     * with a zero side have nothing to scale and are copied as they are */
+    pub fn fit_within(&self, max_width: u32, max_height: u32) -> Bitmap {
+        if self.pixels.is_empty() {
+            return self.crop(0, 0, self.width, self.height);
+        }
+
+        let (width, height) = self.fitted_size(max_width, max_height);
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+
+        for ty in 0..height {
+            let y0 = (ty as u64 * self.height as u64 / height as u64) as u32;
+            let y1 = (((ty + 1) as u64 * self.height as u64 / height as u64) as u32).max(y0 + 1);
+
+            for tx in 0..width {
+                let x0 = (tx as u64 * self.width as u64 / width as u64) as u32;
+                let x1 = (((tx + 1) as u64 * self.width as u64 / width as u64) as u32).max(x0 + 1);
+
+                let mut sum = [0_u64; 4];
+                for y in y0..y1 {
+                    let row = (y as usize) * (self.width as usize);
+                    for p in &self.pixels[row + x0 as usize..row + x1 as usize] {
+                        sum[0] += p.r as u64;
+                        sum[1] += p.g as u64;
+                        sum[2] += p.b as u64;
+                        sum[3] += p.a as u64;
+                    }
+                }
+
+                let n = ((x1 - x0) * (y1 - y0)) as u64;
+                pixels.push(Pixel::new(
+                    (sum[0] / n) as u8,
+                    (sum[1] / n) as u8,
+                    (sum[2] / n) as u8,
+                    (sum[3] / n) as u8,
+                ));
+            }
+        }
+
+        Bitmap { width, height, pixels, stopped: None, ..*self }
+    }
+
+    /* Whether the image has an alpha channel, RGBA or gray with alpha */
+    pub fn has_alpha(&self) -> bool {
+        matches!(self.channels, 2 | 4)
+    }
+
+    /* Raw bytes in the layout of the source image (RGB24 or RGBA32). Gray
+     * images are expanded to RGB or RGBA */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let _span = tracing::info_span!("convert_channels", channels = self.channels).entered();
+
+        let alpha = self.has_alpha();
+
+        self.pixels
+            .iter()
+            .flat_map(|&p| if alpha {
+                p.to_channels4_iter()
+            } else {
+                p.to_channels3_iter()
+            })
+            .collect()
+    }
+
+    /* A gray copy, one or two channels depending on alpha, by the luma
+     * weights of BT.601 */
+    pub fn to_gray(&self) -> Bitmap {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|p| {
+                let luma = (299 * p.r as u32 + 587 * p.g as u32 + 114 * p.b as u32 + 500) / 1000;
+                Pixel::new(luma as u8, luma as u8, luma as u8, p.a)
+            })
+            .collect();
+
+        let channels = if self.has_alpha() { 2 } else { 1 };
+
+        Bitmap { pixels, channels, recovered: 0, stopped: None, ..*self }
+    }
+
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&p| p.to_channels3_iter())
+            .collect()
+    }
+
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&p| p.to_channels4_iter())
+            .collect()
+    }
+
+    /* Four bytes per pixel in the given order */
+    pub fn to_layout_bytes(&self, layout: PixelLayout) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&p| p.to_layout(layout))
+            .collect()
+    }
+
+    /* Writes the bytes of `to_bytes()` into a buffer whose rows start
+     * `stride` bytes apart, e.g. a locked texture, leaving the padding at
+     * the end of each row alone */
+    pub fn write_into(&self, buf: &mut [u8], stride: usize) -> Result<(), String> {
+        let (pitch, channels) = (self.pitch(), if self.has_alpha() { 4 } else { 3 });
+        let needed = stride * (self.height as usize).saturating_sub(1) + pitch;
+
+        if stride < pitch {
+            return Err(format!("Rows of {} bytes are too short for {} bytes of pixels", stride, pitch));
+        }
+
+        if buf.len() < needed {
+            return Err(format!("A {}x{} image needs {} bytes, got {}", self.width, self.height, needed, buf.len()));
+        }
+
+        let _span = tracing::info_span!("convert_channels", channels = self.channels).entered();
+
+        for (row, pixels) in buf.chunks_mut(stride).zip(self.pixels.chunks(self.width.max(1) as usize)) {
+            for (out, p) in row[..pitch].chunks_exact_mut(channels).zip(pixels) {
+                out.copy_from_slice(&[p.r, p.g, p.b, p.a][..channels]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /* Number of bytes in one row of `to_bytes()` */
+    pub fn pitch(&self) -> usize {
+        (self.width as usize) * if self.has_alpha() { 4 } else { 3 }
+    }
+
+    /* Copies out the given rectangle, which must lie inside the image */
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Bitmap {
+        let stride = self.width as usize;
+
+        let pixels = (y..y + height)
+            .flat_map(|row| {
+                let start = (row as usize) * stride + (x as usize);
+                self.pixels[start..start + (width as usize)].iter().copied()
+            })
+            .collect();
+
+        Bitmap { width, height, pixels, stopped: None, ..*self }
+    }
+
+    pub fn header(&self) -> QOIHeader {
+        QOIHeader {
+            width: self.width,
+            height: self.height,
+            channels: self.channels,
+            colorspace: self.colorspace,
+        }
+    }
+
+    /* Saves as PNG if the path says so, otherwise as QOI */
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let is_png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+
+        if is_png {
+            let (bytes, color) = match self.channels {
+                1 => (self.pixels.iter().map(|p| p.r).collect(), image::ColorType::L8),
+                2 => (self.pixels.iter().flat_map(|p| [p.r, p.a]).collect(), image::ColorType::La8),
+                3 => (self.to_bytes(), image::ColorType::Rgb8),
+                _ => (self.to_bytes(), image::ColorType::Rgba8),
+            };
+
+            return image::save_buffer(path, &bytes, self.width, self.height, color)
+                .map_err(std::io::Error::other);
+        }
+
+        let to_io = |err| match err {
+            crate::decoder::QOIError::IO(err) => err,
+            err => std::io::Error::other(err),
+        };
+
+        // Large screenshots take a while on a single thread
+        let (bytes, _) = encoder::encode_parallel(&self.header(), &self.pixels).map_err(to_io)?;
+
+        std::fs::write(path, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(width: u32, height: u32) -> Bitmap {
+        let pixels = vec![Pixel::new(1, 2, 3, 255); (width * height) as usize];
+        Bitmap { width, height, channels: 4, colorspace: 0, pixels, recovered: 0, stopped: None }
+    }
+
+    #[test]
+    fn fit_within_leaves_empty_images_alone() {
+        for (width, height) in [(0, 5), (5, 0), (0, 0)] {
+            let fitted = bitmap(width, height).fit_within(800, 600);
+            assert_eq!((fitted.width, fitted.height, fitted.pixels.len()), (width, height, 0));
+        }
+    }
+
+    #[test]
+    fn fit_within_scales_down() {
+        let fitted = bitmap(400, 100).fit_within(200, 200);
+
+        assert_eq!((fitted.width, fitted.height), (200, 50));
+        assert!(fitted.pixels.iter().all(|&p| p == Pixel::new(1, 2, 3, 255)));
+    }
+}
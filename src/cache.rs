@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::bitmap::{Bitmap, LoadOptions};
+
+type Decoded = (PathBuf, Result<Bitmap, String>);
+
+/* Small LRU cache of decoded images. Neighbours of the current image are
+ * prefetched on background threads so that stepping through a list does
+ * not wait on the decoder */
+pub struct ImageCache {
+    capacity: usize,
+    entries: Vec<(PathBuf, Arc<Bitmap>)>, // Least recently used first
+    pending: HashSet<PathBuf>,
+
+    options: LoadOptions, // Passed on to `Bitmap::load`
+
+    sender: Sender<Decoded>,
+    receiver: Receiver<Decoded>,
+}
+
+impl ImageCache {
+    pub fn new(capacity: usize, options: LoadOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            capacity: capacity.max(1),
+            entries: Vec::new(),
+            pending: HashSet::new(),
+
+            options,
+
+            sender,
+            receiver,
+        }
+    }
+
+    fn insert(&mut self, path: PathBuf, bitmap: Arc<Bitmap>) {
+        self.entries.retain(|(p, _)| *p != path);
+        self.entries.push((path, bitmap));
+
+        if self.entries.len() > self.capacity {
+            self.entries.remove(0);
+        }
+    }
+
+    fn take_decoded(&mut self, (path, decoded): Decoded) {
+        self.pending.remove(&path);
+
+        // Failed prefetches are not remembered, `load` reports the error
+        // when the image is actually requested
+        if let Ok(bitmap) = decoded {
+            self.insert(path, Arc::new(bitmap));
+        }
+    }
+
+    /* Moves finished prefetches into the cache */
+    pub fn receive(&mut self) {
+        while let Ok(decoded) = self.receiver.try_recv() {
+            self.take_decoded(decoded);
+        }
+    }
+
+    fn get(&mut self, path: &Path) -> Option<Arc<Bitmap>> {
+        let index = self.entries.iter().position(|(p, _)| p == path)?;
+
+        // Mark as most recently used
+        let entry = self.entries.remove(index);
+        let bitmap = Arc::clone(&entry.1);
+        self.entries.push(entry);
+
+        Some(bitmap)
+    }
+
+    /* Returns the decoded image, waiting for a prefetch that is already
+     * under way rather than decoding the file a second time */
+    pub fn load(&mut self, path: &Path) -> Result<Arc<Bitmap>, String> {
+        self.receive();
+
+        while self.pending.contains(path) {
+            match self.receiver.recv() {
+                Ok(decoded) => self.take_decoded(decoded),
+                Err(_) => break,
+            }
+        }
+
+        if let Some(bitmap) = self.get(path) {
+            return Ok(bitmap);
+        }
+
+        let bitmap = Arc::new(Bitmap::load(path.to_str().unwrap_or_default(), &self.options)?);
+        self.insert(path.to_path_buf(), Arc::clone(&bitmap));
+
+        Ok(bitmap)
+    }
+
+    /* A preview of a large image that is not cached yet, starting to decode
+     * the full image in the background. See `ready` for when that is done */
+    pub fn preview(&mut self, path: &Path) -> Option<(Bitmap, (u32, u32))> {
+        if self.entries.iter().any(|(p, _)| p == path) {
+            return None;
+        }
+
+        let preview = Bitmap::load_preview(path, &self.options)?;
+        self.prefetch(path);
+
+        Some(preview)
+    }
+
+    /* The decoded image or why it failed to decode, once no prefetch of it
+     * is under way any more */
+    pub fn ready(&mut self, path: &Path) -> Option<Result<Arc<Bitmap>, String>> {
+        self.receive();
+
+        match self.pending.contains(path) {
+            true => None,
+            false => Some(self.load(path)),
+        }
+    }
+
+    /* Forgets the decoded image, so that the next load reads the file again */
+    pub fn invalidate(&mut self, path: &Path) {
+        self.entries.retain(|(p, _)| p != path);
+    }
+
+    /* Starts decoding in the background unless cached or already under way */
+    pub fn prefetch(&mut self, path: &Path) {
+        if self.pending.contains(path) || self.entries.iter().any(|(p, _)| p == path) {
+            return;
+        }
+
+        self.pending.insert(path.to_path_buf());
+
+        let path = path.to_path_buf();
+        let sender = self.sender.clone();
+        let options = self.options;
+
+        thread::spawn(move || {
+            let decoded = Bitmap::load(path.to_str().unwrap_or_default(), &options);
+            let _ = sender.send((path, decoded));
+        });
+    }
+}
@@ -0,0 +1,546 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use crate::bench::Source;
+use crate::encode::Target;
+use crate::filelist::{Filter, SortOrder};
+use crate::filter::Kernel;
+use crate::gamut::Gamut;
+use crate::metadata::{Entry, Metadata};
+use crate::raw::RawFormat;
+use crate::resample::Resampler;
+use crate::view::Scaling;
+
+pub const USAGE: &str = "\
+Usage: qoiviewer [OPTIONS] [PATH]...
+       qoiviewer stats [--color-stats] [--channel-stats] [PATH]...
+       qoiviewer trace [PATH]...
+       qoiviewer verify [PATH]...
+       qoiviewer crosscheck [PATH]...
+       qoiviewer bench [--iterations N] [--warmup N] [--source LIST] [PATH]...
+       qoiviewer decode [--raw FORMAT] [--region X,Y,WxH] [PATH]... > out.bin
+       qoiviewer animate [--delay MS] --output OUT.qoia [PATH]...
+       qoiviewer encode [--stats] [--resize WxH] [--meta KEY=VALUE]... [--output OUT.qoi] [PATH]...
+       qoiviewer convert [--to FORMAT] [--out-dir DIR] [PATTERN]...
+       qoiviewer palette [-n COLORS] [--output SWATCH.png] [PATH]...
+       qoiviewer diff A B
+       qoiviewer run SCRIPT [PATH]...
+
+Shows the given QOI images. Directories as well as .zip and .tar archives are
+expanded to the images inside, and - reads one from standard input.
+
+Commands:
+  stats                     Print chunk counts and byte totals per op type
+  trace                     Print every chunk with its offset, raw bytes, op
+                            and resulting pixel, skipping past faulty chunks
+  verify                    Decode, re-encode and decode again, checking that
+                            the pixels survive the round trip
+  crosscheck                Compare the decoded pixels against the `qoi` crate
+                            (needs the `crosscheck` feature)
+  bench                     Time decoding each file, reporting min, median and
+                            p95 times, MB/s of input and megapixels/s of output
+  decode                    Write the decoded pixels of each file to stdout as
+                            raw bytes, back to back
+  animate                   Combine the files into a .qoia animation, one frame
+                            each in the given order. .qoia files open as
+                            looping streams
+  diff                      Print the per channel max and mean absolute error,
+                            PSNR and SSIM of two images, exiting with 1 if
+                            their pixels differ
+  encode, convert           Convert each file to QOI, written next to it with
+                            a .qoi extension, several files at a time. Quoted
+                            patterns like 'renders/**/*.png' are expanded,
+                            ** standing for any number of directories
+  palette                   Print the dominant colors of each file as hex,
+                            most common first
+  run                       Run a batch script written in Rhai, the paths after
+                            it being in ARGS. Scripts call open(path),
+                            files(path) for the images of a directory,
+                            archive or pattern, name(path) and dir(path), and
+                            chain image.trim(), .resize(longest) or
+                            .resize(w, h), .filter(kernel), .gray() and
+                            .save(path), e.g.
+                              for p in files(\"in\") {
+                                open(p).trim().resize(512).save(`out/${name(p)}.qoi`)
+                              }
+
+Options:
+  --sort <name|mtime|size>  Order of the file list
+  --filter <PATTERNS>       Comma separated patterns of files to pick up from
+                            directories and archives [default: *.qoi]
+  --watch-dir <DIR>         Open the newest image in a directory, then each new
+                            one as it appears there, e.g. from a renderer
+  --watch-clipboard         Show every image copied to the clipboard from then
+                            on, e.g. to collect screenshots
+  --save-clipboard <DIR>    Watch the clipboard and save each new image into
+                            DIR as clipboard_0001.qoi, clipboard_0002.qoi, ...
+  --open-with <COMMAND>     Command that E opens the current file with; `{}`
+                            stands for the path [default: $EDITOR_IMAGE]
+  --fullscreen              Start in fullscreen
+  --backdrop <IMAGE>        Show an image such as a game background through the
+                            transparent parts, tiled at the same zoom
+  --stretch-backdrop        Stretch the backdrop over the image instead
+  --gamut <GAMUT>           Gamut of the monitor, for showing sRGB images right
+                            without color management: srgb, display-p3
+                            [default: srgb]
+  --deep-color              Show opaque images with 10 bits per channel, where
+                            the renderer and monitor have them
+  --fit                     Scale images to fit the window
+  --actual-size             Show images unscaled
+  --integer                 Scale images by the largest whole factor that
+                            fits, keeping pixel art crisp
+  --zoom <PERCENT>          Show images at a fixed zoom, e.g. 200
+  --position <X,Y>          Place the window at X,Y on the screen
+  --monitor <N>             Open on the Nth monitor, 1 being the primary one.
+                            Shift+M moves the window on to the next
+  --windows                 Open every file in a window of its own, e.g. to
+                            spread images over several monitors
+  --compare                 Show two images side by side, zooming and panning
+                            both at once. Shift+X fades the second over the
+                            first
+  --diff-gain <N>           How much X amplifies differences in the heatmap of
+                            a comparison [default: 8]
+  --stream                  Play the first path as a stream of back to back
+                            QOI frames, e.g. - for the output of
+                            `ffmpeg -f image2pipe -vcodec qoi -`
+  --fps <N>                 Frame rate to play streams at [default: 30]
+  --delta                   Write recordings and animations as delta frames,
+                            each coded against the one before it
+  --info                    Print the header of each file and exit
+  --validate                Strictly check that each file is a well formed QOI
+                            image, exiting with 1 if any is not. Metadata after
+                            the end marker passes
+  --strict                  Have --validate reject metadata after the end
+                            marker as well, like any other trailing data
+  --hash                    Print a SHA-256 of the decoded RGBA pixels of each
+                            file and exit
+  --sixel                   Print each file to the terminal as sixel graphics
+                            and exit, e.g. to preview images over SSH
+  --kitty                   Print each file with the kitty graphics protocol
+                            and exit, for kitty, WezTerm and the like
+  --term                    Print each file as colored half blocks and exit,
+                            a rough preview for any truecolor terminal
+  --term-size <WxH>         Largest size in pixels of images printed to the
+                            terminal, where --term fits two in a character
+                            [default: 800x600, or 80x48 with --term]
+  --dither                  Dither images printed to the terminal, or written
+                            as rgb565 or rgb555, instead of mapping each
+                            pixel to the nearest color
+  --lenient                 Show corrupt QOI files anyway, with undecodable
+                            chunks in magenta
+  --untrusted               Harden decoding for hostile files: only QOI files,
+                            no data past the declared pixels, and at most 1G
+                            of decoded pixels unless --max-memory says otherwise
+  --max-pixels <COUNT>      Refuse images with more pixels than this
+                            [default: 400000000]
+  --max-memory <SIZE>       Refuse images that take more memory than this once
+                            decoded, e.g. 512M or 2G
+  --iterations <N>          Timed decodes per file for bench [default: 10]
+  --warmup <N>              Untimed decodes before those [default: 2]
+  --source <LIST>           Comma separated decode paths for bench to compare:
+                            read, slice, mmap or all [default: slice]
+  --raw <FORMAT>            Pixel layout that decode writes: rgb, rgba, bgra,
+                            argb, abgr, or rgb565 and rgb555 packed into 16
+                            little endian bits [default: rgba]
+  --premultiply             Have decode multiply colors by alpha
+  --region <X,Y,WxH>        Have decode write only this part of each image,
+                            e.g. 0,1000,1920x1080. QOI files are indexed
+                            first, then only the rows of the region decoded
+  --delay <MS>              How long each frame of animate is shown
+                            [default: 100]
+  -o, --output <PATH>       File that animate or encode writes, or where
+                            palette saves a swatch of the colors
+  -n, --colors <N>          How many colors palette picks [default: 8]
+  --color-stats             Have stats also count unique colors and
+                            transparent pixels
+  --channel-stats           Have stats also print the min, max, mean and
+                            standard deviation of each channel
+  --stats                   Print the chunks, bytes and pixels of every op
+                            that encode emitted
+  --reduce-channels         Have encode write images with 4 channels but no
+                            transparency as RGB
+  --approx <BITS>           Have encode round each color channel to this many
+                            bits first, 1 to 7, for smaller but lossy files.
+                            Gains start below 6 bits
+  --optimize                Have encode search op choices and band layouts
+                            for a smaller file than the greedy encoder's,
+                            printing the bytes saved. Slower
+  --resize <WxH>            Have encode scale every image to exactly this size
+  --resample <FILTER>       How --resize interpolates: nearest, bilinear or
+                            lanczos3 [default: lanczos3]
+  --meta <KEY=VALUE>        Have encode store this pair, e.g. author=Jo, in a
+                            metadata block after the end marker. Repeatable.
+                            --info prints the pairs
+  --gray                    Have encode convert images to gray and write them
+                            with 1 channel, or 2 with alpha. An extension of
+                            QOI for height maps and masks that other decoders
+                            may reject
+  --to <qoi|qoi16|png>      Format encode writes [default: qoi]
+  --out-dir <DIR>           Directory encode writes to instead of next to each
+                            input, keeping the directories below the one all
+                            inputs share
+  --qoi16                   Have encode write the experimental 16 bits per
+                            channel format, .qoi16, which is NOT standard QOI
+                            and only this viewer reads (needs the `qoi16`
+                            feature). Same as --to qoi16
+  --kernel <FILTER>         Filter that encode applies first, and that the
+                            viewer cycles to after its presets: box,
+                            gaussian, sharpen or 9 or 25 comma separated
+                            weights of a 3x3 or 5x5 kernel
+  -v, --verbose             Log warnings about recoverable problems, such as
+                            trailing data. Twice (-vv) also logs every chunk.
+                            RUST_LOG overrides this
+  --trace                   Log timed spans for decoding, texture uploads and
+                            presenting frames to stderr
+  --config <PATH>           Settings file to use instead of
+                            ~/.config/qoiviewer/config.toml
+  -h, --help                Print this help";
+
+/* Runs instead of opening the viewer */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Command {
+    Stats,
+    Trace,
+    Verify,
+    CrossCheck,
+    Bench,
+    Decode,
+    Animate,
+    Diff,
+    Encode,
+    Palette,
+    Run,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub command: Option<Command>,
+    pub paths: Vec<PathBuf>,
+    pub sort: Option<SortOrder>,
+    pub filter: Filter,
+    pub open_with: Option<String>,
+    pub watch_dir: Option<PathBuf>,
+    pub watch_clipboard: bool,
+    pub save_clipboard: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub verbose: u8,
+    pub trace: bool,
+    pub info: bool,
+    pub validate: bool,
+    pub hash: bool,
+    pub sixel: bool,
+    pub kitty: bool,
+    pub term: bool,
+    pub term_size: Option<(u32, u32)>,
+    pub dither: bool,
+    pub lenient: bool,
+    pub untrusted: bool,
+    pub iterations: Option<u32>,
+    pub warmup: Option<u32>,
+    pub sources: Vec<Source>,
+    pub raw: Option<RawFormat>,
+    pub premultiply: bool,
+    pub region: Option<(u32, u32, u32, u32)>,
+    pub delay: Option<u32>,
+    pub output: Option<PathBuf>,
+    pub stats: bool,
+    pub reduce_channels: bool,
+    pub approx: Option<u8>,
+    pub optimize: bool,
+    pub kernel: Option<Kernel>,
+    pub resize: Option<(u32, u32)>,
+    pub resampler: Option<Resampler>,
+    pub target: Option<Target>,
+    pub out_dir: Option<PathBuf>,
+    pub gray: bool,
+    pub metadata: Metadata,
+    pub strict: bool,
+    pub colors: Option<usize>,
+    pub color_stats: bool,
+    pub channel_stats: bool,
+    pub max_pixels: Option<u64>,
+    pub max_memory: Option<u64>,
+    pub fullscreen: bool,
+    pub backdrop: Option<PathBuf>,
+    pub stretch_backdrop: bool,
+    pub gamut: Option<Gamut>,
+    pub deep_color: bool,
+    pub scaling: Option<Scaling>,
+    pub position: Option<(i32, i32)>,
+    pub monitor: Option<u32>,
+    pub windows: bool,
+    pub compare: bool,
+    pub diff_gain: Option<f32>,
+    pub stream: bool,
+    pub fps: Option<f32>,
+    pub delta: bool,
+    pub help: bool,
+}
+
+pub fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter().peekable();
+
+    // A command is only recognized as the first argument
+    let command = match args.peek().and_then(|arg| arg.to_str()) {
+        Some("stats") => Some(Command::Stats),
+        Some("trace") => Some(Command::Trace),
+        Some("verify") => Some(Command::Verify),
+        Some("crosscheck") => Some(Command::CrossCheck),
+        Some("bench") => Some(Command::Bench),
+        Some("decode") => Some(Command::Decode),
+        Some("animate") => Some(Command::Animate),
+        Some("diff") => Some(Command::Diff),
+        Some("encode" | "convert") => Some(Command::Encode),
+        Some("palette") => Some(Command::Palette),
+        Some("run") => Some(Command::Run),
+        _ => None,
+    };
+
+    if command.is_some() {
+        args.next();
+        options.command = command;
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("-h" | "--help") => options.help = true,
+            Some("--sort") => {
+                let value = value_of("--sort", args.next())?;
+                options.sort = Some(value.parse()?);
+            },
+            Some("--filter") => {
+                let value = value_of("--filter", args.next())?;
+                options.filter = value.parse()?;
+            },
+            Some("--watch-dir") => {
+                options.watch_dir = Some(PathBuf::from(value_of("--watch-dir", args.next())?));
+            },
+            Some("--watch-clipboard") => options.watch_clipboard = true,
+            Some("--save-clipboard") => {
+                options.save_clipboard = Some(PathBuf::from(value_of("--save-clipboard", args.next())?));
+            },
+            Some("--open-with") => {
+                options.open_with = Some(value_of("--open-with", args.next())?);
+            },
+            Some("-v" | "--verbose") => options.verbose += 1,
+            Some("-vv") => options.verbose += 2,
+            Some("--trace") => options.trace = true,
+            Some("--config") => {
+                options.config = Some(PathBuf::from(value_of("--config", args.next())?));
+            },
+            Some("--info") => options.info = true,
+            Some("--validate") => options.validate = true,
+            Some("--strict") => options.strict = true,
+            Some("--hash") => options.hash = true,
+            Some("--sixel") => options.sixel = true,
+            Some("--kitty") => options.kitty = true,
+            Some("--term") => options.term = true,
+            Some("--dither") => options.dither = true,
+            Some("--term-size") => {
+                let value = value_of("--term-size", args.next())?;
+                options.term_size = Some(parse_dimensions(&value)?);
+            },
+            Some("--lenient") => options.lenient = true,
+            Some("--untrusted") => options.untrusted = true,
+            Some("--iterations") => {
+                let value = value_of("--iterations", args.next())?;
+                options.iterations = Some(value.parse().map_err(|_| format!("Invalid iteration count: {}", value))?);
+            },
+            Some("--warmup") => {
+                let value = value_of("--warmup", args.next())?;
+                options.warmup = Some(value.parse().map_err(|_| format!("Invalid warmup count: {}", value))?);
+            },
+            Some("--source") => {
+                let value = value_of("--source", args.next())?;
+                options.sources = match value.as_str() {
+                    "all" => Source::ALL.to_vec(),
+                    _ => value.split(',').map(|s| s.trim().parse()).collect::<Result<_, _>>()?,
+                };
+            },
+            Some("--raw") => {
+                let value = value_of("--raw", args.next())?;
+                options.raw = Some(value.parse()?);
+            },
+            Some("--premultiply") => options.premultiply = true,
+            Some("--region") => {
+                let value = value_of("--region", args.next())?;
+                options.region = Some(parse_region(&value)?);
+            },
+            Some("--delay") => {
+                let value = value_of("--delay", args.next())?;
+                options.delay = Some(value.parse().map_err(|_| format!("Invalid delay: {}", value))?);
+            },
+            Some("--stats") => options.stats = true,
+            Some("--reduce-channels") => options.reduce_channels = true,
+            Some("--optimize") => options.optimize = true,
+            Some("--approx") => {
+                let value = value_of("--approx", args.next())?;
+                let bits = value.parse().ok().filter(|bits: &u8| (1..=7).contains(bits));
+                options.approx = Some(bits.ok_or_else(|| format!("Invalid bit count, expected 1 to 7: {}", value))?);
+            },
+            Some("--resize") => {
+                let value = value_of("--resize", args.next())?;
+                options.resize = Some(parse_dimensions(&value)?);
+            },
+            Some("--resample") => {
+                let value = value_of("--resample", args.next())?;
+                options.resampler = Some(value.parse()?);
+            },
+            Some("--kernel") => {
+                let value = value_of("--kernel", args.next())?;
+                options.kernel = Some(value.parse()?);
+            },
+            Some("--qoi16") => options.target = Some(Target::Qoi16),
+            Some("--to") => {
+                let value = value_of("--to", args.next())?;
+                options.target = Some(value.parse()?);
+            },
+            Some("--out-dir") => {
+                options.out_dir = Some(PathBuf::from(value_of("--out-dir", args.next())?));
+            },
+            Some("--gray") => options.gray = true,
+            Some("--meta") => {
+                let Entry(key, value) = value_of("--meta", args.next())?.parse()?;
+                options.metadata.entries.push((key, value));
+            },
+            Some("--color-stats") => options.color_stats = true,
+            Some("--channel-stats") => options.channel_stats = true,
+            Some("-n" | "--colors") => {
+                let value = value_of("--colors", args.next())?;
+                let colors = value.parse().ok().filter(|&colors: &usize| colors > 0);
+                options.colors = Some(colors.ok_or_else(|| format!("Invalid color count: {}", value))?);
+            },
+            Some("-o" | "--output") => {
+                options.output = Some(PathBuf::from(value_of("--output", args.next())?));
+            },
+            Some("--max-pixels") => {
+                let value = value_of("--max-pixels", args.next())?;
+                options.max_pixels = Some(value.parse().map_err(|_| format!("Invalid pixel count: {}", value))?);
+            },
+            Some("--max-memory") => {
+                let value = value_of("--max-memory", args.next())?;
+                options.max_memory = Some(parse_size(&value)?);
+            },
+            Some("--fullscreen") => options.fullscreen = true,
+            Some("--backdrop") => options.backdrop = Some(PathBuf::from(value_of("--backdrop", args.next())?)),
+            Some("--stretch-backdrop") => options.stretch_backdrop = true,
+            Some("--gamut") => options.gamut = Some(value_of("--gamut", args.next())?.parse()?),
+            Some("--deep-color") => options.deep_color = true,
+            Some("--fit") => options.scaling = Some(Scaling::Fit),
+            Some("--actual-size") => options.scaling = Some(Scaling::Actual),
+            Some("--integer") => options.scaling = Some(Scaling::Integer),
+            Some("--zoom") => {
+                let value = value_of("--zoom", args.next())?;
+                options.scaling = Some(Scaling::Zoom(parse_zoom(&value)?));
+            },
+            Some("--windows") => options.windows = true,
+            Some("--compare") => options.compare = true,
+            Some("--diff-gain") => {
+                let value = value_of("--diff-gain", args.next())?;
+                let gain = value.parse().ok().filter(|&gain: &f32| gain > 0.0);
+                options.diff_gain = Some(gain.ok_or_else(|| format!("Invalid gain: {}", value))?);
+            },
+            Some("--stream") => options.stream = true,
+            Some("--delta") => options.delta = true,
+            Some("--fps") => {
+                let value = value_of("--fps", args.next())?;
+                let fps = value.parse().ok().filter(|&fps: &f32| fps > 0.0);
+                options.fps = Some(fps.ok_or_else(|| format!("Invalid frame rate: {}", value))?);
+            },
+            Some("--position") => {
+                let value = value_of("--position", args.next())?;
+                options.position = Some(parse_position(&value)?);
+            },
+            Some("--monitor") => {
+                let value = value_of("--monitor", args.next())?;
+                let monitor = value.parse().ok().filter(|&monitor: &u32| monitor > 0);
+                options.monitor = Some(monitor.ok_or_else(|| format!("Invalid monitor: {}", value))?);
+            },
+            Some(flag) if flag.starts_with("--") => {
+                return Err(format!("Unknown option: {}", flag));
+            },
+            _ => options.paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn value_of(flag: &str, value: Option<OsString>) -> Result<String, String> {
+    value
+        .and_then(|v| v.into_string().ok())
+        .ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+/* A percentage like "150" or "150%" as a scale factor */
+fn parse_zoom(value: &str) -> Result<f32, String> {
+    value
+        .trim_end_matches('%')
+        .parse::<f32>()
+        .ok()
+        .filter(|pct| pct.is_finite() && *pct > 0.0)
+        .map(|pct| pct / 100.0)
+        .ok_or_else(|| format!("Invalid zoom: {}", value))
+}
+
+fn parse_position(value: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("Invalid position, expected X,Y: {}", value);
+
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+
+    Ok((
+        x.trim().parse().map_err(|_| invalid())?,
+        y.trim().parse().map_err(|_| invalid())?,
+    ))
+}
+
+fn parse_dimensions(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("Invalid size, expected WxH: {}", value);
+
+    let (width, height) = value.split_once(['x', 'X']).ok_or_else(invalid)?;
+
+    let parse = |n: &str| n.trim().parse().ok().filter(|&n: &u32| n > 0).ok_or_else(invalid);
+
+    Ok((parse(width)?, parse(height)?))
+}
+
+/* A rectangle as X,Y,WxH */
+fn parse_region(value: &str) -> Result<(u32, u32, u32, u32), String> {
+    let invalid = || format!("Invalid region, expected X,Y,WxH: {}", value);
+
+    let (position, size) = value.rsplit_once(',').ok_or_else(invalid)?;
+    let (x, y) = position.split_once(',').ok_or_else(invalid)?;
+    let (width, height) = parse_dimensions(size).map_err(|_| invalid())?;
+
+    Ok((
+        x.trim().parse().map_err(|_| invalid())?,
+        y.trim().parse().map_err(|_| invalid())?,
+        width,
+        height,
+    ))
+}
+
+/* A byte count with an optional K, M or G suffix (powers of 1024) */
+fn parse_size(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size: {}", value);
+
+    let (digits, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => value.split_at(i),
+        None => (value, ""),
+    };
+
+    let shift = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(invalid()),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(invalid)
+}
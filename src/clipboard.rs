@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::bitmap::Bitmap;
+
+// Reading the clipboard copies the whole image over from its owner
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/* Image access to the system clipboard. On X11 the clipboard contents are
+ * served by the owning process, so this has to outlive the copy */
+pub struct Clipboard {
+    inner: arboard::Clipboard,
+}
+
+impl Clipboard {
+    pub fn new() -> Result<Self, arboard::Error> {
+        arboard::Clipboard::new().map(|inner| Self { inner })
+    }
+
+    pub fn copy_bitmap(&mut self, bitmap: &Bitmap) -> Result<(), arboard::Error> {
+        self.inner.set_image(arboard::ImageData {
+            width: bitmap.width as usize,
+            height: bitmap.height as usize,
+            bytes: Cow::Owned(bitmap.to_rgba_bytes()),
+        })
+    }
+
+    /* Clipboard images always come in as RGBA */
+    pub fn paste_bitmap(&mut self) -> Result<Bitmap, arboard::Error> {
+        let image = self.inner.get_image()?;
+
+        Ok(Bitmap::from_bytes(image.width as u32, image.height as u32, 4, &image.bytes))
+    }
+
+    /* Identifies the image on the clipboard, None when there is none */
+    fn fingerprint(&mut self) -> Option<(u64, arboard::ImageData<'static>)> {
+        let image = self.inner.get_image().ok()?;
+        Some((fingerprint(image.width as u32, image.height as u32, &image.bytes), image))
+    }
+}
+
+fn fingerprint(width: u32, height: u32, rgba: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (width, height, rgba).hash(&mut hasher);
+    hasher.finish()
+}
+
+/* Polls the clipboard for images put on it after watching started, e.g. to
+ * collect screenshots. Each can be saved as the next of a numbered series,
+ * clipboard_0001.qoi and on, in a directory */
+pub struct ClipboardWatch {
+    seen: Option<u64>, // The image on the clipboard at the last poll
+    last_poll: Instant,
+    save_dir: Option<PathBuf>,
+    next: u32, // Number of the next file to save, unless it exists
+}
+
+impl ClipboardWatch {
+    /* The image on the clipboard right now does not count as new */
+    pub fn new(clipboard: &mut Clipboard, save_dir: Option<PathBuf>) -> Self {
+        Self {
+            seen: clipboard.fingerprint().map(|(seen, _)| seen),
+            last_poll: Instant::now(),
+            save_dir,
+            next: 1,
+        }
+    }
+
+    pub fn poll(&mut self, clipboard: &mut Clipboard) -> Option<Bitmap> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+
+        self.last_poll = Instant::now();
+
+        let (seen, image) = clipboard.fingerprint()?;
+
+        if self.seen == Some(seen) {
+            return None;
+        }
+
+        self.seen = Some(seen);
+        Some(Bitmap::from_bytes(image.width as u32, image.height as u32, 4, &image.bytes))
+    }
+
+    /* Takes an image the viewer copied itself for one that is already seen */
+    pub fn ignore(&mut self, bitmap: &Bitmap) {
+        self.seen = Some(fingerprint(bitmap.width, bitmap.height, &bitmap.to_rgba_bytes()));
+    }
+
+    /* Saves the image under the first number not taken yet, or None when
+     * there is no directory to save to */
+    pub fn save(&mut self, bitmap: &Bitmap) -> Option<std::io::Result<PathBuf>> {
+        let dir = self.save_dir.as_ref()?;
+
+        let result = std::fs::create_dir_all(dir).and_then(|()| {
+            let mut path = dir.join(format!("clipboard_{:04}.qoi", self.next));
+
+            while path.exists() {
+                self.next += 1;
+                path = dir.join(format!("clipboard_{:04}.qoi", self.next));
+            }
+
+            bitmap.save(&path).map(|()| path)
+        });
+
+        Some(result)
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use sdl2::pixels::Color;
+
+use crate::bindings::{Action, Bindings, KeyCombo};
+use crate::bitmap::LoadOptions;
+use crate::diffmap;
+use crate::filter::Kernel;
+use crate::gamut::Output;
+use crate::view::Scaling;
+
+/* Settings from ~/.config/qoiviewer/config.toml, e.g.
+ *
+ *     background = "#202020"
+ *     scaling = "fit"          # or "actual", "integer"
+ *     slideshow_interval = 3.0 # seconds
+ *     open_with = "gimp {}"
+ *     lenient = false          # show corrupt files with errors in magenta
+ *     record_delta = false     # record delta frames, see `--delta`
+ *     diff_gain = 8.0          # amplification of the compare heatmap
+ *     kernel = "1,2,1,0,0,0,-1,-2,-1" # filter cycled to after the presets
+ *     gamut = "display-p3"     # or "srgb", of the monitor when not color managed
+ *     deep_color = false       # 10 bit textures for opaque images
+ *
+ *     [keys]
+ *     quit = ["Escape", "Q"]
+ *     next_image = ["Right", "Space", "N"]
+ *
+ * Every setting is optional. A key list replaces all default keys of that
+ * action, see `Action::name` for the names */
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub background: Color,
+    pub scaling: Scaling,
+    pub slideshow_interval: Duration,
+    pub open_with: Option<String>,
+    pub record_delta: bool,
+    pub diff_gain: f32,
+    pub kernel: Option<Kernel>,
+    pub output: Output,
+    pub decode: LoadOptions,
+    pub bindings: Bindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            background: Color::RGB(0, 255, 0),
+            scaling: Scaling::Fit,
+            slideshow_interval: Duration::from_secs(3),
+            open_with: None,
+            record_delta: false,
+            diff_gain: diffmap::DEFAULT_GAIN,
+            kernel: None,
+            output: Output::default(),
+            decode: LoadOptions::default(),
+            bindings: Bindings::default(),
+        }
+    }
+}
+
+impl Config {
+    /* $XDG_CONFIG_HOME/qoiviewer/config.toml, falling back to ~/.config */
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+        Some(base.join("qoiviewer").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        Self::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table = text.parse::<toml::Table>().map_err(|err| err.to_string())?;
+        let mut config = Self::default();
+
+        for (key, value) in table.iter() {
+            match key.as_str() {
+                "background" => {
+                    config.background = parse_color(expect_str(key, value)?)?;
+                },
+                "scaling" => {
+                    config.scaling = expect_str(key, value)?.parse()?;
+                },
+                "slideshow_interval" => {
+                    let secs = value
+                        .as_float()
+                        .or_else(|| value.as_integer().map(|i| i as f64))
+                        .filter(|&secs| secs > 0.0)
+                        .ok_or_else(|| format!("`{}` must be a positive number of seconds", key))?;
+
+                    config.slideshow_interval = Duration::from_secs_f64(secs);
+                },
+                "diff_gain" => {
+                    config.diff_gain = value
+                        .as_float()
+                        .or_else(|| value.as_integer().map(|i| i as f64))
+                        .filter(|&gain| gain > 0.0)
+                        .ok_or_else(|| format!("`{}` must be a positive number", key))? as f32;
+                },
+                "kernel" => {
+                    config.kernel = Some(expect_str(key, value)?.parse()?);
+                },
+                "open_with" => {
+                    config.open_with = Some(expect_str(key, value)?.to_string());
+                },
+                "lenient" => {
+                    config.decode.lenient = value
+                        .as_bool()
+                        .ok_or_else(|| format!("`{}` must be true or false", key))?;
+                },
+                "gamut" => {
+                    config.output.gamut = expect_str(key, value)?.parse()?;
+                },
+                "deep_color" => {
+                    config.output.deep_color = value
+                        .as_bool()
+                        .ok_or_else(|| format!("`{}` must be true or false", key))?;
+                },
+                "record_delta" => {
+                    config.record_delta = value
+                        .as_bool()
+                        .ok_or_else(|| format!("`{}` must be true or false", key))?;
+                },
+                "keys" => {
+                    let keys = value
+                        .as_table()
+                        .ok_or_else(|| "`keys` must be a table".to_string())?;
+
+                    for (name, combos) in keys.iter() {
+                        let action = Action::from_name(name)
+                            .ok_or_else(|| format!("Unknown action `{}`", name))?;
+
+                        config.bindings.rebind(action, parse_combos(name, combos)?);
+                    }
+                },
+                _ => return Err(format!("Unknown setting `{}`", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn expect_str<'v>(key: &str, value: &'v toml::Value) -> Result<&'v str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("`{}` must be a string", key))
+}
+
+/* A single key or a list of keys */
+fn parse_combos(name: &str, value: &toml::Value) -> Result<Vec<KeyCombo>, String> {
+    match value {
+        toml::Value::String(s) => Ok(vec![KeyCombo::parse(s)?]),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| KeyCombo::parse(expect_str(name, item)?))
+            .collect(),
+        _ => Err(format!("Keys for `{}` must be a string or a list of strings", name)),
+    }
+}
+
+/* "#RRGGBB" or "#RRGGBBAA" */
+pub fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let invalid = || format!("Invalid color \"{}\", expected #RRGGBB", s);
+
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+
+    Ok(Color::RGBA(channel(0)?, channel(2)?, channel(4)?, alpha))
+}
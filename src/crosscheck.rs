@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use crate::decoder::{EvaluatedChunk, ImageDecoder, Pixel};
+use crate::input;
+
+/* Decodes a file with both our decoder and the `qoi` crate, failing on the
+ * first pixel where they disagree */
+pub fn check(path: &Path) -> Result<u64, String> {
+    let data = input::read(path)
+        .map_err(|err| format!("Failed to read file: {}", err))?;
+
+    let (reference_header, reference) = qoi::decode_to_vec(&data)
+        .map_err(|err| format!("Reference decoder failed: {}", err))?;
+
+    let dec = ImageDecoder::new(&data[..])
+        .map_err(|err| format!("Decoder failed: {}", err))?;
+
+    let header = dec.header().clone();
+    let channels = reference_header.channels.as_u8() as usize;
+
+    if (header.width, header.height, header.channels as usize) != (reference_header.width, reference_header.height, channels) {
+        return Err(format!(
+            "Headers differ: {}x{}x{} against the reference {}x{}x{}",
+            header.width, header.height, header.channels,
+            reference_header.width, reference_header.height, channels
+        ));
+    }
+
+    let width = header.width.max(1) as u64;
+    let count = reference.len() / channels;
+
+    let mut chunks = dec.chunks_iter();
+    let mut decoded = 0;
+
+    while decoded < count {
+        let px = match chunks.next_chunk() {
+            EvaluatedChunk::Ok(px) => px,
+            EvaluatedChunk::EndMarker => {
+                return Err(format!("End marker after {} pixels, the reference decoded {}", decoded, count));
+            },
+            EvaluatedChunk::Faulty(err) => {
+                return Err(format!("Decoder failed at pixel {}: {}", decoded, err));
+            },
+        };
+
+        let expected = &reference[decoded * channels..(decoded + 1) * channels];
+        let expected = Pixel::new(expected[0], expected[1], expected[2], if channels == 4 { expected[3] } else { 255 });
+
+        // The alpha of RGB images is not part of the output
+        let matches = match channels {
+            4 => px == expected,
+            _ => (px.r, px.g, px.b) == (expected.r, expected.g, expected.b),
+        };
+
+        if !matches {
+            let offset = chunks.position() - chunks.last_chunk().map_or(0, |c| c.get_size() as u64);
+
+            return Err(format!(
+                "Pixel ({}, {}) from the chunk at byte {}: decoded {}, the reference decoded {}",
+                decoded as u64 % width, decoded as u64 / width, offset, px.to_hex(), expected.to_hex()
+            ));
+        }
+
+        decoded += 1;
+    }
+
+    Ok(count as u64)
+}
+
+/* Cross checks every file, returning whether both decoders agreed on all of them */
+pub fn check_all<'p>(paths: impl Iterator<Item = &'p Path>) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        match check(path) {
+            Ok(pixels) => println!("{}: ok, {} pixels identical", path.display(), pixels),
+            Err(err) => {
+                println!("{}: {}", path.display(), err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
@@ -1,29 +1,43 @@
-use std::io::Read;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::ops::{Add, Sub};
 
 type U8Array<const N: usize> = [u8; N];
 type EndMarker = U8Array<8>;
 
-const QOI_END_MARKER: EndMarker = [0, 0, 0, 0, 0, 0, 0, 1];
+pub(crate) const QOI_END_MARKER: EndMarker = [0, 0, 0, 0, 0, 0, 0, 1];
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-pub struct Pixel {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-    pub a: u8,
+/* Channels are 8 bits everywhere but in the experimental 16 bit format */
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pixel<T = u8> {
+    pub r: T,
+    pub g: T,
+    pub b: T,
+    pub a: T,
+}
+
+/* Order of the four bytes of a pixel, for consumers that expect something
+ * other than RGBA, e.g. BGRA for Windows DIBs and Cairo on little endian */
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum PixelLayout {
+    #[default]
+    Rgba,
+    Bgra,
+    Argb,
+    Abgr,
 }
 
 impl Pixel {
-    fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+    pub(crate) fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self { r, g, b, a }
     }
 
-    fn zero() -> Self {
+    pub(crate) fn zero() -> Self {
         Self { r: 0, g: 0, b: 0, a: 0 }
     }
 
-    fn hash_index(&self) -> usize {
+    pub(crate) fn hash_index(&self) -> usize {
         ( (self.r as usize) * 3
         +  (self.g as usize) * 5
         +  (self.b as usize) * 7
@@ -42,6 +56,75 @@ impl Pixel {
     pub fn to_rgba32(&self) -> u32 {
         u32::from_be_bytes([self.r, self.g, self.b, self.a])
     }
+
+    pub fn to_layout(self, layout: PixelLayout) -> [u8; 4] {
+        let Pixel { r, g, b, a } = self;
+
+        match layout {
+            PixelLayout::Rgba => [r, g, b, a],
+            PixelLayout::Bgra => [b, g, r, a],
+            PixelLayout::Argb => [a, r, g, b],
+            PixelLayout::Abgr => [a, b, g, r],
+        }
+    }
+
+    /* Color channels scaled by alpha, rounded */
+    pub fn premultiplied(self) -> Pixel {
+        let scale = |c: u8| ((c as u16 * self.a as u16 + 127) / 255) as u8;
+        Pixel::new(scale(self.r), scale(self.g), scale(self.b), self.a)
+    }
+
+    /* `self` drawn on top of `below`, the "over" operator on straight alpha */
+    pub fn over(self, below: Pixel) -> Pixel {
+        // Both alphas in 255ths, so the result is in 255ths of 255ths
+        let top = self.a as u32 * 255;
+        let rest = below.a as u32 * (255 - self.a as u32);
+        let total = top + rest;
+
+        if total == 0 {
+            return Pixel::zero();
+        }
+
+        let mix = |c: u8, d: u8| ((c as u32 * top + d as u32 * rest + total / 2) / total) as u8;
+
+        Pixel::new(
+            mix(self.r, below.r),
+            mix(self.g, below.g),
+            mix(self.b, below.b),
+            ((total + 127) / 255) as u8,
+        )
+    }
+
+    /* Adds the color of `self`, scaled by its alpha, to `below`, as light
+     * does. Alpha stays that of `below`, as with SDL's additive blending */
+    #[allow(unused)]
+    pub fn plus(self, below: Pixel) -> Pixel {
+        let Pixel { r, g, b, .. } = self.premultiplied();
+        Pixel::new(below.r.saturating_add(r), below.g.saturating_add(g), below.b.saturating_add(b), below.a)
+    }
+
+    /* Darkens `below` by the color of `self`, as far as `self` is opaque.
+     * White and transparent leave it as it is */
+    #[allow(unused)]
+    pub fn multiply(self, below: Pixel) -> Pixel {
+        let filter = self.lerp(Pixel::new(255, 255, 255, 255), 1.0 - self.a as f32 / 255.0);
+        let scale = |c: u8, d: u8| ((c as u16 * d as u16 + 127) / 255) as u8;
+
+        Pixel::new(scale(filter.r, below.r), scale(filter.g, below.g), scale(filter.b, below.b), below.a)
+    }
+
+    /* `t` of the way from `self` to `other`, alpha included */
+    pub fn lerp(self, other: Pixel, t: f32) -> Pixel {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |c: u8, d: u8| (c as f32 + (d as f32 - c as f32) * t).round() as u8;
+
+        Pixel::new(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b), mix(self.a, other.a))
+    }
+
+    /* Formats as #RRGGBBAA */
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}{:02X}", self.r, self.g, self.b, self.a)
+    }
 }
 
 pub struct PixelChannelIterator {
@@ -108,9 +191,46 @@ pub enum QOIChunk {
 
 const INVALID_CHUNK: QOIChunk = QOIChunk::Run(255);
 
+/* The kind of a chunk, without its payload */
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Op {
+    Rgb,
+    Rgba,
+    Index,
+    Diff,
+    Luma,
+    Run,
+}
+
+impl Op {
+    pub const ALL: [Op; 6] = [Op::Rgb, Op::Rgba, Op::Index, Op::Diff, Op::Luma, Op::Run];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Rgb   => "RGB",
+            Op::Rgba  => "RGBA",
+            Op::Index => "INDEX",
+            Op::Diff  => "DIFF",
+            Op::Luma  => "LUMA",
+            Op::Run   => "RUN",
+        }
+    }
+}
+
 impl QOIChunk {
+    pub fn op(&self) -> Op {
+        match self {
+            QOIChunk::ColorRGB(..)  => Op::Rgb,
+            QOIChunk::ColorRGBA(..) => Op::Rgba,
+            QOIChunk::Index(..)     => Op::Index,
+            QOIChunk::Diff(..)      => Op::Diff,
+            QOIChunk::Luma { .. }   => Op::Luma,
+            QOIChunk::Run(..)       => Op::Run,
+        }
+    }
+
     /* Number of bytes the chunk consumes */
-    const fn get_size(&self) -> usize {
+    pub const fn get_size(&self) -> usize {
         match self {
             QOIChunk::ColorRGB(..)  => 4,
             QOIChunk::ColorRGBA(..) => 5,
@@ -123,6 +243,7 @@ impl QOIChunk {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct QOIHeader {
     pub width: u32,
     pub height: u32,
@@ -130,45 +251,82 @@ pub struct QOIHeader {
     pub colorspace: u8,
 }
 
+/* Caps on how much a header may make the decoder allocate, so that crafted
+ * dimensions are rejected up front instead of exhausting memory */
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_pixels: u64,
+    pub max_memory: u64, // Bytes taken by the decoded pixels
+}
+
+impl Default for Limits {
+    /* The pixel cap of the reference implementation */
+    fn default() -> Self {
+        Self { max_pixels: 400_000_000, max_memory: u64::MAX }
+    }
+}
+
+impl Limits {
+    pub fn check(&self, width: u32, height: u32) -> Result<(), QOIError> {
+        let pixels = width as u64 * height as u64;
+        let bytes = pixels * std::mem::size_of::<Pixel>() as u64;
+
+        if pixels > self.max_pixels {
+            Err(QOIError::TooManyPixels { pixels, limit: self.max_pixels })
+        } else if bytes > self.max_memory {
+            Err(QOIError::TooMuchMemory { bytes, limit: self.max_memory })
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub struct ImageDecoder<R> {
     source: R,
     header: QOIHeader
 }
 
 impl<R: Read> ImageDecoder<R> {
-    pub fn new(mut source: R) -> Result<Self, QOIError> {
+    pub fn new(source: R) -> Result<Self, QOIError> {
+        Self::with_limits(source, Limits::default())
+    }
+
+    pub fn with_limits(mut source: R, limits: Limits) -> Result<Self, QOIError> {
         let header = Self::parse_header(&mut source)?;
+        limits.check(header.width, header.height)?;
 
         Ok(Self { source, header })
     }
 
-    fn verify_magic(buf: &[u8]) -> bool {
-        match std::str::from_utf8(buf) {
-            Ok(s) => s == "qoif",
-            Err(_) => false
-        }
-    }
-
     fn parse_header(source: &mut R) -> Result<QOIHeader, QOIError> {
+        let _span = tracing::info_span!("parse_header").entered();
+
         let mut header_bytes = [0_u8; 14];
 
-        source
-            .read_exact(&mut header_bytes[..])
-            .map_err(|err| QOIError::IO(err))
-            .and_then(|_| {
-                Self::verify_magic(&header_bytes[0..4])
-                    .then(|| QOIHeader {
-                        width: be_u32(&header_bytes[4..8]),
-                        height: be_u32(&header_bytes[8..12]),
-                        channels: header_bytes[12],
-                        colorspace: header_bytes[13],
-                    })
-                    .ok_or(QOIError::IncorrectMagic)
-            })
+        source.read_exact(&mut header_bytes[..])?;
+
+        header_from_bytes(&header_bytes)
     }
 
     pub fn chunks_iter(self) -> DecodeChunks<R> {
-        DecodeChunks::new(self)
+        DecodeChunks::new(self, Seed::default())
+    }
+
+    /* Decodes a delta frame, which has to start where the encoder started it */
+    pub fn chunks_iter_seeded(self, seed: Seed) -> DecodeChunks<R> {
+        DecodeChunks::new(self, seed)
+    }
+
+    /* Like `chunks_iter`, but for untrusted input: no more pixels than the
+     * header declares are ever produced, anything past them is an error.
+     * Every call reads at most one chunk, so decoding ends after at most
+     * width * height + 1 calls that do not return an error */
+    pub fn chunks_iter_bounded(self) -> DecodeChunks<R> {
+        let budget = self.header.width as u64 * self.header.height as u64;
+
+        let mut chunks = DecodeChunks::new(self, Seed::default());
+        chunks.budget = Some(budget);
+        chunks
     }
 
     pub fn header<'a>(&'a self) -> &'a QOIHeader {
@@ -176,10 +334,58 @@ impl<R: Read> ImageDecoder<R> {
     }
 }
 
+impl<R: Read + Seek> ImageDecoder<R> {
+    /* Seeks back to the first chunk, just past the header. The source has to
+     * start with the header, as files do */
+    pub fn rewind(&mut self) -> Result<(), QOIError> {
+        self.source
+            .seek(SeekFrom::Start(QOI_HEADER_SIZE))
+            .map_err(QOIError::IO)?;
+
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub enum QOIError {
     IO(std::io::Error),
-    IncorrectMagic
+    IncorrectMagic,
+    TooManyPixels { pixels: u64, limit: u64 },
+    TooMuchMemory { bytes: u64, limit: u64 },
+    PixelCount { expected: u64, written: u64 }, // Encoded a different number of pixels than the header declares
+}
+
+impl fmt::Display for QOIError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QOIError::IO(err) => write!(f, "{}", err),
+            QOIError::IncorrectMagic => write!(f, "not a QOI file"),
+            QOIError::TooManyPixels { pixels, limit } => {
+                write!(f, "image has {} pixels, more than the limit of {}", pixels, limit)
+            },
+            QOIError::TooMuchMemory { bytes, limit } => {
+                write!(f, "image needs {} bytes, more than the limit of {}", bytes, limit)
+            },
+            QOIError::PixelCount { expected, written } => {
+                write!(f, "header declares {} pixels, but {} were written", expected, written)
+            },
+        }
+    }
+}
+
+impl std::error::Error for QOIError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QOIError::IO(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for QOIError {
+    fn from(err: io::Error) -> Self {
+        QOIError::IO(err)
+    }
 }
 
 pub enum EvaluatedChunk {
@@ -188,10 +394,164 @@ pub enum EvaluatedChunk {
     Faulty(String)
 }
 
-const SEEN_ARRAY_SIZE: usize = 64;
+/* Where `DecodeChunks::decode_into` puts the pixels. Runs arrive in a
+ * single call, which most sinks can fill faster than pixel by pixel */
+pub trait PixelSink {
+    fn put_pixel(&mut self, px: Pixel);
+
+    fn put_run(&mut self, px: Pixel, count: usize) {
+        for _ in 0..count {
+            self.put_pixel(px);
+        }
+    }
+}
+
+impl PixelSink for Vec<Pixel> {
+    fn put_pixel(&mut self, px: Pixel) {
+        self.push(px);
+    }
+
+    fn put_run(&mut self, px: Pixel, count: usize) {
+        self.extend(std::iter::repeat_n(px, count));
+    }
+}
+
+/* Fills a slice from its start, dropping whatever does not fit */
+pub struct SliceSink<'s> {
+    pixels: &'s mut [Pixel],
+    pub written: usize, // Including dropped pixels
+}
+
+impl<'s> SliceSink<'s> {
+    pub fn new(pixels: &'s mut [Pixel]) -> Self {
+        Self { pixels, written: 0 }
+    }
+}
+
+impl PixelSink for SliceSink<'_> {
+    fn put_pixel(&mut self, px: Pixel) {
+        self.put_run(px, 1);
+    }
+
+    fn put_run(&mut self, px: Pixel, count: usize) {
+        let start = self.written.min(self.pixels.len());
+        let end = (self.written + count).min(self.pixels.len());
+
+        self.pixels[start..end].fill(px);
+        self.written += count;
+    }
+}
+
+/* Converts to bytes as the pixels arrive, three per pixel for RGB or four
+ * in the given order, optionally multiplying colors by alpha first */
+pub struct ByteSink<'b> {
+    bytes: &'b mut Vec<u8>,
+    layout: Option<PixelLayout>, // None for RGB
+    premultiply: bool,
+}
+
+impl<'b> ByteSink<'b> {
+    pub fn rgb(bytes: &'b mut Vec<u8>) -> Self {
+        Self { bytes, layout: None, premultiply: false }
+    }
+
+    pub fn with_layout(bytes: &'b mut Vec<u8>, layout: PixelLayout) -> Self {
+        Self { bytes, layout: Some(layout), premultiply: false }
+    }
+
+    pub fn premultiplied(self, premultiply: bool) -> Self {
+        Self { premultiply, ..self }
+    }
+
+    fn convert(&self, px: Pixel) -> ([u8; 4], usize) {
+        let px = if self.premultiply { px.premultiplied() } else { px };
+
+        match self.layout {
+            Some(layout) => (px.to_layout(layout), 4),
+            None => ([px.r, px.g, px.b, 0], 3),
+        }
+    }
+}
+
+impl PixelSink for ByteSink<'_> {
+    fn put_pixel(&mut self, px: Pixel) {
+        let (bytes, len) = self.convert(px);
+        self.bytes.extend_from_slice(&bytes[..len]);
+    }
+
+    fn put_run(&mut self, px: Pixel, count: usize) {
+        let (bytes, len) = self.convert(px);
+
+        for _ in 0..count {
+            self.bytes.extend_from_slice(&bytes[..len]);
+        }
+    }
+}
+
+pub(crate) const SEEN_ARRAY_SIZE: usize = 64;
+
+/* The state a QOI encoder or decoder starts an image with. Every image
+ * normally starts from the same state, delta frames start from the one the
+ * previous frame ended in, so that its colors are already in `seen` */
+#[derive(Debug, Clone)]
+pub struct Seed {
+    pub prev: Pixel,
+    pub seen: [Pixel; SEEN_ARRAY_SIZE],
+}
+
+impl Default for Seed {
+    fn default() -> Self {
+        Self { prev: Pixel::new(0, 0, 0, 255), seen: [Pixel::zero(); SEEN_ARRAY_SIZE] }
+    }
+}
+
+impl Seed {
+    /* The state after coding `pixels` from the default state. Encoder and
+     * decoder agree on it, since runs are the only pixels neither stores */
+    pub fn after(pixels: &[Pixel]) -> Self {
+        let mut seed = Self::default();
+
+        for &px in pixels {
+            if px != seed.prev {
+                seed.seen[px.hash_index()] = px;
+                seed.prev = px;
+            }
+        }
+
+        seed
+    }
+}
+
+const QOI_HEADER_SIZE: u64 = 14;
+
+/* A place decoding can resume from: where a chunk starts, the index of the
+ * first pixel it decodes to and the state of the decoder just before it */
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub pixel: u64,
+    pub offset: u64,
+    pub seed: Seed,
+}
+
+/* Everything the decoder carries from one chunk to the next, to go back to
+ * after trying something, e.g. how many bytes to skip past a faulty chunk */
+#[derive(Debug, Clone)]
+pub struct DecoderState {
+    prev: Pixel,
+    seen: [Pixel; SEEN_ARRAY_SIZE],
+    window: [u8; 8],
+    run_active: bool,
+    run_length: u8,
+    position: u64,
+    chunk: Option<QOIChunk>,
+    chunk_start: bool,
+    exhausted: bool,
+    budget: Option<u64>,
+}
 
 pub struct DecodeChunks<R> {
     decoder: ImageDecoder<R>,
+    seed: Seed, // What `prev` and `seen` started as, for rewinding
 
     prev: Pixel, // Previous pixel
     seen: [Pixel; SEEN_ARRAY_SIZE], // The QOI array of pixels 
@@ -201,24 +561,41 @@ pub struct DecodeChunks<R> {
 
     run_active: bool,
     run_length: u8,
+
+    position: u64, // Offset in the file just past the last decoded chunk
+
+    chunk: Option<QOIChunk>, // The chunk that produced `prev`, as stored in the file
+    chunk_start: bool,       // Whether `prev` was the first pixel of that chunk
+
+    exhausted: bool, // The source ran out before the end marker
+
+    // Pixels left before the header's count is reached, when decoding is bounded
+    budget: Option<u64>,
 }
 
-impl<R> DecodeChunks<R>
-where
-    R: Read
-{
-    fn new(decoder: ImageDecoder<R>) -> Self {
+impl<R> DecodeChunks<R> {
+    fn new(decoder: ImageDecoder<R>, seed: Seed) -> Self {
         Self {
             decoder: decoder,
 
-            seen: [Pixel::zero(); SEEN_ARRAY_SIZE],
-            prev: Pixel::new(0, 0, 0, 255),
+            seen: seed.seen,
+            prev: seed.prev,
+            seed,
 
             window: [0; 8],
             window_processed: 8,
 
             run_active: false,
             run_length: 0,
+
+            position: QOI_HEADER_SIZE,
+
+            chunk: None,
+            chunk_start: false,
+
+            exhausted: false,
+
+            budget: None,
         }
     }
 
@@ -252,8 +629,9 @@ where
             },
 
             /* QOI_OP_INDEX */
-            // Consective OP_INDEX's to same index are not allowed
-            x if tag_2bit(x, 0b00) && self.window[1] != x => {
+            // Consective OP_INDEX's to same index are not allowed. An INDEX(0)
+            // followed by the end marker looks the same within the window though
+            x if tag_2bit(x, 0b00) && (self.window[1] != x || self.window[1..] == QOI_END_MARKER[..7]) => {
                 // The lower 6 bits of tag contain index 
                 QOIChunk::Index(tag & 0x3F)
             },
@@ -313,38 +691,79 @@ where
                 self.prev.a
             ),
 
-            // This must be handled by the caller by repeatedly emitting previous
-            // pixel if it encounters a RUN chunk 
-            QOIChunk::Run(..) => unreachable!()
+            // Runs are handled by the caller repeatedly emitting the previous
+            // pixel, which is also what a run is
+            QOIChunk::Run(..) => self.prev
         }
     }
 
-    pub fn next_chunk(&mut self) -> EvaluatedChunk {
-        if self.run_active {
-            if self.run_length > 0 {
-                self.run_length -= 1;
-                return EvaluatedChunk::Ok(self.prev.clone());
-            }
-            else {
-                self.run_active = false;
-            }
+    /* The fault of a pixel past the budget, known before reading anything */
+    fn over_budget(&self) -> Option<EvaluatedChunk> {
+        match self.budget {
+            Some(0) if self.run_active && self.run_length > 0 => Some(EvaluatedChunk::Faulty(
+                format!("Run at byte {} goes past the last pixel", self.position - 1)
+            )),
+            _ => None,
+        }
+    }
+
+    /* Counts a decoded pixel against the budget, `at` being where its chunk started */
+    fn charge_budget(&mut self, at: u64, result: EvaluatedChunk) -> EvaluatedChunk {
+        match (&mut self.budget, result) {
+            (Some(0), EvaluatedChunk::Ok(_)) => {
+                EvaluatedChunk::Faulty(format!("Chunk at byte {} comes after the last pixel", at))
+            },
+            (Some(budget), result @ EvaluatedChunk::Ok(_)) => {
+                *budget -= 1;
+                result
+            },
+            (_, result) => result,
         }
+    }
 
+    /* The next pixel of a run that is under way, without reading */
+    fn continue_run(&mut self) -> Option<EvaluatedChunk> {
+        if !self.run_active {
+            return None;
+        }
+
+        if self.run_length > 0 {
+            self.run_length -= 1;
+            self.chunk_start = false;
+            return Some(EvaluatedChunk::Ok(self.prev.clone()));
+        }
+
+        self.run_active = false;
+        None
+    }
+
+    /* Shifts the last chunk out of the window, returning where the bytes
+     * to read in its place start */
+    fn shift_window(&mut self) -> usize {
         if self.window_processed > 0 {
             self.window.rotate_left(self.window_processed);
         }
 
-        self.decoder
-            .source
-            .read_exact(&mut self.window[(8 - self.window_processed)..])
-            .expect("Failed to read source");
+        8 - self.window_processed
+    }
 
+    fn source_ended(&mut self) -> EvaluatedChunk {
+        self.exhausted = true;
+        EvaluatedChunk::Faulty(format!("Unexpected end of data after byte {}", self.position))
+    }
+
+    /* Decodes the chunk at the start of the refilled window */
+    fn evaluate_window(&mut self) -> EvaluatedChunk {
         if &self.window[..] == &QOI_END_MARKER[..] {
             EvaluatedChunk::EndMarker
         } else {
             match self.decode_next_chunk() {
                 Some(mut chunk) => {
                     self.window_processed = chunk.get_size();
+                    self.position += self.window_processed as u64;
+
+                    self.chunk = Some(chunk.clone());
+                    self.chunk_start = true;
 
                     if let QOIChunk::Run(run_length) = &mut chunk {
                         // Un-bias the run length
@@ -359,10 +778,259 @@ where
 
                     EvaluatedChunk::Ok(self.prev.clone())
                 },
-                None => EvaluatedChunk::Faulty(format!("Unrecognized chunk"))
+                None => {
+                    self.window_processed = 0;
+                    // Every tag is recognized, only a repeated OP_INDEX is rejected
+                    EvaluatedChunk::Faulty(format!(
+                        "Consecutive QOI_OP_INDEX chunks to index {} at byte {}",
+                        self.window[0] & 0x3F, self.position
+                    ))
+                }
             }   
         }
     }
+
+    /* Hands a decoded pixel to the sink together with what is left of its
+     * run, up to `limit` pixels in all, returning how many it got */
+    fn hand_over<S: PixelSink + ?Sized>(&mut self, px: Pixel, limit: u64, sink: &mut S) -> u64 {
+        // The run is consumed here rather than one pixel per call
+        let rest = match self.run_active {
+            true => (self.run_length as u64).min(limit - 1),
+            false => 0,
+        };
+
+        self.run_length -= rest as u8;
+
+        if let Some(budget) = &mut self.budget {
+            *budget = budget.saturating_sub(rest);
+        }
+
+        match rest {
+            0 => sink.put_pixel(px),
+            _ => sink.put_run(px, 1 + rest as usize),
+        }
+
+        1 + rest
+    }
+}
+
+impl<R> DecodeChunks<R>
+where
+    R: Read
+{
+    pub fn next_chunk(&mut self) -> EvaluatedChunk {
+        if let Some(fault) = self.over_budget() {
+            return fault;
+        }
+
+        let at = self.position;
+        let result = self.decode_pixel();
+
+        self.charge_budget(at, result)
+    }
+
+    fn decode_pixel(&mut self) -> EvaluatedChunk {
+        if let Some(result) = self.continue_run() {
+            return result;
+        }
+
+        let start = self.shift_window();
+
+        if self.decoder.source.read_exact(&mut self.window[start..]).is_err() {
+            return self.source_ended();
+        }
+
+        self.evaluate_window()
+    }
+
+    /* Decodes the next `count` pixels into the sink, handing over what is
+     * left of a run at once. Fails at the first chunk that does not decode,
+     * or at an end marker that comes too early */
+    pub fn decode_into<S: PixelSink + ?Sized>(&mut self, sink: &mut S, count: u64) -> Result<(), String> {
+        let mut done = 0;
+
+        while done < count {
+            match self.next_chunk() {
+                EvaluatedChunk::Ok(px) => done += self.hand_over(px, count - done, sink),
+                EvaluatedChunk::EndMarker => return Err(format!("End marker after {} of {} pixels", done, count)),
+                EvaluatedChunk::Faulty(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /* Skips bytes from where the chunk that could not be decoded starts, or
+     * the next one, so that decoding carries on from there. Drops any run
+     * under way */
+    pub fn skip_bytes(&mut self, bytes: u64) {
+        // Bytes of the window read from the source but not decoded yet
+        let held = (8 - self.window_processed) as u64;
+
+        if bytes <= held {
+            self.window_processed += bytes as usize;
+        } else {
+            let rest = bytes - held;
+            let skipped = io::copy(&mut (&mut self.decoder.source).take(rest), &mut io::sink()).unwrap_or(0);
+
+            self.exhausted = skipped < rest;
+            self.window_processed = 8;
+        }
+
+        self.position += bytes;
+        self.run_active = false;
+    }
+}
+
+impl<R> DecodeChunks<R> {
+    pub fn header(&self) -> &QOIHeader {
+        &self.decoder.header
+    }
+
+    /* Number of bytes of the file consumed so far */
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /* The chunk that produced the last pixel. Runs keep reporting the same
+     * chunk for every pixel they repeat */
+    pub fn last_chunk(&self) -> Option<&QOIChunk> {
+        self.chunk.as_ref()
+    }
+
+    /* Whether the last pixel was the first one of its chunk */
+    pub fn starts_chunk(&self) -> bool {
+        self.chunk_start
+    }
+
+    /* Whether decoding failed because the data ended early */
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /* Raw bytes of the last chunk */
+    pub fn chunk_bytes(&self) -> &[u8] {
+        match &self.chunk {
+            Some(chunk) => &self.window[..chunk.get_size()],
+            None => &[],
+        }
+    }
+
+    pub fn snapshot(&self) -> DecoderState {
+        DecoderState {
+            prev: self.prev,
+            seen: self.seen,
+            window: self.window,
+            run_active: self.run_active,
+            run_length: self.run_length,
+            position: self.position,
+            chunk: self.chunk.clone(),
+            chunk_start: self.chunk_start,
+            exhausted: self.exhausted,
+            budget: self.budget,
+        }
+    }
+
+    /* The place the next chunk starts, to resume from later. None in the
+     * middle of a run, `pixel` being how many pixels were decoded so far */
+    pub fn checkpoint(&self, pixel: u64) -> Option<Checkpoint> {
+        if self.run_active && self.run_length > 0 {
+            return None;
+        }
+
+        Some(Checkpoint {
+            pixel,
+            offset: self.position,
+            seed: Seed { prev: self.prev, seen: self.seen },
+        })
+    }
+
+    /* Puts the decoder in the state of `at`, the source already being there */
+    fn reset(&mut self, at: &Checkpoint) {
+        self.prev = at.seed.prev;
+        self.seen = at.seed.seen;
+
+        self.window = [0; 8];
+        self.window_processed = 8;
+
+        self.run_active = false;
+        self.run_length = 0;
+
+        self.position = at.offset;
+
+        self.chunk = None;
+        self.chunk_start = false;
+
+        self.exhausted = false;
+
+        let header = &self.decoder.header;
+        self.budget = self.budget.map(|_| (header.width as u64 * header.height as u64).saturating_sub(at.pixel));
+    }
+
+    /* Whatever the source still holds. After the end marker this is any trailing data */
+    pub fn into_source(self) -> R {
+        self.decoder.source
+    }
+}
+
+impl<R> DecodeChunks<R>
+where
+    R: Read + Seek
+{
+    /* Starts decoding over from the first chunk without reopening the
+     * source, in the same state and with the same budget as at first. For
+     * decoding the same data again, as the benchmark does. Reloading in the
+     * viewer opens the file anew instead, since editors tend to replace a
+     * file rather than write over it */
+    pub fn rewind(&mut self) -> Result<(), QOIError> {
+        self.decoder.rewind()?;
+        self.reset(&Checkpoint { pixel: 0, offset: QOI_HEADER_SIZE, seed: self.seed.clone() });
+
+        Ok(())
+    }
+
+    /* Goes back to a snapshot of this decoder, reading on from where it was */
+    pub fn restore(&mut self, state: &DecoderState) -> Result<(), QOIError> {
+        self.decoder
+            .source
+            .seek(SeekFrom::Start(state.position))
+            .map_err(QOIError::IO)?;
+
+        let state = state.clone();
+
+        self.prev = state.prev;
+        self.seen = state.seen;
+
+        // Refilled from the source, the old bytes only serve `chunk_bytes`
+        self.window = state.window;
+        self.window_processed = 8;
+
+        self.run_active = state.run_active;
+        self.run_length = state.run_length;
+
+        self.position = state.position;
+
+        self.chunk = state.chunk;
+        self.chunk_start = state.chunk_start;
+
+        self.exhausted = state.exhausted;
+        self.budget = state.budget;
+
+        Ok(())
+    }
+
+    /* Carries on decoding from a checkpoint taken by an earlier pass over
+     * the same source */
+    pub fn resume(&mut self, at: &Checkpoint) -> Result<(), QOIError> {
+        self.decoder
+            .source
+            .seek(SeekFrom::Start(at.offset))
+            .map_err(QOIError::IO)?;
+
+        self.reset(at);
+
+        Ok(())
+    }
 }
 
 impl<R> Iterator for DecodeChunks<R>
@@ -380,6 +1048,129 @@ where
     }
 }
 
+/* Decodes from an `AsyncRead` such as a network upload, yielding to the
+ * executor while waiting for data instead of blocking its thread. Runs the
+ * same chunk state machine as `DecodeChunks`, only reading differs. The
+ * viewer itself reads synchronously, this is for embedding the decoder */
+#[cfg(feature = "tokio")]
+pub struct AsyncImageDecoder<R> {
+    chunks: DecodeChunks<R>,
+}
+
+#[cfg(feature = "tokio")]
+#[allow(unused)]
+impl<R> AsyncImageDecoder<R>
+where
+    R: tokio::io::AsyncRead + Unpin
+{
+    pub async fn new(source: R) -> Result<Self, QOIError> {
+        Self::with_limits(source, Limits::default()).await
+    }
+
+    pub async fn with_limits(mut source: R, limits: Limits) -> Result<Self, QOIError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut header_bytes = [0_u8; 14];
+        source.read_exact(&mut header_bytes).await.map_err(QOIError::IO)?;
+
+        let header = header_from_bytes(&header_bytes)?;
+        limits.check(header.width, header.height)?;
+
+        let decoder = ImageDecoder { source, header };
+        Ok(Self { chunks: DecodeChunks::new(decoder, Seed::default()) })
+    }
+
+    /* See `ImageDecoder::chunks_iter_bounded` */
+    pub fn bounded(mut self) -> Self {
+        let header = &self.chunks.decoder.header;
+        self.chunks.budget = Some(header.width as u64 * header.height as u64);
+        self
+    }
+
+    pub fn header(&self) -> &QOIHeader {
+        &self.chunks.decoder.header
+    }
+
+    /* See `DecodeChunks::next_chunk` */
+    pub async fn next_chunk(&mut self) -> EvaluatedChunk {
+        use tokio::io::AsyncReadExt;
+
+        let chunks = &mut self.chunks;
+
+        if let Some(fault) = chunks.over_budget() {
+            return fault;
+        }
+
+        let at = chunks.position;
+
+        let result = match chunks.continue_run() {
+            Some(result) => result,
+            None => {
+                let start = chunks.shift_window();
+
+                match chunks.decoder.source.read_exact(&mut chunks.window[start..]).await {
+                    Ok(_) => chunks.evaluate_window(),
+                    Err(_) => chunks.source_ended(),
+                }
+            },
+        };
+
+        chunks.charge_budget(at, result)
+    }
+
+    /* See `DecodeChunks::decode_into` */
+    pub async fn decode_into<S: PixelSink + ?Sized>(&mut self, sink: &mut S, count: u64) -> Result<(), String> {
+        let mut done = 0;
+
+        while done < count {
+            match self.next_chunk().await {
+                EvaluatedChunk::Ok(px) => done += self.chunks.hand_over(px, count - done, sink),
+                EvaluatedChunk::EndMarker => return Err(format!("End marker after {} of {} pixels", done, count)),
+                EvaluatedChunk::Faulty(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /* Every pixel the header declares */
+    pub async fn decode_to_vec(&mut self) -> Result<Vec<Pixel>, String> {
+        let count = self.header().width as u64 * self.header().height as u64;
+        let mut pixels = Vec::with_capacity(count as usize);
+
+        self.decode_into(&mut pixels, count).await?;
+        Ok(pixels)
+    }
+}
+
+fn verify_magic(buf: &[u8]) -> bool {
+    match std::str::from_utf8(buf) {
+        Ok(s) => s == "qoif",
+        Err(_) => false
+    }
+}
+
+/* Shared by the sync and async decoders, which only read the bytes differently */
+fn header_from_bytes(header_bytes: &[u8; 14]) -> Result<QOIHeader, QOIError> {
+    let header = verify_magic(&header_bytes[0..4])
+        .then(|| QOIHeader {
+            width: be_u32(&header_bytes[4..8]),
+            height: be_u32(&header_bytes[8..12]),
+            channels: header_bytes[12],
+            colorspace: header_bytes[13],
+        })
+        .ok_or(QOIError::IncorrectMagic)?;
+
+    // Purely informative, so decoding goes on regardless
+    if header.colorspace > 1 {
+        tracing::warn!("Unknown colorspace byte {}, expected 0 or 1", header.colorspace);
+    }
+
+    tracing::debug!("Header: {}x{}, {} channels, colorspace {}", header.width, header.height, header.channels, header.colorspace);
+
+    Ok(header)
+}
+
 fn be_u32(bytes: &[u8]) -> u32 {
     u32::from_be_bytes(bytes.try_into().unwrap())
 }
@@ -387,4 +1178,60 @@ fn be_u32(bytes: &[u8]) -> u32 {
 fn tag_2bit(x: u8, tag: u8) -> bool {
     const MASK: u8 = 0b_11_00_00_00_u8;
     (x & MASK) >> 6 == tag
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::encoder::ImageEncoder;
+
+    fn encoded(count: u32) -> (Vec<u8>, Vec<Pixel>) {
+        let pixels = (0..count * count)
+            .map(|i| match i % 11 {
+                0..=4 => Pixel::new(9, 9, 9, 255),
+                5 => Pixel::new(i as u8, 0, 0, 255),
+                _ => Pixel::new((i / 3) as u8, (i / 5) as u8, 40, (i % 3) as u8 * 100),
+            })
+            .collect::<Vec<_>>();
+
+        let header = QOIHeader { width: count, height: count, channels: 4, colorspace: 0 };
+        let bytes = ImageEncoder::new(Vec::new(), &header).encode_from_iter(pixels.iter().copied()).unwrap();
+
+        (bytes, pixels)
+    }
+
+    #[test]
+    fn rewind_decodes_again() {
+        let (bytes, pixels) = encoded(32);
+        let mut chunks = ImageDecoder::new(Cursor::new(bytes)).unwrap().chunks_iter();
+
+        for _ in 0..2 {
+            let mut decoded = Vec::new();
+            chunks.decode_into(&mut decoded, pixels.len() as u64).unwrap();
+            assert_eq!(decoded, pixels);
+
+            chunks.rewind().unwrap();
+        }
+    }
+
+    #[test]
+    fn restore_goes_back_to_snapshot() {
+        let (bytes, pixels) = encoded(32);
+        let mut chunks = ImageDecoder::new(Cursor::new(bytes)).unwrap().chunks_iter();
+
+        let mut decoded = Vec::new();
+        chunks.decode_into(&mut decoded, 100).unwrap();
+        let state = chunks.snapshot();
+
+        let mut rest = Vec::new();
+        chunks.decode_into(&mut rest, pixels.len() as u64 - 100).unwrap();
+
+        chunks.restore(&state).unwrap();
+        let mut again = Vec::new();
+        chunks.decode_into(&mut again, pixels.len() as u64 - 100).unwrap();
+
+        assert_eq!(rest, again);
+        assert_eq!(&again[..], &pixels[100..]);
+    }
+}
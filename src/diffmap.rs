@@ -0,0 +1,111 @@
+use crate::bitmap::Bitmap;
+use crate::decoder::Pixel;
+
+pub const DEFAULT_GAIN: f32 = 8.0;
+
+pub const LEGEND: &str = "same=black, then red, yellow and white as the difference grows";
+
+// Opacity the compared image is faded to in the onion skin
+const ONION_OPACITY: f32 = 0.5;
+
+/* What replaces the compared image, when anything does */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Comparison {
+    Heatmap,
+    OnionSkin, // Both images on top of each other, for spotting shifts
+}
+
+impl Comparison {
+    pub fn build(self, a: &Bitmap, b: &Bitmap, gain: f32) -> Bitmap {
+        match self {
+            Comparison::Heatmap => heatmap(a, b, gain),
+            Comparison::OnionSkin => onion_skin(a, b),
+        }
+    }
+
+    pub fn label(self, gain: f32) -> String {
+        match self {
+            Comparison::Heatmap => format!("difference x{}: {}", gain, LEGEND),
+            Comparison::OnionSkin => "onion skin".to_string(),
+        }
+    }
+}
+
+/* Maps a difference of 0 to 255 onto black, red, yellow and white */
+fn heat_color(d: u8) -> Pixel {
+    let d = d as u32 * 3;
+
+    let r = d.min(255);
+    let g = d.saturating_sub(255).min(255);
+    let b = d.saturating_sub(510).min(255);
+
+    Pixel::new(r as u8, g as u8, b as u8, 255)
+}
+
+/* Colors every pixel by the largest channel difference between the two
+ * images, multiplied by `gain` so that small errors stand out. Wherever
+ * only one of the images has pixels counts as the largest difference */
+pub fn heatmap(a: &Bitmap, b: &Bitmap, gain: f32) -> Bitmap {
+    let (width, height) = (a.width.max(b.width), a.height.max(b.height));
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let d = match (a.pixel_at(x, y), b.pixel_at(x, y)) {
+                (Some(p), Some(q)) => {
+                    let d = p.r.abs_diff(q.r)
+                        .max(p.g.abs_diff(q.g))
+                        .max(p.b.abs_diff(q.b))
+                        .max(p.a.abs_diff(q.a));
+
+                    (d as f32 * gain).min(255.0) as u8
+                },
+                _ => 255,
+            };
+
+            pixels.push(heat_color(d));
+        }
+    }
+
+    Bitmap {
+        width,
+        height,
+        channels: 3,
+        colorspace: 0,
+        pixels,
+        recovered: 0,
+        stopped: None,
+    }
+}
+
+/* The second image faded and drawn over the first. Wherever only one of
+ * them has pixels, those show as they are */
+pub fn onion_skin(a: &Bitmap, b: &Bitmap) -> Bitmap {
+    let (width, height) = (a.width.max(b.width), a.height.max(b.height));
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let px = match (a.pixel_at(x, y), b.pixel_at(x, y)) {
+                (Some(p), Some(q)) => {
+                    let faded = Pixel { a: (q.a as f32 * ONION_OPACITY).round() as u8, ..q };
+                    faded.over(p)
+                },
+                (Some(p), None) | (None, Some(p)) => p,
+                (None, None) => Pixel::zero(),
+            };
+
+            pixels.push(px);
+        }
+    }
+
+    Bitmap {
+        width,
+        height,
+        channels: if a.has_alpha() || b.has_alpha() { 4 } else { 3 },
+        colorspace: a.colorspace,
+        pixels,
+        recovered: 0,
+        stopped: None,
+    }
+}
@@ -0,0 +1,53 @@
+use sdl2::rect::Rect;
+use sdl2::sys::SDL_WindowFlags;
+use sdl2::video::{FullscreenType, Window, WindowPos};
+use sdl2::VideoSubsystem;
+
+/* Monitors are numbered from 1, the primary one, as --monitor takes them,
+ * while SDL counts its displays from 0 */
+pub fn bounds(video: &VideoSubsystem, monitor: u32) -> Result<Rect, String> {
+    let count = video.num_video_displays()?;
+
+    if monitor == 0 || monitor as i32 > count {
+        return Err(format!("No monitor {}, there are {}", monitor, count));
+    }
+
+    video.display_bounds(monitor as i32 - 1)
+}
+
+/* The top left corner at which a window of `size` is centered on `bounds` */
+pub fn centered(bounds: Rect, size: (u32, u32)) -> (i32, i32) {
+    let center = bounds.center();
+    (center.x() - size.0 as i32 / 2, center.y() - size.1 as i32 / 2)
+}
+
+/* Moves the window on to the monitor after the one it is on, wrapping
+ * around, and keeps it fullscreen or maximized if it was. Returns the
+ * monitor it is on now and how many there are */
+pub fn move_to_next(window: &mut Window) -> Result<(u32, u32), String> {
+    let count = window.subsystem().num_video_displays()?;
+    let next = (window.display_index()? + 1) % count;
+    let bounds = window.subsystem().display_bounds(next)?;
+
+    // Windows only move between monitors while neither of these
+    let fullscreen = window.fullscreen_state();
+    let maximized = window.window_flags() & SDL_WindowFlags::SDL_WINDOW_MAXIMIZED as u32 != 0;
+
+    window.set_fullscreen(FullscreenType::Off)?;
+    window.restore();
+
+    let (width, height) = window.size();
+    let size = (width.min(bounds.width()), height.min(bounds.height()));
+    let (x, y) = centered(bounds, size);
+
+    window.set_size(size.0, size.1).map_err(|err| err.to_string())?;
+    window.set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+
+    if maximized {
+        window.maximize();
+    }
+
+    window.set_fullscreen(fullscreen)?;
+
+    Ok((next as u32 + 1, count as u32))
+}
@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, TextureAccess, TextureCreator};
+
+use crate::bitmap::Bitmap;
+use crate::filter::Filter;
+use crate::gamut;
+use crate::histogram::Histogram;
+use crate::opmap;
+
+/* The image currently on display, together with everything derived from it */
+pub struct Document<'a> {
+    pub path: PathBuf,
+    pub bitmap: Arc<Bitmap>, // Shared with the image cache
+    pub texture: Texture<'a>,
+    histogram: Histogram, // Of `bitmap`, see `histogram()`
+
+    // Pixels colored by the chunk op that produced them, built the first time it is shown
+    op_view: Option<Result<Texture<'a>, String>>,
+
+    // The image run through the filter it was last filtered with, and its histogram
+    filtered: Option<(Arc<dyn Filter>, Texture<'a>, Histogram)>,
+
+    // The image halved again and again, so that far zoomed out views do not
+    // shimmer. Built the first time it is drawn at less than half its size
+    mips: Vec<Texture<'a>>,
+
+    // Size of the full image while `bitmap` is only a subsampled preview of it
+    full_size: Option<(u32, u32)>,
+
+    // Set for images that did not come from `path`, e.g. pasted ones
+    pub unsaved: bool,
+}
+
+impl<'a> Document<'a> {
+    pub fn new<T: 'a>(crt: &'a TextureCreator<T>, path: PathBuf, bitmap: Arc<Bitmap>) -> Result<Self, String> {
+        let texture = gen_texture(crt, &bitmap)?;
+        let histogram = Histogram::compute(&bitmap);
+
+        Ok(Self { path, bitmap, texture, histogram, op_view: None, filtered: None, mips: Vec::new(), full_size: None, unsaved: false })
+    }
+
+    /* Stands in for the image of `full_size` until it is decoded, see `replace_bitmap` */
+    pub fn preview<T: 'a>(crt: &'a TextureCreator<T>, path: PathBuf, preview: Bitmap, full_size: (u32, u32)) -> Result<Self, String> {
+        let mut doc = Self::new(crt, path, Arc::new(preview))?;
+        doc.full_size = Some(full_size);
+
+        Ok(doc)
+    }
+
+    pub fn is_preview(&self) -> bool {
+        self.full_size.is_some()
+    }
+
+    /* Size the image is laid out at, which a preview shares with the full image */
+    pub fn size(&self) -> (u32, u32) {
+        self.full_size.unwrap_or(self.bitmap.size())
+    }
+
+    /* Builds the op view unless that was already tried, returning why it is unavailable */
+    pub fn build_op_view<T: 'a>(&mut self, crt: &'a TextureCreator<T>) -> Result<(), String> {
+        let (path, unsaved) = (&self.path, self.unsaved);
+
+        self.op_view
+            .get_or_insert_with(|| {
+                if unsaved || !is_qoi(path) {
+                    return Err("op view is only available for QOI files".to_string());
+                }
+
+                opmap::op_bitmap(path).and_then(|bitmap| gen_texture(crt, &bitmap))
+            })
+            .as_ref()
+            .map(|_| ())
+            .map_err(|err| err.clone())
+    }
+
+    /* Runs the image through `filter`, unless it already is, or drops the
+     * filtered image for None */
+    pub fn filter<T: 'a>(&mut self, crt: &'a TextureCreator<T>, filter: Option<&Arc<dyn Filter>>) -> Result<(), String> {
+        let Some(filter) = filter else {
+            self.filtered = None;
+            return Ok(());
+        };
+
+        if self.filtered.as_ref().is_some_and(|(current, _, _)| Arc::ptr_eq(current, filter)) {
+            return Ok(());
+        }
+
+        self.filtered = None;
+
+        let bitmap = filter.transform(&self.bitmap)?;
+        let texture = gen_texture(crt, &bitmap)?;
+        self.filtered = Some((filter.clone(), texture, Histogram::compute(&bitmap)));
+
+        Ok(())
+    }
+
+    /* The histogram of what is on display, the filtered image if there is one */
+    pub fn histogram(&self) -> &Histogram {
+        match &self.filtered {
+            Some((_, _, histogram)) => histogram,
+            None => &self.histogram,
+        }
+    }
+
+    /* Builds the mip chain if drawing at `zoom` needs it. Levels that fail to
+     * upload end the chain early, the largest ones still help */
+    pub fn build_mips<T: 'a>(&mut self, crt: &'a TextureCreator<T>, zoom: f32) {
+        if zoom >= 0.5 || !self.mips.is_empty() || self.is_preview() {
+            return;
+        }
+
+        let mut level = self.bitmap.fit_within((self.bitmap.width / 2).max(1), (self.bitmap.height / 2).max(1));
+
+        while let Ok(texture) = gen_texture(crt, &level) {
+            self.mips.push(texture);
+
+            if level.width == 1 && level.height == 1 {
+                break;
+            }
+
+            level = level.fit_within((level.width / 2).max(1), (level.height / 2).max(1));
+        }
+    }
+
+    /* The op view if requested and built, then the filtered image if any, then
+     * the smallest mip level still at least as large as the image appears at
+     * `zoom`, the image otherwise */
+    pub fn display_texture(&self, ops: bool, zoom: f32) -> &Texture<'a> {
+        // Halvings of the size the image is drawn at, the float cast
+        // saturates to 0 when zoomed in
+        let level = match self.is_preview() {
+            true => 0,
+            false => (1.0 / zoom).log2().floor() as usize,
+        };
+
+        match (&self.op_view, &self.filtered) {
+            (Some(Ok(texture)), _) if ops => texture,
+            (_, Some((_, texture, _))) => texture,
+            _ if level > 0 => self.mips.get(level - 1).or(self.mips.last()).unwrap_or(&self.texture),
+            _ => &self.texture,
+        }
+    }
+
+    /* Swaps in new pixels for the same path, which also ends a preview. The
+     * texture is reused when the dimensions and format did not change */
+    pub fn replace_bitmap<T: 'a>(&mut self, crt: &'a TextureCreator<T>, bitmap: Arc<Bitmap>) -> Result<(), String> {
+        let query = self.texture.query();
+        let same_shape = query.width == bitmap.width
+            && query.height == bitmap.height
+            && self.bitmap.channels == bitmap.channels;
+
+        if same_shape {
+            upload(&mut self.texture, &bitmap);
+        } else {
+            self.texture = gen_texture(crt, &bitmap)?;
+        }
+
+        self.histogram = Histogram::compute(&bitmap);
+        self.op_view = None;
+        self.filtered = None;
+        self.mips.clear();
+        self.full_size = None;
+        self.bitmap = bitmap;
+
+        Ok(())
+    }
+}
+
+fn is_qoi(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"))
+}
+
+/* Fails when the renderer is out of memory or the image is larger than its
+ * maximum texture size. Opaque images get 10 bits per channel with deep
+ * color, unless the renderer has no such textures */
+pub fn gen_texture<'a, T: 'a>(crt: &'a TextureCreator<T>, bitmap: &Bitmap) -> Result<Texture<'a>, String> {
+    let format = if bitmap.has_alpha() {
+        PixelFormatEnum::RGBA32
+    } else {
+        PixelFormatEnum::RGB24
+    };
+
+    let create = |format| crt.create_texture(format, TextureAccess::Streaming, bitmap.width, bitmap.height);
+
+    let created = match gamut::output().deep_color && !bitmap.has_alpha() {
+        true => create(PixelFormatEnum::ARGB2101010).or_else(|_| create(format)),
+        false => create(format),
+    };
+
+    let mut tex = created
+        .map_err(|err| format!("Failed to create a {}x{} texture: {}", bitmap.width, bitmap.height, err))?;
+
+    upload(&mut tex, bitmap);
+
+    Ok(tex)
+}
+
+/* Converts straight into the locked texture, whose rows may be padded */
+fn upload(texture: &mut Texture, bitmap: &Bitmap) {
+    let _span = tracing::info_span!("texture_upload", width = bitmap.width, height = bitmap.height).entered();
+
+    let gamut = gamut::output().gamut;
+
+    let written = match texture.query().format {
+        PixelFormatEnum::ARGB2101010 => {
+            texture.with_lock(None, |buf, stride| gamut::write_deep(bitmap, buf, stride, gamut))
+        },
+        _ => {
+            let converted = gamut::to_output(bitmap, gamut);
+            let converted = converted.as_ref().unwrap_or(bitmap);
+
+            texture.with_lock(None, |buf, stride| converted.write_into(buf, stride))
+        },
+    };
+
+    written.and_then(|written| written).unwrap();
+}
@@ -0,0 +1,324 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use crate::archive;
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::decoder::Pixel;
+use crate::encoder::{self, ImageEncoder, Optimized};
+use crate::filter::Kernel;
+use crate::input;
+use crate::metadata::Metadata;
+#[cfg(feature = "qoi16")]
+use crate::qoi16;
+use crate::resample::Resampler;
+use crate::stats::ChunkStats;
+
+/* The format encode writes */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    #[default]
+    Qoi,
+    Qoi16, // The experimental, non-standard 16 bit format of qoi16.rs
+    Png,
+}
+
+impl Target {
+    fn extension(self) -> &'static str {
+        match self {
+            Target::Qoi => "qoi",
+            Target::Qoi16 => "qoi16",
+            Target::Png => "png",
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "qoi" => Ok(Target::Qoi),
+            "qoi16" => Ok(Target::Qoi16),
+            "png" => Ok(Target::Png),
+            _ => Err(format!("Unknown format to convert to: {}", s)),
+        }
+    }
+}
+
+/* Knobs of the `encode` command */
+#[derive(Debug, Default)]
+pub struct EncodeOptions {
+    pub output: Option<PathBuf>, // Only with a single input
+    pub out_dir: Option<PathBuf>, // Where outputs go instead of next to their inputs
+    pub target: Target,
+    pub stats: bool, // Print where the bytes of each file went
+    pub reduce_channels: bool, // Write RGBA images without any transparency as RGB
+    pub approx: Option<u8>, // Bits kept of each color channel, lossy
+    pub optimize: bool, // Search for a smaller encoding than the greedy one
+    pub kernel: Option<Kernel>, // Convolved into the image before anything else
+    pub resize: Option<(u32, u32)>, // Exact size to scale to, after filtering
+    pub resampler: Resampler,
+    pub gray: bool, // Write 1 or 2 channels, an extension for height maps and masks
+    pub metadata: Metadata, // Appended after the end marker unless empty
+}
+
+/* Rounds a channel to the nearest of 2^bits evenly spaced levels, still
+ * spanning 0 to 255. Neighbouring pixels then differ less and repeat more,
+ * so more of them fit DIFF, LUMA, INDEX and RUN ops */
+fn quantize(value: u8, bits: u8) -> u8 {
+    let levels = (1_u32 << bits) - 1;
+    let level = (value as u32 * levels + 127) / 255;
+
+    ((level * 255 + levels / 2) / levels) as u8
+}
+
+/* Alpha is left alone, so that quantizing never changes what is transparent */
+fn approximate(px: Pixel, bits: u8) -> Pixel {
+    Pixel::new(quantize(px.r, bits), quantize(px.g, bits), quantize(px.b, bits), px.a)
+}
+
+/* Next to the input with the extension of the target, unless that is the
+ * input itself or the input has no place on disk. Under --out-dir, inputs
+ * keep where they are below `base`, the directory all of them share */
+fn output_path(path: &Path, base: &Path, options: &EncodeOptions) -> Result<PathBuf, String> {
+    if let Some(out) = &options.output {
+        return Ok(out.clone());
+    }
+
+    let out = match &options.out_dir {
+        Some(dir) if !input::is_stdin(path) => {
+            let relative = path.strip_prefix(base).unwrap_or(path);
+
+            match relative.has_root() {
+                true => dir.join(relative.file_name().unwrap_or_default()),
+                false => dir.join(relative),
+            }
+        },
+        _ if input::is_stdin(path) || archive::is_entry(path) => {
+            return Err(format!("\"{}\" needs --output or --out-dir to be encoded", path.display()));
+        },
+        _ => path.to_path_buf(),
+    };
+
+    let out = out.with_extension(options.target.extension());
+
+    match out == path {
+        true => Err(format!("Refusing to encode \"{}\" over itself, pass --output", path.display())),
+        false => Ok(out),
+    }
+}
+
+#[cfg(feature = "qoi16")]
+fn encode_wide(path: &Path, out: &Path, decode: &LoadOptions) -> Result<(ChunkStats, Option<Optimized>), String> {
+    let (header, size) = qoi16::encode_file(path, out, decode)?;
+
+    Ok((ChunkStats::from_encoder(header, size, encoder::EncodeStats::default()), None))
+}
+
+#[cfg(not(feature = "qoi16"))]
+fn encode_wide(_path: &Path, _out: &Path, _decode: &LoadOptions) -> Result<(ChunkStats, Option<Optimized>), String> {
+    Err("Built without the qoi16 feature, rebuild with `--features qoi16`".to_string())
+}
+
+/* Encodes one file, returning its statistics and what --optimize found.
+ * With `parallel` the image is split into bands encoded on all cores, without
+ * it everything happens on the calling thread, for when every core already
+ * has a file of its own */
+pub fn encode_file(
+    path: &Path,
+    out: &Path,
+    options: &EncodeOptions,
+    decode: &LoadOptions,
+    parallel: bool,
+) -> Result<(ChunkStats, Option<Optimized>), String> {
+    if options.target == Target::Qoi16 {
+        return encode_wide(path, out, decode);
+    }
+
+    let mut bitmap = Bitmap::load(path.to_str().unwrap_or_default(), decode)?;
+
+    if let Some(stop) = &bitmap.stopped {
+        return Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message));
+    }
+
+    if let Some(kernel) = &options.kernel {
+        bitmap = kernel.apply(&bitmap);
+    }
+
+    if let Some((width, height)) = options.resize {
+        bitmap = options.resampler.resize(&bitmap, width, height);
+    }
+
+    if options.gray {
+        bitmap = bitmap.to_gray();
+    }
+
+    // Decoders then hand out 3 bytes a pixel, or 1 for gray. Every alpha is
+    // already the 255 the encoder starts from, so no RGBA ops are saved on
+    // top of that
+    if options.reduce_channels && bitmap.has_alpha() && bitmap.pixels.iter().all(|p| p.a == 255) {
+        bitmap.channels -= 1;
+    }
+
+    if let Some(bits) = options.approx {
+        bitmap.pixels.iter_mut().for_each(|px| *px = approximate(*px, bits));
+    }
+
+    let header = bitmap.header();
+    let write_error = |err: &dyn std::fmt::Display| format!("Failed to write \"{}\": {}", out.display(), err);
+
+    if options.target == Target::Png {
+        bitmap.save(out).map_err(|err| write_error(&err))?;
+        let size = std::fs::metadata(out).map_err(|err| write_error(&err))?.len();
+
+        return Ok((ChunkStats::from_encoder(header, size, encoder::EncodeStats::default()), None));
+    }
+
+    let (mut bytes, stats, optimized) = match (options.optimize, parallel) {
+        (true, _) => encoder::encode_optimized(&header, &bitmap.pixels, parallel)
+            .map(|(bytes, stats, found)| (bytes, stats, Some(found))),
+        (false, true) => encoder::encode_parallel(&header, &bitmap.pixels).map(|(bytes, stats)| (bytes, stats, None)),
+        (false, false) => {
+            let mut enc = ImageEncoder::new(Vec::new(), &header);
+
+            bitmap.pixels
+                .iter()
+                .try_for_each(|&px| enc.encode_pixel(px))
+                .and_then(|_| enc.finish_with_stats())
+                .map(|(bytes, stats)| (bytes, stats, None))
+        },
+    }
+    .map_err(|err| write_error(&err))?;
+
+    if !options.metadata.is_empty() {
+        bytes.extend(options.metadata.to_bytes());
+    }
+    std::fs::write(out, &bytes).map_err(|err| write_error(&err))?;
+
+    Ok((ChunkStats::from_encoder(header, bytes.len() as u64, stats), optimized))
+}
+
+/* The deepest directory that all the paths are in */
+fn common_dir(paths: &[&Path]) -> PathBuf {
+    let mut base = paths.first().and_then(|path| path.parent()).map(Path::to_path_buf).unwrap_or_default();
+
+    for path in paths {
+        while !path.starts_with(&base) && base.pop() {}
+    }
+
+    base
+}
+
+/* Writes the input to `out`, making the directories it goes in first */
+fn convert(
+    path: &Path,
+    base: &Path,
+    options: &EncodeOptions,
+    decode: &LoadOptions,
+    parallel: bool,
+) -> Result<(PathBuf, ChunkStats, Option<Optimized>), String> {
+    let out = output_path(path, base, options)?;
+
+    if let Some(dir) = out.parent().filter(|dir| options.out_dir.is_some() && !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|err| format!("Failed to create \"{}\": {}", dir.display(), err))?;
+    }
+
+    encode_file(path, &out, options, decode, parallel).map(|(stats, optimized)| (out, stats, optimized))
+}
+
+/* Encodes every file, several at a time, returning whether all of them
+ * succeeded. Each file is reported as it is done, and the sizes before and
+ * after are summed up at the end */
+pub fn encode_all<'p>(paths: impl Iterator<Item = &'p Path>, options: &EncodeOptions, decode: &LoadOptions) -> bool {
+    let paths = paths.collect::<Vec<_>>();
+
+    if options.output.is_some() && paths.len() != 1 {
+        eprintln!("--output needs exactly one image to encode, got {}", paths.len());
+        return false;
+    }
+
+    let knobs = options.stats || options.reduce_channels || options.approx.is_some()
+        || options.kernel.is_some() || options.resize.is_some() || options.gray || !options.metadata.is_empty()
+        || options.optimize;
+
+    if options.target == Target::Qoi16 && knobs {
+        eprintln!("--qoi16 does not combine with --stats, --reduce-channels, --approx, --kernel, --resize, --gray, --meta or --optimize");
+        return false;
+    }
+
+    if options.target == Target::Png && (options.stats || !options.metadata.is_empty() || options.optimize) {
+        eprintln!("--to png does not combine with --stats, --meta or --optimize");
+        return false;
+    }
+
+    let base = common_dir(&paths);
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    let workers = cores.min(paths.len());
+
+    // Bands only pay off while some cores have no file to work on, with more
+    // files than cores they would just pile threads on top of the workers
+    let parallel = paths.len() < cores;
+
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let size_before = AtomicU64::new(0);
+    let size_after = AtomicU64::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(&path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = convert(path, &base, options, decode, parallel);
+                    let progress = format!("[{}/{}]", done.fetch_add(1, Ordering::Relaxed) + 1, paths.len());
+
+                    match result {
+                        Ok((out, stats, optimized)) => {
+                            let mut report = format!(
+                                "{} {} -> {}: {} bytes, {} channels",
+                                progress, path.display(), out.display(), stats.file_size, stats.header.channels
+                            );
+
+                            if options.stats {
+                                report = format!("{}\n{}", report, stats.describe());
+                            }
+
+                            if let Some(optimized) = optimized {
+                                report = format!("{}\n{}", report, optimized.describe());
+                            }
+
+                            println!("{}", report);
+
+                            // Inputs of unknown size are left out of the totals on both sides
+                            if let Ok(size) = input::size(path) {
+                                size_before.fetch_add(size, Ordering::Relaxed);
+                                size_after.fetch_add(stats.file_size, Ordering::Relaxed);
+                            }
+                        },
+                        Err(err) => {
+                            eprintln!("{} {}", progress, err);
+                            failed.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner();
+
+    if paths.len() > 1 {
+        let (before, after) = (size_before.into_inner(), size_after.into_inner());
+        let change = 100.0 * (after as f64 / before.max(1) as f64 - 1.0);
+
+        println!(
+            "Converted {} of {} files, {} bytes to {} bytes, {:.1}% {}",
+            paths.len() - failed, paths.len(), before, after, change.abs(),
+            if change > 0.0 { "larger" } else { "smaller" }
+        );
+    }
+
+    failed == 0
+}
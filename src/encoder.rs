@@ -0,0 +1,674 @@
+use std::io::Write;
+use std::thread;
+
+use crate::decoder::{Pixel, QOIChunk, QOIError, QOIHeader, Seed, QOI_END_MARKER, SEEN_ARRAY_SIZE};
+
+const MAX_RUN_LENGTH: u8 = 62;
+
+// Smaller bands are not worth a thread of their own
+const MIN_BAND_PIXELS: usize = 1 << 18;
+
+// Pixels the optimize pass weighs choices across at once
+const SEARCH_WINDOW: usize = 4096;
+
+// Run lengths a pixel can leave open, 0 being none
+const RUN_STATES: usize = MAX_RUN_LENGTH as usize + 1;
+
+/* What the encoder emitted, indexed like `Op::ALL` */
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodeStats {
+    pub chunks: [u64; 6],
+    pub bytes: [u64; 6],
+    pub pixels: [u64; 6],
+}
+
+/* Encodes straight into the sink as pixels come in, so only the previous
+ * pixel and the seen array are ever held, however large the image. The
+ * header is written first, then pixels are fed one at a time with
+ * `encode_pixel` or in slices of raw bytes with `write_pixels`, in any mix,
+ * and `finish` ends the stream */
+pub struct ImageEncoder<W: Write> {
+    sink: W,
+    header: QOIHeader,
+    header_written: bool,
+
+    prev: Pixel, // Previous pixel
+    seen: [Pixel; SEEN_ARRAY_SIZE], // The QOI array of pixels
+
+    // What the decoder is known to hold at this point. Bands encoded on
+    // their own know neither until they have written them themselves
+    prev_known: bool,
+    seen_known: u64, // One bit per entry of `seen`
+
+    run_length: u8,
+
+    written: u64, // Pixels so far, checked against the header
+    partial: Vec<u8>, // Bytes of a pixel split between calls to `write_pixels`
+
+    stats: EncodeStats,
+}
+
+impl<W: Write> ImageEncoder<W> {
+    pub fn new(sink: W, header: &QOIHeader) -> Self {
+        Self::seeded(sink, header, Seed::default())
+    }
+
+    /* Encodes a delta frame, which only decodes when started from the same seed */
+    pub fn seeded(sink: W, header: &QOIHeader, seed: Seed) -> Self {
+        Self {
+            sink,
+            header: header.clone(),
+            header_written: false,
+
+            prev: seed.prev,
+            seen: seed.seen,
+
+            prev_known: true,
+            seen_known: u64::MAX,
+
+            run_length: 0,
+
+            written: 0,
+            partial: Vec::with_capacity(4),
+
+            stats: EncodeStats::default(),
+        }
+    }
+
+    /* Also done by the first pixel if not called before */
+    pub fn write_header(&mut self) -> Result<(), QOIError> {
+        if self.header_written {
+            return Ok(());
+        }
+
+        let header = &self.header;
+
+        self.sink.write_all(b"qoif")
+            .and_then(|_| self.sink.write_all(&header.width.to_be_bytes()))
+            .and_then(|_| self.sink.write_all(&header.height.to_be_bytes()))
+            .and_then(|_| self.sink.write_all(&[header.channels, header.colorspace]))
+            .map_err(QOIError::IO)?;
+
+        self.header_written = true;
+        Ok(())
+    }
+
+    /* Chunks for a band of `header.height` rows, with no header of its own and
+     * no end marker. Bands after the first are stitched after others, so they
+     * start from whatever state those left the decoder in */
+    fn band(sink: W, header: &QOIHeader, first: bool) -> Self {
+        Self {
+            header_written: true,
+            prev_known: first,
+            seen_known: if first { u64::MAX } else { 0 },
+            ..Self::new(sink, header)
+        }
+    }
+
+    fn expected_pixels(&self) -> u64 {
+        self.header.width as u64 * self.header.height as u64
+    }
+
+    /* The smallest op that fits. After any op the decoder holds the pixel as
+     * `prev` and in its seen entry, so within a band the choice here does not
+     * change what later pixels can be coded with. Bands encoded on their own
+     * do cost bytes though, which `encode_optimized` weighs */
+    fn next_chunk(&mut self, px: Pixel) -> QOIChunk {
+        let index = px.hash_index();
+
+        if self.seen[index] == px && self.seen_known & 1 << index != 0 {
+            return QOIChunk::Index(index as u8);
+        }
+
+        self.seen[index] = px;
+        self.seen_known |= 1 << index;
+
+        if px.a != self.prev.a {
+            return QOIChunk::ColorRGBA(px);
+        }
+
+        // Differences are stored wrapped, same as the decoder produces them
+        let dr = px.r.wrapping_sub(self.prev.r);
+        let dg = px.g.wrapping_sub(self.prev.g);
+        let db = px.b.wrapping_sub(self.prev.b);
+
+        let drdg = dr.wrapping_sub(dg);
+        let dbdg = db.wrapping_sub(dg);
+
+        let within = |d: u8, lo: i8, hi: i8| (lo..=hi).contains(&(d as i8));
+
+        if within(dr, -2, 1) && within(dg, -2, 1) && within(db, -2, 1) {
+            QOIChunk::Diff(dr, dg, db)
+        } else if within(dg, -32, 31) && within(drdg, -8, 7) && within(dbdg, -8, 7) {
+            QOIChunk::Luma { diff_green: dg, drdg, dbdg }
+        } else {
+            QOIChunk::ColorRGB(px)
+        }
+    }
+
+    /* Every op that can code `px` next, given what the decoder holds. Runs
+     * are left to the caller */
+    fn candidates(&self, px: Pixel) -> Vec<QOIChunk> {
+        let mut ops = vec![QOIChunk::ColorRGBA(px)];
+
+        if !self.prev_known {
+            return ops;
+        }
+
+        let index = px.hash_index();
+
+        // Two indexes to the same entry in a row are rejected as a broken
+        // run, and a pixel repeating the last one has a run or DIFF as small
+        if self.seen[index] == px && self.seen_known & 1 << index != 0 && px != self.prev {
+            ops.push(QOIChunk::Index(index as u8));
+        }
+
+        if px.a == self.prev.a {
+            let dr = px.r.wrapping_sub(self.prev.r);
+            let dg = px.g.wrapping_sub(self.prev.g);
+            let db = px.b.wrapping_sub(self.prev.b);
+
+            let drdg = dr.wrapping_sub(dg);
+            let dbdg = db.wrapping_sub(dg);
+
+            let within = |d: u8, lo: i8, hi: i8| (lo..=hi).contains(&(d as i8));
+
+            ops.push(QOIChunk::ColorRGB(px));
+
+            if within(dr, -2, 1) && within(dg, -2, 1) && within(db, -2, 1) {
+                ops.push(QOIChunk::Diff(dr, dg, db));
+            }
+
+            if within(dg, -32, 31) && within(drdg, -8, 7) && within(dbdg, -8, 7) {
+                ops.push(QOIChunk::Luma { diff_green: dg, drdg, dbdg });
+            }
+        }
+
+        ops
+    }
+
+    /* Encodes the pixels a window at a time, picking the sequence of ops and
+     * runs that comes out smallest over the whole window rather than the
+     * smallest op for each pixel in turn. Every op leaves the decoder in the
+     * same state, so a pixel's choices differ only in size and in whether a
+     * run goes on, which is what the search keeps track of */
+    fn encode_searched(&mut self, pixels: &[Pixel]) -> Result<(), QOIError> {
+        self.write_header()?;
+
+        let written = self.written + pixels.len() as u64;
+
+        if written > self.expected_pixels() {
+            return Err(QOIError::PixelCount { expected: self.expected_pixels(), written });
+        }
+
+        for window in pixels.chunks(SEARCH_WINDOW) {
+            self.encode_window(window).map_err(QOIError::IO)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode_window(&mut self, pixels: &[Pixel]) -> std::io::Result<()> {
+        // Bytes so far for each run length left open, and for every pixel the
+        // run length before it that led to each
+        let mut cost = [u64::MAX; RUN_STATES];
+        let mut from = Vec::with_capacity(pixels.len());
+        let mut ops = Vec::with_capacity(pixels.len());
+
+        cost[self.run_length as usize] = 0;
+
+        for &px in pixels {
+            let index = px.hash_index();
+            let can_run = self.prev_known && px == self.prev;
+
+            // A run leaves `seen` alone, so an op may only stand in for one
+            // where it stores what the entry already holds
+            let op = match !can_run || (self.seen[index] == px && self.seen_known & 1 << index != 0) {
+                true => self.candidates(px).into_iter().min_by_key(QOIChunk::get_size),
+                false => None,
+            };
+
+            let mut next = [u64::MAX; RUN_STATES];
+            let mut back = [0_u8; RUN_STATES];
+
+            for (r, &bytes) in cost.iter().enumerate().filter(|(_, &bytes)| bytes != u64::MAX) {
+                if let Some(op) = &op {
+                    if bytes + (op.get_size() as u64) < next[0] {
+                        next[0] = bytes + op.get_size() as u64;
+                        back[0] = r as u8;
+                    }
+                }
+
+                if can_run {
+                    // Full runs end and a new one starts, costing a byte
+                    let (to, added) = match r == 0 || r == MAX_RUN_LENGTH as usize {
+                        true => (1, 1),
+                        false => (r + 1, 0),
+                    };
+
+                    if bytes + added < next[to] {
+                        next[to] = bytes + added;
+                        back[to] = r as u8;
+                    }
+                }
+            }
+
+            // The same whichever way the pixel ends up coded
+            if op.is_some() {
+                self.seen[index] = px;
+                self.seen_known |= 1 << index;
+            }
+
+            self.prev = px;
+            self.prev_known = true;
+
+            cost = next;
+            from.push(back);
+            ops.push(op);
+        }
+
+        // Run lengths of the cheapest path, walked back from its end. Of
+        // paths as cheap, one leaving a run open goes on for free into the
+        // next window, and the shorter that run the longer it can
+        let closed = |r: usize| r == 0 || r == MAX_RUN_LENGTH as usize;
+        let mut r = (0..RUN_STATES).min_by_key(|&r| (cost[r], closed(r), r)).unwrap_or(0);
+        let mut path = vec![0; pixels.len()];
+
+        for i in (0..pixels.len()).rev() {
+            path[i] = r;
+            r = from[i][r] as usize;
+        }
+
+        for (r, op) in path.into_iter().zip(ops) {
+            self.written += 1;
+
+            match op.filter(|_| r == 0) {
+                Some(op) => {
+                    self.flush_run()?;
+                    self.write_chunk(op)?;
+                },
+                None => {
+                    if r == 1 {
+                        self.flush_run()?;
+                    }
+
+                    self.run_length += 1;
+                },
+            }
+        }
+
+        // As `encode_pixel` would, which may carry on from here
+        if self.run_length == MAX_RUN_LENGTH {
+            self.flush_run()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_chunk(&mut self, chunk: QOIChunk) -> std::io::Result<()> {
+        let op = chunk.op() as usize;
+
+        self.stats.chunks[op] += 1;
+        self.stats.bytes[op] += chunk.get_size() as u64;
+        self.stats.pixels[op] += match chunk {
+            QOIChunk::Run(biased_length) => biased_length as u64 + 1,
+            _ => 1,
+        };
+
+        match chunk {
+            QOIChunk::ColorRGB(p) => self.sink.write_all(&[0xFE, p.r, p.g, p.b]),
+            QOIChunk::ColorRGBA(p) => self.sink.write_all(&[0xFF, p.r, p.g, p.b, p.a]),
+            QOIChunk::Index(index) => self.sink.write_all(&[index & 0x3F]),
+            QOIChunk::Diff(dr, dg, db) => self.sink.write_all(&[
+                0b01_00_00_00
+                | (dr.wrapping_add(2) << 4)
+                | (dg.wrapping_add(2) << 2)
+                | (db.wrapping_add(2))
+            ]),
+            QOIChunk::Luma { diff_green, drdg, dbdg } => self.sink.write_all(&[
+                0b10_00_00_00 | diff_green.wrapping_add(32),       // Bias by 32
+                (drdg.wrapping_add(8) << 4) | dbdg.wrapping_add(8) // Bias by 8
+            ]),
+            QOIChunk::Run(biased_length) => self.sink.write_all(&[0b11_00_00_00 | biased_length]),
+        }
+    }
+
+    fn flush_run(&mut self) -> std::io::Result<()> {
+        if self.run_length > 0 {
+            // Run lengths are stored with a bias of -1
+            let biased_length = self.run_length - 1;
+            self.run_length = 0;
+            self.write_chunk(QOIChunk::Run(biased_length))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn encode_pixel(&mut self, px: Pixel) -> Result<(), QOIError> {
+        self.write_header()?;
+
+        if self.written == self.expected_pixels() {
+            return Err(QOIError::PixelCount { expected: self.expected_pixels(), written: self.written + 1 });
+        }
+
+        self.written += 1;
+
+        // Only a full color sets every channel of the decoder's previous pixel
+        if !self.prev_known {
+            self.seen[px.hash_index()] = px;
+            self.seen_known |= 1 << px.hash_index();
+            self.prev = px;
+            self.prev_known = true;
+
+            return self.write_chunk(QOIChunk::ColorRGBA(px)).map_err(QOIError::IO);
+        }
+
+        if px == self.prev {
+            self.run_length += 1;
+
+            if self.run_length == MAX_RUN_LENGTH {
+                self.flush_run().map_err(QOIError::IO)?;
+            }
+
+            return Ok(());
+        }
+
+        self.flush_run().map_err(QOIError::IO)?;
+
+        let chunk = self.next_chunk(px);
+        self.write_chunk(chunk).map_err(QOIError::IO)?;
+        self.prev = px;
+
+        Ok(())
+    }
+
+    /* Raw bytes with as many channels as the header: gray, gray and alpha,
+     * RGB or RGBA. Slices may end in the middle of a pixel, e.g. when
+     * encoding row by row from a reader, which is then completed by the
+     * next call */
+    pub fn write_pixels(&mut self, bytes: &[u8]) -> Result<(), QOIError> {
+        let channels = self.header.channels as usize;
+        let mut bytes = bytes;
+
+        if !self.partial.is_empty() {
+            let needed = (channels - self.partial.len()).min(bytes.len());
+            self.partial.extend_from_slice(&bytes[..needed]);
+            bytes = &bytes[needed..];
+
+            if self.partial.len() < channels {
+                return Ok(());
+            }
+
+            let px = to_pixel(&std::mem::take(&mut self.partial));
+            self.encode_pixel(px)?;
+        }
+
+        let mut pixels = bytes.chunks_exact(channels);
+
+        for px in pixels.by_ref() {
+            self.encode_pixel(to_pixel(px))?;
+        }
+
+        self.partial.extend_from_slice(pixels.remainder());
+
+        Ok(())
+    }
+
+    /* Encodes every pixel the iterator yields and finishes, the counterpart of
+     * the decoder's `chunks_iter` for pixels that are generated rather than
+     * stored */
+    pub fn encode_from_iter(mut self, pixels: impl IntoIterator<Item = Pixel>) -> Result<W, QOIError> {
+        for px in pixels {
+            self.encode_pixel(px)?;
+        }
+
+        self.finish()
+    }
+
+    /* Terminates the stream and hands back the sink */
+    pub fn finish(self) -> Result<W, QOIError> {
+        self.finish_with_stats().map(|(sink, _)| sink)
+    }
+
+    /* Also hands back how many chunks, bytes and pixels went to each op */
+    pub fn finish_with_stats(mut self) -> Result<(W, EncodeStats), QOIError> {
+        self.write_header()?;
+        self.end_chunks()?;
+
+        self.sink.write_all(&QOI_END_MARKER)
+            .and_then(|_| self.sink.flush())
+            .map_err(QOIError::IO)?;
+
+        let stats = &self.stats;
+        tracing::debug!("Encoded chunks {:?}, bytes {:?}, pixels {:?} per op", stats.chunks, stats.bytes, stats.pixels);
+
+        Ok((self.sink, self.stats))
+    }
+
+    fn end_chunks(&mut self) -> Result<(), QOIError> {
+        if self.written != self.expected_pixels() || !self.partial.is_empty() {
+            return Err(QOIError::PixelCount { expected: self.expected_pixels(), written: self.written });
+        }
+
+        self.flush_run().map_err(QOIError::IO)
+    }
+}
+
+/* What the optimize pass found, sizes being those of the whole stream */
+#[derive(Debug, Clone)]
+pub struct Optimized {
+    pub greedy: u64, // Written without the pass
+    pub layouts: Vec<(usize, u64)>, // Number of bands tried and what each came to
+    pub bands: usize, // The layout kept
+}
+
+impl Optimized {
+    pub fn describe(&self) -> String {
+        let kept = self.layouts.iter().find(|(bands, _)| *bands == self.bands).map_or(self.greedy, |&(_, size)| size);
+        let saved = self.greedy.saturating_sub(kept);
+
+        let mut lines = vec![format!("  {:<10} {:>10} bytes", "greedy", self.greedy)];
+
+        for &(bands, size) in &self.layouts {
+            let name = format!("{} band{}", bands, if bands == 1 { "" } else { "s" });
+            lines.push(format!("  {:<10} {:>10} bytes{}", name, size, if bands == self.bands { ", kept" } else { "" }));
+        }
+
+        lines.push(format!("  saved {} bytes ({:.2}%)", saved, 100.0 * saved as f64 / self.greedy.max(1) as f64));
+        lines.join("\n")
+    }
+}
+
+/* One band per core, as long as each gets enough pixels */
+fn default_bands(pixels: usize) -> usize {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    threads.min(pixels / MIN_BAND_PIXELS).max(1)
+}
+
+/* Encodes bands of rows on all cores at once and stitches them together.
+ * Each band after the first starts with a full RGBA color and only indexes
+ * colors it wrote itself, so it decodes the same whatever came before it.
+ * That costs a few bytes per band over encoding on one thread */
+pub fn encode_parallel(header: &QOIHeader, pixels: &[Pixel]) -> Result<(Vec<u8>, EncodeStats), QOIError> {
+    encode_bands(header, pixels, default_bands(pixels.len()), false)
+}
+
+/* The opt-in optimize pass, slower by far: searches each band for the
+ * smallest choice of ops, see `encode_searched`, and tries fewer bands than
+ * `encode_parallel` splits the image into, down to one, keeping whichever
+ * layout comes out smallest. Without `parallel` only one band is tried, on
+ * the calling thread */
+pub fn encode_optimized(header: &QOIHeader, pixels: &[Pixel], parallel: bool) -> Result<(Vec<u8>, EncodeStats, Optimized), QOIError> {
+    let bands = if parallel { default_bands(pixels.len()) } else { 1 };
+    let (greedy, _) = encode_bands(header, pixels, bands, false)?;
+
+    let mut best: Option<(Vec<u8>, EncodeStats, usize)> = None;
+    let mut layouts = Vec::new();
+
+    for bands in std::iter::successors(Some(bands), |&bands| (bands > 1).then_some(bands / 2)) {
+        let (bytes, stats) = encode_bands(header, pixels, bands, true)?;
+        layouts.push((bands, bytes.len() as u64));
+
+        if best.as_ref().is_none_or(|(best, _, _)| bytes.len() < best.len()) {
+            best = Some((bytes, stats, bands));
+        }
+    }
+
+    let (bytes, stats, bands) = best.expect("at least one layout is tried");
+
+    Ok((bytes, stats, Optimized { greedy: greedy.len() as u64, layouts, bands }))
+}
+
+/* Encodes `bands` bands of rows at once, see `encode_parallel`, searching
+ * each for its smallest encoding if `search` is set */
+fn encode_bands(header: &QOIHeader, pixels: &[Pixel], bands: usize, search: bool) -> Result<(Vec<u8>, EncodeStats), QOIError> {
+    let width = header.width as usize;
+    let expected = width * header.height as usize;
+
+    if pixels.len() != expected {
+        return Err(QOIError::PixelCount { expected: expected as u64, written: pixels.len() as u64 });
+    }
+
+    let band_rows = (header.height as usize).div_ceil(bands).max(1);
+
+    let encode_band = |i: usize, band: &[Pixel]| {
+        let band_header = QOIHeader { height: (band.len() / width.max(1)) as u32, ..header.clone() };
+        let mut enc = ImageEncoder::band(Vec::new(), &band_header, i == 0);
+
+        match search {
+            true => enc.encode_searched(band)?,
+            false => band.iter().try_for_each(|&px| enc.encode_pixel(px))?,
+        }
+
+        enc.end_chunks()?;
+        Ok::<_, QOIError>((enc.sink, enc.stats))
+    };
+
+    // A single band needs no thread of its own
+    let encoded = match bands {
+        1 => vec![encode_band(0, pixels)?],
+        _ => thread::scope(|scope| {
+            let encode_band = &encode_band;
+
+            let workers = pixels
+                .chunks((band_rows * width).max(1))
+                .enumerate()
+                .map(|(i, band)| scope.spawn(move || encode_band(i, band)))
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("encoder thread panicked"))
+                .collect::<Result<Vec<_>, QOIError>>()
+        })?,
+    };
+
+    let mut out = ImageEncoder::new(Vec::new(), header);
+    out.write_header()?;
+
+    let mut bytes = out.sink;
+    let mut stats = EncodeStats::default();
+
+    for (band, band_stats) in encoded {
+        bytes.extend(band);
+
+        for op in 0..stats.chunks.len() {
+            stats.chunks[op] += band_stats.chunks[op];
+            stats.bytes[op] += band_stats.bytes[op];
+            stats.pixels[op] += band_stats.pixels[op];
+        }
+    }
+
+    bytes.extend(QOI_END_MARKER);
+
+    Ok((bytes, stats))
+}
+
+fn to_pixel(bytes: &[u8]) -> Pixel {
+    match *bytes {
+        [v] => Pixel::new(v, v, v, 255),
+        [v, a] => Pixel::new(v, v, v, a),
+        _ => Pixel::new(bytes[0], bytes[1], bytes[2], bytes.get(3).copied().unwrap_or(255)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::ImageDecoder;
+
+    fn header(width: u32, height: u32) -> QOIHeader {
+        QOIHeader { width, height, channels: 4, colorspace: 0 }
+    }
+
+    /* Runs past the longest op, small and large steps, alpha changes and
+     * colors coming back, so that every op shows up */
+    fn pixels(count: usize) -> Vec<Pixel> {
+        let mut state = 0x2545_f491_u32;
+
+        (0..count)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+
+                let v = (i / 7) as u8;
+                match (i / 100) % 5 {
+                    0 => Pixel { r: 10, g: 20, b: 30, a: 255 },
+                    1 => Pixel { r: v, g: v.wrapping_add(1), b: v, a: 255 },
+                    2 => Pixel { r: (state % 4) as u8 * 60, g: 0, b: 60, a: 255 },
+                    3 => Pixel { r: state as u8, g: (state >> 8) as u8, b: (state >> 16) as u8, a: 255 },
+                    _ => Pixel { r: v, g: v, b: v, a: (state >> 24) as u8 | 0x80 },
+                }
+            })
+            .collect()
+    }
+
+    fn decode(bytes: &[u8], count: usize) -> Vec<Pixel> {
+        let mut decoded = Vec::new();
+        ImageDecoder::new(bytes).unwrap().chunks_iter().decode_into(&mut decoded, count as u64).unwrap();
+        decoded
+    }
+
+    #[test]
+    fn encoder_round_trips() {
+        let pixels = pixels(3000);
+        let mut enc = ImageEncoder::new(Vec::new(), &header(60, 50));
+        pixels.iter().try_for_each(|&px| enc.encode_pixel(px)).unwrap();
+
+        assert_eq!(decode(&enc.finish().unwrap(), pixels.len()), pixels);
+    }
+
+    #[test]
+    fn bands_round_trip() {
+        let pixels = pixels(4800);
+
+        for bands in 1..=4 {
+            for search in [false, true] {
+                let (bytes, _) = encode_bands(&header(80, 60), &pixels, bands, search).unwrap();
+                assert_eq!(decode(&bytes, pixels.len()), pixels, "{} bands, search {}", bands, search);
+            }
+        }
+    }
+
+    #[test]
+    fn search_is_never_larger() {
+        let pixels = pixels(4800);
+
+        for bands in 1..=4 {
+            let (greedy, _) = encode_bands(&header(80, 60), &pixels, bands, false).unwrap();
+            let (searched, _) = encode_bands(&header(80, 60), &pixels, bands, true).unwrap();
+            assert!(searched.len() <= greedy.len(), "{} bands: {} > {}", bands, searched.len(), greedy.len());
+        }
+    }
+
+    #[test]
+    fn optimized_round_trips() {
+        let pixels = pixels(4800);
+        let (bytes, _, optimized) = encode_optimized(&header(80, 60), &pixels, true).unwrap();
+
+        assert_eq!(decode(&bytes, pixels.len()), pixels);
+        assert!(bytes.len() as u64 <= optimized.greedy);
+    }
+}
@@ -0,0 +1,453 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::archive;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SortOrder {
+    Name,     // Natural order, so that "img2" comes before "img10"
+    Modified, // Oldest first
+    Size,     // Smallest first
+}
+
+impl SortOrder {
+    pub fn cycle(self) -> Self {
+        match self {
+            SortOrder::Name => SortOrder::Modified,
+            SortOrder::Modified => SortOrder::Size,
+            SortOrder::Size => SortOrder::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::Name => "name",
+            SortOrder::Modified => "mtime",
+            SortOrder::Size => "size",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortOrder::Name),
+            "mtime" => Ok(SortOrder::Modified),
+            "size" => Ok(SortOrder::Size),
+            _ => Err(format!("Unknown sort order: {}", s)),
+        }
+    }
+}
+
+/* Comma separated file name patterns such as "*.qoi,*.png", where `*`
+ * matches any run of characters and `?` a single one. Case insensitive */
+#[derive(Debug, Clone)]
+pub struct Filter {
+    patterns: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, path: &Path) -> bool {
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_ascii_lowercase(),
+            None => return false,
+        };
+
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes()))
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self { patterns: vec!["*.qoi".to_string()] }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let patterns = s
+            .split(',')
+            .map(|p| p.trim().to_ascii_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>();
+
+        if patterns.is_empty() {
+            Err(format!("Empty filter: \"{}\"", s))
+        } else {
+            Ok(Self { patterns })
+        }
+    }
+}
+
+/* The images that can be browsed, in display order. Shuffling only permutes
+ * `order`, so that the original order can be restored */
+pub struct FileList {
+    paths: Vec<PathBuf>,
+    order: Vec<usize>,
+    position: usize, // Index into `order`
+    shuffled: bool,
+    sort: Option<SortOrder>, // None keeps the order the paths were given in
+}
+
+impl FileList {
+    /* Files are taken as is, directories and archives are expanded to the
+     * files inside that pass the filter. Paths with wildcards are expanded
+     * to the files they match, see `expand_glob` */
+    pub fn from_args<I>(args: I, filter: &Filter) -> std::io::Result<Self>
+    where
+        I: IntoIterator<Item = PathBuf>
+    {
+        let mut paths = Vec::new();
+
+        for arg in args {
+            if is_glob(&arg) && !arg.exists() {
+                let mut entries = expand_glob(&arg, filter);
+
+                entries.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+                paths.extend(entries);
+            } else if arg.is_dir() {
+                let mut entries = std::fs::read_dir(&arg)?
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.is_file() && filter.matches(path))
+                    .collect::<Vec<_>>();
+
+                entries.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+                paths.extend(entries);
+            } else if archive::is_archive(&arg) {
+                let mut entries = archive::list(&arg)?
+                    .into_iter()
+                    .filter(|path| filter.matches(path))
+                    .collect::<Vec<_>>();
+
+                entries.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+                paths.extend(entries);
+            } else {
+                paths.push(arg);
+            }
+        }
+
+        Ok(Self::new(paths))
+    }
+
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let order = (0..paths.len()).collect();
+        Self { paths, order, position: 0, shuffled: false, sort: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: usize) {
+        if position < self.len() {
+            self.position = position;
+        }
+    }
+
+    /* All paths, in display order */
+    pub fn iter(&self) -> impl Iterator<Item = &Path> {
+        self.order.iter().map(|&i| self.paths[i].as_path())
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.order
+            .get(self.position)
+            .map(|&i| self.paths[i].as_path())
+    }
+
+    /* Appends a path after all the others, whatever the order, and returns
+     * its position */
+    pub fn push(&mut self, path: PathBuf) -> usize {
+        self.order.push(self.paths.len());
+        self.paths.push(path);
+
+        self.len() - 1
+    }
+
+    /* Drops the current path from the list, moving on to the one after it */
+    pub fn remove_current(&mut self) -> Option<PathBuf> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let index = self.order.remove(self.position);
+        let path = self.paths.remove(index);
+
+        for i in self.order.iter_mut() {
+            if *i > index {
+                *i -= 1;
+            }
+        }
+
+        if self.position >= self.len() {
+            self.position = 0;
+        }
+
+        Some(path)
+    }
+
+    /* Path `offset` steps away from the current one, wrapping around */
+    pub fn peek(&self, offset: isize) -> Option<&Path> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let len = self.len() as isize;
+        let position = (self.position as isize + offset).rem_euclid(len) as usize;
+
+        Some(self.paths[self.order[position]].as_path())
+    }
+
+    /* Steps through the list, wrapping around at either end */
+    pub fn next(&mut self) {
+        if !self.is_empty() {
+            self.position = (self.position + 1) % self.len();
+        }
+    }
+
+    pub fn prev(&mut self) {
+        if !self.is_empty() {
+            self.position = (self.position + self.len() - 1) % self.len();
+        }
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        self.shuffled
+    }
+
+    pub fn sort_order(&self) -> Option<SortOrder> {
+        self.sort
+    }
+
+    /* Sorts the list in place, staying on the current image. This also
+     * becomes the order that un-shuffling goes back to */
+    pub fn sort_by(&mut self, sort: SortOrder) {
+        let current = self.current().map(Path::to_path_buf);
+
+        match sort {
+            SortOrder::Name => self.paths.sort_by(|a, b| {
+                natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+            }),
+            SortOrder::Modified => self.paths.sort_by_cached_key(|path| {
+                std::fs::metadata(path).and_then(|m| m.modified()).ok()
+            }),
+            SortOrder::Size => self.paths.sort_by_cached_key(|path| {
+                std::fs::metadata(path).map(|m| m.len()).ok()
+            }),
+        }
+
+        self.order = (0..self.paths.len()).collect();
+        self.shuffled = false;
+        self.sort = Some(sort);
+
+        self.position = current
+            .and_then(|current| self.paths.iter().position(|p| *p == current))
+            .unwrap_or(0);
+    }
+
+    /* Shuffles or restores the original order, staying on the current image */
+    pub fn toggle_shuffle(&mut self) {
+        let current = match self.order.get(self.position) {
+            Some(&i) => i,
+            None => return,
+        };
+
+        if self.shuffled {
+            self.order.sort_unstable();
+        } else {
+            shuffle(&mut self.order);
+        }
+
+        self.shuffled = !self.shuffled;
+        self.position = self.order.iter().position(|&i| i == current).unwrap();
+    }
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_str().is_some_and(|path| path.contains(['*', '?']))
+}
+
+/* The files a path with `*` and `?` in its components matches, for shells
+ * that leave a quoted pattern alone. A component that is just `**` matches
+ * any number of directories, none included, or every file below that
+ * passes the filter when it comes last. Unreadable directories are passed
+ * over */
+fn expand_glob(pattern: &Path, filter: &Filter) -> Vec<PathBuf> {
+    let parts = pattern.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>();
+    let literal = parts.iter().take_while(|part| !part.contains(['*', '?'])).count();
+
+    let base = match literal {
+        0 => PathBuf::from("."),
+        n => parts[..n].iter().map(|part| part.as_ref()).collect(),
+    };
+
+    let mut paths = Vec::new();
+    walk_glob(&base, &parts[literal..].iter().map(|part| part.as_ref()).collect::<Vec<_>>(), filter, &mut paths);
+
+    // Without a base, matches read as they were written rather than as ./name
+    match literal {
+        0 => paths.into_iter().map(|path| path.strip_prefix(".").map(Path::to_path_buf).unwrap_or(path)).collect(),
+        _ => paths,
+    }
+}
+
+fn walk_glob(dir: &Path, parts: &[&str], filter: &Filter, paths: &mut Vec<PathBuf>) {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|e| e.path())).collect::<Vec<_>>(),
+        Err(_) => return,
+    };
+
+    if *part == "**" {
+        match rest.is_empty() {
+            true => paths.extend(entries.iter().filter(|entry| entry.is_file() && filter.matches(entry)).cloned()),
+            false => walk_glob(dir, rest, filter, paths),
+        }
+
+        // Links to directories are not followed, as they may lead in a circle
+        let is_dir = |entry: &&PathBuf| std::fs::symlink_metadata(entry).is_ok_and(|meta| meta.is_dir());
+
+        for entry in entries.iter().filter(is_dir) {
+            walk_glob(entry, parts, filter, paths);
+        }
+
+        return;
+    }
+
+    for entry in entries {
+        let matches = entry
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| glob_match(part.as_bytes(), name.as_bytes()));
+
+        match (matches, rest.is_empty()) {
+            (true, true) if entry.is_file() => paths.push(entry),
+            (true, false) if entry.is_dir() => walk_glob(&entry, rest, filter, paths),
+            _ => {},
+        }
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], name)
+                || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        },
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/* Compares runs of digits by their numeric value, everything else bytewise */
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+                let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+
+                let (a_digits, a_rest) = a.split_at(a_len);
+                let (b_digits, b_rest) = b.split_at(b_len);
+
+                // Compare without leading zeros: longer means larger
+                let trim = |d: &[u8]| -> usize { d.iter().take_while(|&&c| c == b'0').count() };
+                let a_num = &a_digits[trim(a_digits)..];
+                let b_num = &b_digits[trim(b_digits)..];
+
+                let ord = a_num.len()
+                    .cmp(&b_num.len())
+                    .then_with(|| a_num.cmp(b_num))
+                    .then_with(|| a_len.cmp(&b_len));
+
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+
+                a = a_rest;
+                b = b_rest;
+            },
+
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+
+                a = &a[1..];
+                b = &b[1..];
+            },
+        }
+    }
+}
+
+/* Fisher-Yates driven by xorshift64*, seeded from the clock. Good enough
+ * for a playback order and saves pulling in a dependency */
+fn shuffle<T>(items: &mut [T]) {
+    let mut state = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+        | 1;
+
+    let mut next_random = || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    };
+
+    for i in (1..items.len()).rev() {
+        let j = (next_random() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_double_star_applies_filter() {
+        let dir = std::env::temp_dir().join(format!("qoiviewer_glob_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+
+        for name in ["a.qoi", "notes.txt", "sub/b.QOI", "sub/.DS_Store"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let filter = "*.qoi".parse().unwrap();
+        let mut found = expand_glob(&dir.join("**"), &filter);
+        found.sort();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, vec![dir.join("a.qoi"), dir.join("sub/b.QOI")]);
+    }
+}
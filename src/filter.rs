@@ -0,0 +1,128 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::bitmap::Bitmap;
+use crate::decoder::Pixel;
+
+/* A transform of the pixels on display, cycled through in the viewer and
+ * shown by its description. The kernels here are filters, and so are
+ * plugins, see plugin.rs */
+pub trait Filter: fmt::Display {
+    fn transform(&self, bitmap: &Bitmap) -> Result<Bitmap, String>;
+}
+
+/* A square convolution kernel, 3x3 or 5x5, with weights in rows. Parsed
+ * from a preset name or from comma separated weights, e.g.
+ *
+ *     gaussian
+ *     0,-1,0,-1,5,-1,0,-1,0
+ *
+ * Weights that do not add up to zero are divided by their sum, so that
+ * filtering keeps the overall brightness */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    name: String,
+    size: usize,
+    weights: Vec<f32>,
+}
+
+/* Presets cycled through in the viewer, before a custom kernel if any */
+pub const PRESETS: [&str; 3] = ["box", "gaussian", "sharpen"];
+
+impl Kernel {
+    fn new(name: &str, weights: Vec<f32>) -> Result<Self, String> {
+        let size = match weights.len() {
+            9 => 3,
+            25 => 5,
+            n => return Err(format!("A kernel needs 9 or 25 weights, got {}", n)),
+        };
+
+        let sum = weights.iter().sum::<f32>();
+        let weights = match sum.abs() > f32::EPSILON {
+            true => weights.iter().map(|w| w / sum).collect(),
+            false => weights,
+        };
+
+        Ok(Self { name: name.to_string(), size, weights })
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        let weights = match name {
+            "box" => vec![1.0; 9],
+            "gaussian" => vec![1.0, 2.0, 1.0, 2.0, 4.0, 2.0, 1.0, 2.0, 1.0],
+            "sharpen" => vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0],
+            _ => return None,
+        };
+
+        Self::new(name, weights).ok()
+    }
+
+    /* Convolves every channel, alpha included. Pixels past the edges repeat
+     * the nearest edge pixel */
+    pub fn apply(&self, bitmap: &Bitmap) -> Bitmap {
+        let (width, height) = (bitmap.width as i64, bitmap.height as i64);
+        let radius = (self.size / 2) as i64;
+
+        let at = |x: i64, y: i64| {
+            let (x, y) = (x.clamp(0, width - 1), y.clamp(0, height - 1));
+            bitmap.pixels[(y * width + x) as usize]
+        };
+
+        let mut pixels = Vec::with_capacity(bitmap.pixels.len());
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0_f32; 4];
+
+                for (i, &weight) in self.weights.iter().enumerate() {
+                    let dx = (i % self.size) as i64 - radius;
+                    let dy = (i / self.size) as i64 - radius;
+                    let p = at(x + dx, y + dy);
+
+                    for (c, value) in [p.r, p.g, p.b, p.a].into_iter().enumerate() {
+                        sum[c] += weight * value as f32;
+                    }
+                }
+
+                let [r, g, b, a] = sum.map(|v| v.round().clamp(0.0, 255.0) as u8);
+                pixels.push(Pixel::new(r, g, b, a));
+            }
+        }
+
+        Bitmap { pixels, recovered: 0, stopped: None, ..*bitmap }
+    }
+}
+
+impl FromStr for Kernel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(kernel) = Self::preset(s) {
+            return Ok(kernel);
+        }
+
+        if !s.contains(',') {
+            return Err(format!("Unknown filter: {}, expected {} or kernel weights", s, PRESETS.join(", ")));
+        }
+
+        let weights = s
+            .split(',')
+            .map(|w| w.trim().parse::<f32>().ok().filter(|w| w.is_finite()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("Invalid kernel weights: {}", s))?;
+
+        Self::new("custom", weights)
+    }
+}
+
+impl Filter for Kernel {
+    fn transform(&self, bitmap: &Bitmap) -> Result<Bitmap, String> {
+        Ok(self.apply(bitmap))
+    }
+}
+
+impl fmt::Display for Kernel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}x{}", self.name, self.size, self.size)
+    }
+}
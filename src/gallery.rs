@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::document::gen_texture;
+use crate::overlay;
+
+const THUMB_SIZE: u32 = 160;
+const CELL_PADDING: u32 = 12;
+const CELL_SIZE: u32 = THUMB_SIZE + 2 * CELL_PADDING;
+
+const CELL_BACKGROUND: Color = Color::RGBA(255, 255, 255, 16);
+const SELECTED_BORDER: Color = Color::RGBA(255, 255, 255, 220);
+const FAILED_MARK: Color = Color::RGBA(255, 0, 0, 96);
+
+type Thumbnail = (PathBuf, Result<Bitmap, String>);
+
+/* A grid of thumbnails for every file in the list. Thumbnails are decoded
+ * and downscaled on worker threads and uploaded as they come in */
+pub struct Gallery<'a> {
+    thumbnails: HashMap<PathBuf, Texture<'a>>,
+    failed: HashSet<PathBuf>,
+    receiver: Receiver<Thumbnail>,
+
+    pub selected: usize,
+    scroll: usize, // First visible row
+}
+
+impl<'a> Gallery<'a> {
+    pub fn new<'p, I: IntoIterator<Item = &'p Path>>(paths: I, selected: usize, options: LoadOptions) -> Self {
+        let paths = Arc::new(paths.into_iter().map(Path::to_path_buf).collect::<Vec<_>>());
+        let next = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::channel();
+
+        let workers = thread::available_parallelism().map_or(2, |n| n.get());
+
+        for _ in 0..workers {
+            let paths = Arc::clone(&paths);
+            let next = Arc::clone(&next);
+            let sender = sender.clone();
+
+            thread::spawn(move || {
+                while let Some(path) = paths.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let thumbnail = Bitmap::load(path.to_str().unwrap_or_default(), &options)
+                        .map(|bitmap| bitmap.thumbnail(THUMB_SIZE));
+
+                    // The gallery is gone, no point in continuing
+                    if sender.send((path.clone(), thumbnail)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self {
+            thumbnails: HashMap::new(),
+            failed: HashSet::new(),
+            receiver,
+
+            selected,
+            scroll: 0,
+        }
+    }
+
+    /* Uploads whatever the workers have finished since the last call */
+    pub fn receive<T: 'a>(&mut self, crt: &'a TextureCreator<T>) {
+        for (path, thumbnail) in self.receiver.try_iter() {
+            match thumbnail.and_then(|bitmap| gen_texture(crt, &bitmap)) {
+                Ok(texture) => {
+                    self.thumbnails.insert(path, texture);
+                },
+                Err(err) => {
+                    tracing::warn!("{}", err);
+                    self.failed.insert(path);
+                },
+            }
+        }
+    }
+
+    fn columns(window_width: u32) -> usize {
+        (window_width / CELL_SIZE).max(1) as usize
+    }
+
+    /* Moves the selection by whole cells, clamped to the list */
+    pub fn move_selection(&mut self, dx: i32, dy: i32, count: usize, window_width: u32) {
+        if count == 0 {
+            return;
+        }
+
+        let columns = Self::columns(window_width) as i64;
+        let target = self.selected as i64 + dx as i64 + dy as i64 * columns;
+
+        self.selected = target.clamp(0, count as i64 - 1) as usize;
+    }
+
+    pub fn draw<'p, I: IntoIterator<Item = &'p Path>>(&mut self, canvas: &mut WindowCanvas, paths: I) {
+        let (window_width, window_height) = canvas.output_size().unwrap();
+
+        let columns = Self::columns(window_width);
+        let visible_rows = (window_height / CELL_SIZE).max(1) as usize;
+
+        // Scroll just enough to keep the selection on screen
+        let selected_row = self.selected / columns;
+        if selected_row < self.scroll {
+            self.scroll = selected_row;
+        } else if selected_row >= self.scroll + visible_rows {
+            self.scroll = selected_row + 1 - visible_rows;
+        }
+
+        // Center the grid horizontally
+        let left = ((window_width - (columns as u32 * CELL_SIZE).min(window_width)) / 2) as i32;
+
+        let first = self.scroll * columns;
+        let last = first + (visible_rows + 1) * columns;
+
+        for (index, path) in paths.into_iter().enumerate().skip(first).take(last - first) {
+            let (row, column) = ((index / columns - self.scroll) as i32, (index % columns) as i32);
+
+            let cell = Rect::new(
+                left + column * CELL_SIZE as i32 + (CELL_PADDING / 2) as i32,
+                row * CELL_SIZE as i32 + (CELL_PADDING / 2) as i32,
+                CELL_SIZE - CELL_PADDING,
+                CELL_SIZE - CELL_PADDING,
+            );
+
+            overlay::fill_rect(canvas, cell, CELL_BACKGROUND);
+
+            if let Some(texture) = self.thumbnails.get(path) {
+                let query = texture.query();
+                let thumb = Rect::from_center(cell.center(), query.width, query.height);
+                canvas.copy(texture, None, thumb).unwrap();
+            } else if self.failed.contains(path) {
+                overlay::fill_rect(canvas, cell, FAILED_MARK);
+            }
+
+            if index == self.selected {
+                overlay::draw_rect(canvas, cell, SELECTED_BORDER);
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::bitmap::Bitmap;
+use crate::decoder::Pixel;
+
+/* Images are taken to be sRGB, as QOI files nearly always are. A wide gamut
+ * monitor without color management stretches sRGB out to its own, more
+ * saturated primaries, so for those the pixels are converted to Display P3
+ * before they reach the screen. SDL 2 cannot tell which gamut a monitor has
+ * or hand out a 10 bit swapchain on its own, so both are configured */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gamut {
+    #[default]
+    Srgb,
+    DisplayP3,
+}
+
+impl FromStr for Gamut {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(Gamut::Srgb),
+            "display-p3" => Ok(Gamut::DisplayP3),
+            _ => Err(format!("Unknown gamut: {}", s)),
+        }
+    }
+}
+
+/* What textures are made of, the same for every window */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Output {
+    pub gamut: Gamut,
+    pub deep_color: bool, // 10 bits per channel for opaque images, where the renderer has them
+}
+
+static OUTPUT: OnceLock<Output> = OnceLock::new();
+
+/* Called once before the first texture is made */
+pub fn init(output: Output) {
+    let _ = OUTPUT.set(output);
+}
+
+pub fn output() -> Output {
+    OUTPUT.get().copied().unwrap_or_default()
+}
+
+// Linear sRGB to linear Display P3, which share the D65 white point. sRGB
+// lies within Display P3, so nothing is ever clipped
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462, 0.177_538, 0.0],
+    [0.033_194, 0.966_806, 0.0],
+    [0.017_083, 0.072_397, 0.910_520],
+];
+
+// Steps of the table that encodes linear light again, fine enough that the
+// steep start of the curve still gets a step per 10 bit code
+const ENCODE_STEPS: usize = 1 << 14;
+
+const DEEP_MAX: f32 = 1023.0;
+
+/* The sRGB transfer curve both ways, which Display P3 uses too */
+struct Tables {
+    linear: [f32; 256],
+    encode: Vec<u16>, // 10 bit codes
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+
+    TABLES.get_or_init(|| {
+        let to_linear = |v: f32| match v <= 0.04045 {
+            true => v / 12.92,
+            false => ((v + 0.055) / 1.055).powf(2.4),
+        };
+
+        let from_linear = |v: f32| match v <= 0.003_130_8 {
+            true => v * 12.92,
+            false => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+        };
+
+        Tables {
+            linear: std::array::from_fn(|i| to_linear(i as f32 / 255.0)),
+            encode: (0..ENCODE_STEPS)
+                .map(|i| (from_linear(i as f32 / (ENCODE_STEPS - 1) as f32) * DEEP_MAX).round() as u16)
+                .collect(),
+        }
+    })
+}
+
+/* Red, green and blue in 10 bits for the given gamut */
+pub fn convert(px: Pixel, gamut: Gamut) -> [u16; 3] {
+    let widen = |c: u8| (c as u16) << 2 | (c as u16) >> 6;
+
+    if gamut == Gamut::Srgb {
+        return [widen(px.r), widen(px.g), widen(px.b)];
+    }
+
+    let tables = tables();
+    let rgb = [px.r, px.g, px.b].map(|c| tables.linear[c as usize]);
+
+    SRGB_TO_P3.map(|row| {
+        let v = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+        tables.encode[(v.clamp(0.0, 1.0) * (ENCODE_STEPS - 1) as f32).round() as usize]
+    })
+}
+
+/* The pixels as the output wants them in 8 bits, or None when that
+ * changes nothing */
+pub fn to_output(bitmap: &Bitmap, gamut: Gamut) -> Option<Bitmap> {
+    if gamut == Gamut::Srgb {
+        return None;
+    }
+
+    let narrow = |v: u16| ((v + 2) >> 2).min(255) as u8;
+
+    let pixels = bitmap
+        .pixels
+        .iter()
+        .map(|&px| {
+            let [r, g, b] = convert(px, gamut);
+            Pixel::new(narrow(r), narrow(g), narrow(b), px.a)
+        })
+        .collect();
+
+    Some(Bitmap { pixels, stopped: None, ..*bitmap })
+}
+
+/* Packs the pixels into ARGB2101010, a native endian u32 each. Alpha only
+ * gets 2 bits there, so this is for opaque images */
+pub fn write_deep(bitmap: &Bitmap, buf: &mut [u8], stride: usize, gamut: Gamut) -> Result<(), String> {
+    let pitch = bitmap.width as usize * 4;
+    let needed = stride * (bitmap.height as usize).saturating_sub(1) + pitch;
+
+    if stride < pitch || buf.len() < needed {
+        return Err(format!("A {}x{} image does not fit {} bytes in rows of {}", bitmap.width, bitmap.height, buf.len(), stride));
+    }
+
+    for (row, pixels) in buf.chunks_mut(stride).zip(bitmap.pixels.chunks(bitmap.width.max(1) as usize)) {
+        for (out, &px) in row[..pitch].chunks_exact_mut(4).zip(pixels) {
+            let [r, g, b] = convert(px, gamut).map(|c| c as u32);
+            out.copy_from_slice(&(3 << 30 | r << 20 | g << 10 | b).to_ne_bytes());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use crate::bitmap::Bitmap;
+use crate::decoder::Pixel;
+
+/* Largest image in pixels, two of which stack in every character cell */
+pub const DEFAULT_SIZE: (u32, u32) = (80, 48);
+
+// Pixels more transparent than this are left out, showing the terminal behind
+const ALPHA_CUTOFF: u8 = 128;
+
+const UPPER_HALF: &str = "\u{2580}";
+const LOWER_HALF: &str = "\u{2584}";
+const RESET: &str = "\x1b[0m";
+
+/* The image as rows of half block characters in 24-bit ANSI colors, the
+ * foreground painting one pixel and the background the one below it. Works
+ * in any terminal with truecolor support, no graphics protocol needed */
+pub fn encode(bitmap: &Bitmap) -> Vec<u8> {
+    let opaque = |x: u32, y: u32| bitmap.pixel_at(x, y).filter(|p| p.a >= ALPHA_CUTOFF);
+
+    let mut out = String::new();
+
+    for y in (0..bitmap.height).step_by(2) {
+        if y > 0 {
+            out.push('\n');
+        }
+
+        for x in 0..bitmap.width {
+            let cell = match (opaque(x, y), opaque(x, y + 1)) {
+                (Some(top), Some(bottom)) => format!("{}{}{}", fg(top), bg(bottom), UPPER_HALF),
+                (Some(top), None) => format!("{}{}{}", RESET, fg(top), UPPER_HALF),
+                (None, Some(bottom)) => format!("{}{}{}", RESET, fg(bottom), LOWER_HALF),
+                (None, None) => format!("{} ", RESET),
+            };
+
+            out.push_str(&cell);
+        }
+
+        out.push_str(RESET);
+    }
+
+    out.into_bytes()
+}
+
+fn fg(p: Pixel) -> String {
+    format!("\x1b[38;2;{};{};{}m", p.r, p.g, p.b)
+}
+
+fn bg(p: Pixel) -> String {
+    format!("\x1b[48;2;{};{};{}m", p.r, p.g, p.b)
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::bitmap::{Bitmap, LoadOptions};
+
+/* SHA-256 of the decoded pixels as RGBA bytes, so files that decode to the
+ * same image hash the same no matter how they were encoded */
+pub fn pixel_hash(path: &Path, options: &LoadOptions) -> Result<String, String> {
+    let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options)?;
+
+    if let Some(stop) = &bitmap.stopped {
+        return Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message));
+    }
+
+    let digest = Sha256::digest(bitmap.to_rgba_bytes());
+
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/* Prints `<hash>  <path>` for every file, like sha256sum */
+pub fn print_all<'p>(paths: impl Iterator<Item = &'p Path>, options: &LoadOptions) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        match pixel_hash(path, options) {
+            Ok(hash) => println!("{}  {}", hash, path.display()),
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
@@ -0,0 +1,43 @@
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+
+use crate::bindings::{Action, Bindings};
+use crate::text;
+use crate::ui;
+
+const NAME_COLOR: Color = Color::RGBA(160, 200, 255, 255);
+
+/* Every action with the keys it is bound to, straight from the bindings so
+ * that rebound keys show up as they are. Split into as many columns as the
+ * window height needs */
+pub fn draw(canvas: &mut WindowCanvas, bindings: &Bindings) {
+    let rows = Action::ALL
+        .iter()
+        .map(|&action| {
+            let keys = bindings.keys_for(action).map(|combo| combo.to_string()).collect::<Vec<_>>();
+            (action.name(), if keys.is_empty() { "-".to_string() } else { keys.join(", ") })
+        })
+        .collect::<Vec<_>>();
+
+    let name_width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+    let mut lines = vec![("keys, any key closes this".to_string(), ui::TEXT_COLOR)];
+    lines.extend(rows.iter().map(|(name, keys)| (format!("{:<width$}  {}", name, keys, width = name_width), NAME_COLOR)));
+
+    let (width, height) = canvas.output_size().unwrap();
+
+    let fitting = ((height as i32 - 2 * ui::MARGIN) / text::line_height(ui::TEXT_SCALE) as i32 - 2).max(1) as usize;
+    let columns = lines.chunks(fitting).collect::<Vec<_>>();
+
+    let sizes = columns.iter().map(|column| ui::panel_size(column)).collect::<Vec<_>>();
+    let total_width = sizes.iter().map(|(w, _)| w + ui::MARGIN as u32).sum::<u32>() - ui::MARGIN as u32;
+
+    let mut x = (width as i32 - total_width as i32) / 2;
+
+    for (column, (column_width, column_height)) in columns.iter().zip(sizes) {
+        let y = (height as i32 - column_height as i32) / 2;
+
+        ui::text_panel(canvas, column, (x.max(0), y.max(0)));
+        x += column_width as i32 + ui::MARGIN;
+    }
+}
@@ -0,0 +1,86 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+use crate::bitmap::Bitmap;
+use crate::overlay;
+
+const BINS: usize = 256;
+const GRAPH_HEIGHT: u32 = 128;
+const PADDING: i32 = 8;
+const MARGIN: i32 = 16;
+
+pub struct Histogram {
+    channels: u8,
+    bins: [[u32; BINS]; 4], // r, g, b, a
+}
+
+impl Histogram {
+    pub fn compute(bitmap: &Bitmap) -> Self {
+        let mut bins = [[0_u32; BINS]; 4];
+
+        for px in bitmap.pixels.iter() {
+            bins[0][px.r as usize] += 1;
+            bins[1][px.g as usize] += 1;
+            bins[2][px.b as usize] += 1;
+            bins[3][px.a as usize] += 1;
+        }
+
+        Self { channels: bitmap.channels, bins }
+    }
+
+    /* Draws the histogram in the bottom left corner of the canvas. Each channel
+     * is normalized against its own peak, so a fully opaque alpha channel does
+     * not flatten the color curves */
+    pub fn draw(&self, canvas: &mut WindowCanvas) {
+        let (_, window_height) = canvas.output_size().unwrap();
+
+        let width = BINS as u32 + 2 * PADDING as u32;
+        let height = GRAPH_HEIGHT + 2 * PADDING as u32;
+        let panel = Rect::new(
+            MARGIN,
+            window_height as i32 - MARGIN - height as i32,
+            width,
+            height
+        );
+
+        overlay::panel(canvas, panel);
+
+        let left = panel.x() + PADDING;
+        let bottom = panel.bottom() - PADDING;
+
+        if matches!(self.channels, 2 | 4) {
+            overlay::with_blend_mode(canvas, BlendMode::Blend, |c| {
+                self.draw_channel(c, 3, Color::RGBA(160, 160, 160, 96), left, bottom);
+            });
+        }
+
+        // Additive blending makes overlapping channels come out as white
+        let colors = [
+            Color::RGBA(255, 0, 0, 255),
+            Color::RGBA(0, 255, 0, 255),
+            Color::RGBA(0, 0, 255, 255),
+        ];
+
+        overlay::with_blend_mode(canvas, BlendMode::Add, |c| {
+            for (ch, &color) in colors.iter().enumerate() {
+                self.draw_channel(c, ch, color, left, bottom);
+            }
+        });
+    }
+
+    fn draw_channel(&self, canvas: &mut WindowCanvas, ch: usize, color: Color, left: i32, bottom: i32) {
+        let bins = &self.bins[ch];
+        let peak = bins.iter().copied().max().unwrap_or(0).max(1) as u64;
+
+        canvas.set_draw_color(color);
+
+        for (i, &count) in bins.iter().enumerate() {
+            let h = ((count as u64) * (GRAPH_HEIGHT as u64) / peak) as i32;
+            if h > 0 {
+                let x = left + i as i32;
+                canvas.draw_line((x, bottom), (x, bottom - h)).unwrap();
+            }
+        }
+    }
+}
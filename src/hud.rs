@@ -0,0 +1,29 @@
+use sdl2::render::WindowCanvas;
+
+use crate::stream::FrameStream;
+use crate::ui;
+
+/* Playback statistics of a stream, in the top left corner of the canvas.
+ * The frame rate turns orange when playback falls behind the target */
+pub fn draw(canvas: &mut WindowCanvas, stream: &FrameStream) {
+    let stats = stream.stats();
+
+    let state = match (stream.paused, stream.looping) {
+        (true, true) => " paused, loop",
+        (true, false) => " paused",
+        (false, true) => " loop",
+        (false, false) => "",
+    };
+
+    let behind = !stream.paused && stats.fps < stream.target_fps() * 0.9;
+
+    let lines = [
+        (format!("frame {}{}", stream.frame_number(), state), ui::TEXT_COLOR),
+        (format!("fps {:.1} / {:.1}", stats.fps, stream.target_fps()), if behind { ui::WARNING_COLOR } else { ui::TEXT_COLOR }),
+        (format!("dropped {}", stats.dropped), ui::TEXT_COLOR),
+        (format!("decode {:.2} ms", stats.decode_time.as_secs_f64() * 1000.0), ui::TEXT_COLOR),
+        (format!("bitrate {:.2} mbit/s", stats.bitrate / 1e6), ui::TEXT_COLOR),
+    ];
+
+    ui::text_panel(canvas, &lines, (ui::MARGIN, ui::MARGIN));
+}
@@ -0,0 +1,111 @@
+use std::io::{Read, Seek};
+use std::ops::Range;
+
+use crate::bitmap::Bitmap;
+use crate::decoder::{Checkpoint, DecodeChunks, EvaluatedChunk, ImageDecoder, Pixel, PixelSink, QOIHeader};
+
+/* Pixels between checkpoints. Each one holds a copy of the color array,
+ * about 260 bytes */
+pub const DEFAULT_SPACING: u64 = 1 << 16;
+
+/* Throws pixels away, for getting from a checkpoint to the first one wanted */
+struct Discard;
+
+impl PixelSink for Discard {
+    fn put_pixel(&mut self, _px: Pixel) {}
+
+    fn put_run(&mut self, _px: Pixel, _count: usize) {}
+}
+
+/* Checkpoints taken about every `spacing` pixels in a first pass over an
+ * image, so that any band of rows or region can then be decoded from the
+ * checkpoint before it instead of from the start. Keeps the source open */
+pub struct ChunkIndex<R> {
+    chunks: DecodeChunks<R>,
+    checkpoints: Vec<Checkpoint>, // Ordered by pixel, the first at pixel 0
+}
+
+impl<R: Read + Seek> ChunkIndex<R> {
+    /* Decodes the whole image once, keeping none of its pixels */
+    pub fn build(dec: ImageDecoder<R>, spacing: u64) -> Result<Self, String> {
+        let count = dec.header().width as u64 * dec.header().height as u64;
+        let spacing = spacing.max(1);
+
+        let mut chunks = dec.chunks_iter_bounded();
+        let mut checkpoints = Vec::new();
+        let mut pixel = 0;
+
+        while pixel < count {
+            // A run passing the mark puts the checkpoint at the chunk after it
+            if pixel >= checkpoints.len() as u64 * spacing {
+                checkpoints.extend(chunks.checkpoint(pixel));
+            }
+
+            match chunks.next_chunk() {
+                EvaluatedChunk::Ok(_) => pixel += 1,
+                EvaluatedChunk::EndMarker => return Err(format!("End marker after {} of {} pixels", pixel, count)),
+                EvaluatedChunk::Faulty(err) => return Err(err),
+            }
+        }
+
+        Ok(Self { chunks, checkpoints })
+    }
+
+    pub fn header(&self) -> &QOIHeader {
+        self.chunks.header()
+    }
+
+    /* The pixels of the given rows, decoding only from the checkpoint
+     * before the first of them up to the last of them */
+    pub fn decode_rows(&mut self, rows: Range<u32>) -> Result<Vec<Pixel>, String> {
+        let &QOIHeader { width, height, .. } = self.header();
+
+        if rows.start > rows.end || rows.end > height {
+            return Err(format!("Rows {}..{} are outside of the image, which has {}", rows.start, rows.end, height));
+        }
+
+        let first = rows.start as u64 * width as u64;
+        let count = rows.len() as u64 * width as u64;
+
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let nearest = self.checkpoints.partition_point(|c| c.pixel <= first) - 1;
+        let at = &self.checkpoints[nearest];
+
+        self.chunks.resume(at).map_err(|err| err.to_string())?;
+        self.chunks.decode_into(&mut Discard, first - at.pixel)?;
+
+        let mut pixels = Vec::with_capacity(count as usize);
+        self.chunks.decode_into(&mut pixels, count)?;
+
+        Ok(pixels)
+    }
+
+    /* The rectangle of `width` by `height` pixels at `x`, `y` */
+    pub fn decode_region(&mut self, x: u32, y: u32, width: u32, height: u32) -> Result<Bitmap, String> {
+        let &QOIHeader { width: image_width, height: image_height, channels, colorspace } = self.header();
+
+        if x.checked_add(width).is_none_or(|right| right > image_width)
+            || y.checked_add(height).is_none_or(|bottom| bottom > image_height)
+        {
+            return Err(format!(
+                "Region {}x{} at {},{} is outside of the image, which is {}x{}",
+                width, height, x, y, image_width, image_height
+            ));
+        }
+
+        let band = Bitmap {
+            width: image_width,
+            height,
+            channels,
+            colorspace,
+            pixels: self.decode_rows(y..y + height)?,
+            recovered: 0,
+            stopped: None,
+        };
+
+        Ok(band.crop(x, 0, width, height))
+    }
+}
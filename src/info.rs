@@ -0,0 +1,117 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::archive;
+use crate::decoder::{ImageDecoder, QOIHeader};
+use crate::input;
+use crate::metadata::Metadata;
+
+/* Header metadata of a QOI file, read without decoding any pixels */
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Info {
+    pub header: QOIHeader,
+    pub file_size: u64,
+    pub metadata: Option<Metadata>, // Key/value pairs after the end marker
+}
+
+impl Info {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        if input::is_stdin(path) {
+            return Self::read_in_memory(path, "standard input");
+        }
+
+        if archive::is_entry(path) {
+            return Self::read_in_memory(path, &format!("\"{}\"", path.display()));
+        }
+
+        let file = File::open(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path.display(), err))?;
+
+        let file_size = file
+            .metadata()
+            .map_err(|err| format!("Failed to read \"{}\": {}", path.display(), err))?
+            .len();
+
+        let dec = ImageDecoder::new(BufReader::new(file))
+            .map_err(|err| format!("Failed to decode \"{}\": {}", path.display(), err))?;
+
+        let header = dec.header().clone();
+        let metadata = Metadata::read(&mut dec.chunks_iter().into_source())?;
+
+        Ok(Self { header, file_size, metadata })
+    }
+
+    /* Standard input and archive entries have no metadata, so all of it is
+     * read for the size */
+    fn read_in_memory(path: &Path, name: &str) -> Result<Self, String> {
+        let data = input::read(path)
+            .map_err(|err| format!("Failed to read {}: {}", name, err))?;
+
+        let dec = ImageDecoder::new(&data[..])
+            .map_err(|err| format!("Failed to decode {}: {}", name, err))?;
+
+        let metadata = Metadata::find(&data)?;
+
+        Ok(Self { header: dec.header().clone(), file_size: data.len() as u64, metadata })
+    }
+
+    /* Size of the raw pixel data the file decodes to */
+    pub fn decoded_size(&self) -> u64 {
+        self.header.width as u64 * self.header.height as u64 * self.header.channels as u64
+    }
+
+    pub fn describe(&self) -> String {
+        let channels = match self.header.channels {
+            1 => "gray, an extension",
+            2 => "gray and alpha, an extension",
+            3 => "RGB",
+            4 => "RGBA",
+            _ => "invalid",
+        };
+
+        let colorspace = match self.header.colorspace {
+            0 => "sRGB with linear alpha",
+            1 => "all channels linear",
+            _ => "invalid",
+        };
+
+        let ratio = match self.decoded_size() {
+            0 => "-".to_string(),
+            decoded => format!("{:.1}% of {} decoded bytes", 100.0 * self.file_size as f64 / decoded as f64, decoded),
+        };
+
+        let mut out = format!(
+            "  width:       {}\n  height:      {}\n  channels:    {} ({})\n  colorspace:  {} ({})\n  file size:   {} bytes\n  ratio:       {}",
+            self.header.width,
+            self.header.height,
+            self.header.channels, channels,
+            self.header.colorspace, colorspace,
+            self.file_size,
+            ratio,
+        );
+
+        if let Some(metadata) = self.metadata.as_ref().filter(|metadata| !metadata.is_empty()) {
+            out.push_str(&format!("\n{}", metadata));
+        }
+
+        out
+    }
+}
+
+/* Prints the metadata of every file, returning whether all of them could be read */
+pub fn print_all<'p>(paths: impl Iterator<Item = &'p Path>) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        match Info::read(path) {
+            Ok(info) => println!("{}\n{}", path.display(), info.describe()),
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
@@ -0,0 +1,48 @@
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::archive;
+
+/* The path that stands for standard input */
+pub const STDIN: &str = "-";
+
+/* Standard input can only be read once, so the first read keeps all of it
+ * for every later one, e.g. reloading the image */
+static STDIN_DATA: OnceLock<Result<Vec<u8>, (io::ErrorKind, String)>> = OnceLock::new();
+
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN)
+}
+
+/* Like `std::fs::read`, but reads standard input for "-" and paths inside
+ * archives out of the archive */
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    if let Some((archive, entry)) = archive::split(path) {
+        return archive::read(archive, &entry);
+    }
+
+    if !is_stdin(path) {
+        return std::fs::read(path);
+    }
+
+    let data = STDIN_DATA.get_or_init(|| {
+        let mut data = Vec::new();
+
+        io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .map(|_| data)
+            .map_err(|err| (err.kind(), err.to_string()))
+    });
+
+    data.clone().map_err(|(kind, message)| io::Error::new(kind, message))
+}
+
+/* Size in bytes, without reading files */
+pub fn size(path: &Path) -> io::Result<u64> {
+    match is_stdin(path) || archive::is_entry(path) {
+        true => read(path).map(|data| data.len() as u64),
+        false => std::fs::metadata(path).map(|meta| meta.len()),
+    }
+}
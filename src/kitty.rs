@@ -0,0 +1,50 @@
+use crate::bitmap::Bitmap;
+
+// Largest payload of a single escape sequence, larger images are sent in chunks
+const CHUNK_SIZE: usize = 4096;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/* The image as kitty graphics protocol sequences that transmit its RGBA
+ * pixels and display them at the cursor. Terminals that do not know the
+ * protocol ignore them */
+pub fn encode(bitmap: &Bitmap) -> Vec<u8> {
+    let payload = base64(&bitmap.to_rgba_bytes());
+    let chunks = payload.chunks(CHUNK_SIZE).collect::<Vec<_>>();
+
+    let mut out = Vec::with_capacity(payload.len() + chunks.len() * 16 + 64);
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+
+        // Only the first sequence describes the image. q=2 keeps the terminal
+        // from answering, which would show up as typed text
+        let control = match i {
+            0 => format!("a=T,f=32,s={},v={},q=2,m={}", bitmap.width, bitmap.height, more),
+            _ => format!("m={}", more),
+        };
+
+        out.extend(format!("\x1b_G{};", control).bytes());
+        out.extend(*chunk);
+        out.extend(b"\x1b\\");
+    }
+
+    out
+}
+
+fn base64(bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for group in bytes.chunks(3) {
+        let n = group.iter().enumerate().fold(0_u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            match i <= group.len() {
+                true => out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize]),
+                false => out.push(b'='),
+            }
+        }
+    }
+
+    out
+}
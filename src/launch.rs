@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+
+/* Environment variable holding the default "open with" command */
+pub const EDITOR_VAR: &str = "EDITOR_IMAGE";
+
+/* Starts `command` on the given file without waiting for it. The command
+ * is split on whitespace; a `{}` argument is replaced by the path, which is
+ * otherwise appended */
+pub fn open_with(command: &str, path: &Path) -> std::io::Result<()> {
+    let mut words = command.split_whitespace();
+
+    let program = words
+        .next()
+        .ok_or_else(|| std::io::Error::other("empty command"))?;
+
+    let mut cmd = Command::new(program);
+    let mut substituted = false;
+
+    for word in words {
+        if word == "{}" {
+            cmd.arg(path);
+            substituted = true;
+        } else {
+            cmd.arg(word);
+        }
+    }
+
+    if !substituted {
+        cmd.arg(path);
+    }
+
+    let mut child = cmd.spawn()?;
+
+    // Reap the child once it exits, so it does not linger as a zombie
+    thread::spawn(move || child.wait());
+
+    Ok(())
+}
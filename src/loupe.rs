@@ -0,0 +1,75 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+
+use crate::overlay;
+use crate::view::View;
+
+const MAGNIFICATION: u32 = 8;
+const SOURCE_SIZE: u32 = 21; // Odd, so that the hovered pixel sits in the middle
+const LOUPE_SIZE: u32 = SOURCE_SIZE * MAGNIFICATION;
+const CURSOR_GAP: i32 = 24;
+
+const BORDER: Color = Color::RGBA(255, 255, 255, 160);
+const MARKER: Color = Color::RGBA(255, 0, 255, 255);
+
+/* Draws a magnified view of the image pixels around the cursor, next to it */
+pub fn draw(canvas: &mut WindowCanvas, texture: &Texture, view: &View, mouse: (i32, i32)) {
+    let (ix, iy) = view.window_to_image(mouse.0, mouse.1);
+    let (ix, iy) = (ix.floor() as i32, iy.floor() as i32);
+
+    let half = (SOURCE_SIZE / 2) as i32;
+    let src = Rect::new(ix - half, iy - half, SOURCE_SIZE, SOURCE_SIZE);
+
+    let (window_width, window_height) = canvas.output_size().unwrap();
+    let dst = place(mouse, (window_width, window_height));
+
+    overlay::panel(canvas, dst);
+
+    // SDL clips `src` against the texture and shrinks `dst` to match, so
+    // only the part of the neighbourhood that lies inside the image is
+    // copied; it has to be positioned manually to keep the center aligned
+    let query = texture.query();
+    let bounds = Rect::new(0, 0, query.width, query.height);
+
+    if let Some(visible) = src.intersection(bounds) {
+        let m = MAGNIFICATION as i32;
+        let visible_dst = Rect::new(
+            dst.x() + (visible.x() - src.x()) * m,
+            dst.y() + (visible.y() - src.y()) * m,
+            visible.width() * MAGNIFICATION,
+            visible.height() * MAGNIFICATION,
+        );
+
+        canvas.copy(texture, visible, visible_dst).unwrap();
+    }
+
+    let marker = Rect::new(
+        dst.x() + half * MAGNIFICATION as i32,
+        dst.y() + half * MAGNIFICATION as i32,
+        MAGNIFICATION,
+        MAGNIFICATION,
+    );
+
+    overlay::draw_rect(canvas, marker, MARKER);
+    overlay::draw_rect(canvas, dst, BORDER);
+}
+
+/* Below and to the right of the cursor, flipped when it would leave the window */
+fn place(mouse: (i32, i32), window: (u32, u32)) -> Rect {
+    let size = LOUPE_SIZE as i32;
+
+    let x = if mouse.0 + CURSOR_GAP + size > window.0 as i32 {
+        mouse.0 - CURSOR_GAP - size
+    } else {
+        mouse.0 + CURSOR_GAP
+    };
+
+    let y = if mouse.1 + CURSOR_GAP + size > window.1 as i32 {
+        mouse.1 - CURSOR_GAP - size
+    } else {
+        mouse.1 + CURSOR_GAP
+    };
+
+    Rect::new(x, y, LOUPE_SIZE, LOUPE_SIZE)
+}
@@ -2,106 +2,611 @@
 // #![allow(unused)]
 // #![allow(dead_code)]
 
-use std::time::Duration;
-use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 extern crate sdl2;
 
+use sdl2::messagebox::{self, MessageBoxFlag};
 use sdl2::Sdl;
-use sdl2::pixels::{PixelFormatEnum, Color};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
 use sdl2::video::Window;
-use sdl2::render::{Texture, TextureCreator, TextureAccess};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
 mod decoder;
+mod encoder;
+mod bitmap;
+mod overlay;
+mod histogram;
+mod view;
+mod loupe;
+mod ruler;
+mod clipboard;
+mod document;
+mod filelist;
+mod cli;
+mod gallery;
+mod cache;
+mod watch;
+mod launch;
+mod bindings;
+mod config;
+mod app;
+mod info;
+mod validate;
+mod stats;
+mod opmap;
+mod trace;
+mod verify;
+mod hash;
+mod bench;
+mod input;
+mod raw;
+mod metadata;
+mod index;
+mod stream;
+mod text;
+mod hud;
+mod record;
+mod anim;
+mod archive;
+mod diffmap;
+mod metrics;
+mod tiling;
+mod minimap;
+mod ui;
+mod help;
+mod wallpaper;
+mod display;
+mod backdrop;
+mod term;
+mod sixel;
+mod kitty;
+mod halfblock;
+mod encode;
+mod palette;
+mod filter;
+mod gamut;
+mod resample;
+mod script;
+#[cfg(feature = "crosscheck")]
+mod crosscheck;
+#[cfg(feature = "qoi16")]
+mod qoi16;
+#[cfg(feature = "plugins")]
+mod plugin;
 
-use decoder::{ImageDecoder, QOIHeader};
+use app::{App, WINDOW_TITLE};
+use backdrop::BackdropFit;
+use bitmap::{Bitmap, LoadOptions};
+use config::Config;
+use filelist::FileList;
+use filter::Filter;
+use stream::FrameStream;
 
-fn create_window(sdl: &Sdl) -> Window {
-    let video_subsystem = sdl.video().unwrap();
+const DEFAULT_PATH: &str = "qoi_test_images/kodim23.qoi";
+const UNTRUSTED_MAX_MEMORY: u64 = 1 << 30;
+const WINDOW_STAGGER: i32 = 48;
+const WINDOW_SIZE: (u32, u32) = (1600, 900);
 
-    video_subsystem.window("QOI Viewer", 1600, 900)
-        .position_centered()
-        .maximized()
-        .resizable()
-        .build()
-        .unwrap()
+/* `index` is the place of the window among those of --windows */
+fn create_window(sdl: &Sdl, options: &cli::Options, index: Option<usize>) -> Result<Window, String> {
+    let video_subsystem = sdl.video()?;
+
+    let mut builder = video_subsystem.window(WINDOW_TITLE, WINDOW_SIZE.0, WINDOW_SIZE.1);
+    builder.resizable();
+
+    // Positions are then relative to that monitor, and fullscreen fills it
+    let monitor = match options.monitor {
+        Some(monitor) => Some(display::bounds(&video_subsystem, monitor)?),
+        None => None,
+    };
+    let (left, top) = monitor.map_or((0, 0), |bounds| (bounds.x(), bounds.y()));
+
+    // An explicit position keeps the window at its default size instead of maximizing it.
+    // Several windows are staggered instead, so that none hides another completely
+    match (options.position, index) {
+        (Some((x, y)), None) => builder.position(left + x, top + y),
+        (None, None) => match monitor {
+            Some(bounds) => {
+                let (x, y) = display::centered(bounds, WINDOW_SIZE);
+                builder.position(x, y).maximized()
+            },
+            None => builder.position_centered().maximized(),
+        },
+        (position, Some(index)) => {
+            let (x, y) = position.unwrap_or((WINDOW_STAGGER, WINDOW_STAGGER));
+            let offset = WINDOW_STAGGER * index as i32;
+
+            builder.position(left + x + offset, top + y + offset)
+        },
+    };
+
+    if options.fullscreen {
+        builder.fullscreen_desktop();
+    }
+
+    builder.build().map_err(|err| err.to_string())
 }
 
-fn gen_texture<'a, T: 'a>(crt: &'a TextureCreator<T>) -> Texture<'a> {
-    let path = "qoi_test_images/kodim23.qoi";
+/* Everything besides the viewer, returning whether it succeeded or None when
+ * the viewer should open. SDL is never initialized on the way, so these work
+ * on machines without a display */
+fn run_headless(options: &cli::Options, files: &FileList) -> Option<bool> {
+    if options.info {
+        return Some(info::print_all(files.iter()));
+    }
 
-    let file = OpenOptions::new()
-        .read(true)
-        .open(path)
-        .expect(format!("Failed to open file: \"{}\"", path).as_str());
+    if let Some(command) = options.command {
+        let ok = match command {
+            cli::Command::Stats => stats::print_all(files.iter(), options.color_stats, options.channel_stats),
+            cli::Command::Trace => trace::trace_all(files.iter()),
+            cli::Command::Verify => verify::verify_all(files.iter()),
+            cli::Command::Bench => {
+                let sources = match options.sources.is_empty() {
+                    true => vec![bench::Source::Slice],
+                    false => options.sources.clone(),
+                };
 
-    let dec = ImageDecoder::new(file).unwrap();
+                bench::bench_all(
+                    files.iter(),
+                    &sources,
+                    options.warmup.unwrap_or(bench::DEFAULT_WARMUP),
+                    options.iterations.unwrap_or(bench::DEFAULT_ITERATIONS),
+                )
+            },
+            cli::Command::Decode => {
+                let mut decode = LoadOptions::default();
+                apply_decode_flags(options, &mut decode);
 
-    let &QOIHeader { width, height, channels, .. } = dec.header();
+                let raw = raw::RawOptions {
+                    format: options.raw.unwrap_or_default(),
+                    dither: options.dither,
+                    premultiply: options.premultiply,
+                    region: options.region,
+                };
 
-    let pixels = dec
-        .chunks_iter()
-        .map(Result::unwrap)
-        .flat_map(|p| if channels == 3 {
-            p.to_channels3_iter()
+                raw::write_all(files.iter(), &raw, &decode)
+            },
+            cli::Command::Animate => {
+                let mut decode = LoadOptions::default();
+                apply_decode_flags(options, &mut decode);
+
+                animate(files, options, &decode)
+            },
+            cli::Command::Diff => {
+                let mut decode = LoadOptions::default();
+                apply_decode_flags(options, &mut decode);
+
+                metrics::print_diff(files.iter(), &decode)
+            },
+            cli::Command::Encode => {
+                let mut decode = LoadOptions::default();
+                apply_decode_flags(options, &mut decode);
+
+                let encode = encode::EncodeOptions {
+                    output: options.output.clone(),
+                    out_dir: options.out_dir.clone(),
+                    target: options.target.unwrap_or_default(),
+                    stats: options.stats,
+                    reduce_channels: options.reduce_channels,
+                    approx: options.approx,
+                    optimize: options.optimize,
+                    kernel: options.kernel.clone(),
+                    resize: options.resize,
+                    resampler: options.resampler.unwrap_or_default(),
+                    gray: options.gray,
+                    metadata: options.metadata.clone(),
+                };
+
+                encode::encode_all(files.iter(), &encode, &decode)
+            },
+            cli::Command::Palette => {
+                let mut decode = LoadOptions::default();
+                apply_decode_flags(options, &mut decode);
+
+                let colors = options.colors.unwrap_or(palette::DEFAULT_COLORS);
+
+                palette::print_all(files.iter(), colors, options.output.as_deref(), &decode)
+            },
+            cli::Command::Run => {
+                let mut decode = LoadOptions::default();
+                apply_decode_flags(options, &mut decode);
+
+                script::run_all(files.iter(), &options.filter, &decode)
+            },
+            #[cfg(feature = "crosscheck")]
+            cli::Command::CrossCheck => crosscheck::check_all(files.iter()),
+            #[cfg(not(feature = "crosscheck"))]
+            cli::Command::CrossCheck => {
+                eprintln!("Built without the crosscheck feature, rebuild with `--features crosscheck`");
+                false
+            },
+        };
+
+        return Some(ok);
+    }
+
+    if options.hash {
+        let mut decode = LoadOptions::default();
+        apply_decode_flags(options, &mut decode);
+
+        return Some(hash::print_all(files.iter(), &decode));
+    }
+
+    if options.sixel || options.kitty || options.term {
+        let mut decode = LoadOptions::default();
+        apply_decode_flags(options, &mut decode);
+
+        let size = |default| options.term_size.unwrap_or(default);
+
+        return Some(if options.term {
+            term::print_all(files.iter(), &decode, size(halfblock::DEFAULT_SIZE), halfblock::encode)
+        } else if options.kitty {
+            term::print_all(files.iter(), &decode, size(term::DEFAULT_SIZE), kitty::encode)
         } else {
-            p.to_channels4_iter()
-        })
-        .collect::<Vec<_>>();
+            let encode = |bitmap: &Bitmap| sixel::encode(bitmap, options.dither);
+            term::print_all(files.iter(), &decode, size(term::DEFAULT_SIZE), encode)
+        });
+    }
+
+    if options.validate {
+        return Some(validate::check_all(files.iter(), options.strict));
+    }
+
+    None
+}
+
+/* The files of each window: all of them in one, or one each with --windows */
+fn split_windows(files: FileList, windows: bool) -> Vec<FileList> {
+    match windows {
+        true => files.iter().map(|path| FileList::new(vec![path.to_path_buf()])).collect(),
+        false => vec![files],
+    }
+}
+
+/* Filters from the plugins directory, see plugin.rs */
+#[cfg(feature = "plugins")]
+fn load_plugins() -> Vec<Arc<dyn Filter>> {
+    plugin::default_dir().map(|dir| plugin::load_all(&dir)).unwrap_or_default()
+}
 
-    let format = if channels == 3 {
-        PixelFormatEnum::RGB24
-    } else {
-        PixelFormatEnum::RGBA32
+#[cfg(not(feature = "plugins"))]
+fn load_plugins() -> Vec<Arc<dyn Filter>> {
+    Vec::new()
+}
+
+/* Starts decoding the first path as a stream and waits for its first frame */
+fn open_stream(files: &FileList, fps: f32, config: &Config) -> Result<(FrameStream, Arc<Bitmap>), String> {
+    let path = files.current().unwrap();
+
+    let mut stream = FrameStream::open(path, fps, config.decode.limits)?;
+    let first = stream
+        .first_frame()
+        .map_err(|err| format!("Failed to play \"{}\": {}", path.display(), err))?;
+
+    Ok((stream, first))
+}
+
+fn animate(files: &FileList, options: &cli::Options, decode: &LoadOptions) -> bool {
+    let out = match &options.output {
+        Some(out) => out,
+        None => {
+            eprintln!("animate needs --output");
+            return false;
+        }
     };
 
-    let mut tex = crt
-        .create_texture(
-            format,
-            TextureAccess::Static,
-            width, height)
-        .expect("Failed to create texture");
+    let inputs = files.iter().collect::<Vec<_>>();
 
-    tex.update(None, &pixels[..], (width as usize) * (channels as usize)).unwrap();
+    let delay = options.delay.unwrap_or(anim::DEFAULT_DELAY_MS);
 
-    tex
-} 
+    match anim::create(out, &inputs, delay, options.delta, decode) {
+        Ok(frames) => {
+            println!("{}: {} frames", out.display(), frames);
+            true
+        },
+        Err(err) => {
+            eprintln!("{}", err);
+            false
+        }
+    }
+}
+
+fn no_display(err: String) -> ! {
+    fatal(format!(
+        "Failed to open a window: {}\n\
+         Commands and the --info, --hash, --validate, --sixel, --kitty and --term flags work without a display",
+        err
+    ));
+}
+
+/* Reports an error that keeps the viewer from starting. It also goes to a
+ * dialog, since a viewer opened from a file manager has no terminal to show
+ * stderr */
+fn fatal(err: String) -> ! {
+    eprintln!("{}", err);
+
+    // Fails without a display, where stderr is all there is
+    let _ = messagebox::show_simple_message_box(MessageBoxFlag::ERROR, WINDOW_TITLE, &err, None);
+
+    std::process::exit(1);
+}
+
+/* Command line flags override how the config says to decode */
+fn apply_decode_flags(options: &cli::Options, decode: &mut LoadOptions) {
+    decode.lenient |= options.lenient;
+
+    if options.untrusted {
+        decode.untrusted = true;
+        decode.limits.max_memory = decode.limits.max_memory.min(UNTRUSTED_MAX_MEMORY);
+    }
+
+    if let Some(max_pixels) = options.max_pixels {
+        decode.limits.max_pixels = max_pixels;
+    }
+
+    if let Some(max_memory) = options.max_memory {
+        decode.limits.max_memory = max_memory;
+    }
+}
+
+/* The file given with --config, or the default one if it exists */
+fn load_config(path: Option<PathBuf>) -> Result<Config, String> {
+    match path {
+        Some(path) => Config::load(&path),
+        None => match Config::default_path().filter(|p| p.is_file()) {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        },
+    }
+}
+
+/* Errors only by default, each -v shows one more level. The spans of --trace
+ * are at the info level, so it shows at least that much */
+fn init_logging(verbose: u8, trace: bool) {
+    let level = match verbose {
+        0 if trace => "info",
+        0 => "error",
+        1 => "warn",
+        _ => "debug",
+    };
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(if trace { FmtSpan::CLOSE } else { FmtSpan::NONE })
+        .with_writer(std::io::stderr)
+        .init();
+}
 
 pub fn main() {
-    let sdl_context = sdl2::init().unwrap();
+    let mut options = match cli::parse(std::env::args_os().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    init_logging(options.verbose, options.trace);
 
-    let mut canvas = create_window(&sdl_context)
-        .into_canvas()
-        .build()
-        .unwrap();
+    if let Some(dir) = &options.watch_dir {
+        if !dir.is_dir() {
+            eprintln!("--watch-dir needs a directory: {}", dir.display());
+            std::process::exit(2);
+        }
+
+        options.paths.push(dir.clone());
+    }
+
+    if options.paths.is_empty() {
+        options.paths.push(PathBuf::from(DEFAULT_PATH));
+    }
+
+    let mut files = match FileList::from_args(std::mem::take(&mut options.paths), &options.filter) {
+        Ok(files) => files,
+        Err(err) => {
+            tracing::error!("Failed to list input files: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(sort) = options.sort {
+        files.sort_by(sort);
+    }
+
+    let mut dir_watcher = options.watch_dir.as_ref().map(|dir| {
+        watch::DirWatcher::new(dir, options.filter.clone(), files.iter().map(Path::to_path_buf))
+    });
+
+    // A hot folder may well be empty until the first render lands
+    if let Some(watcher) = &mut dir_watcher {
+        if files.is_empty() {
+            eprintln!("Waiting for images to appear in {}", watcher.dir().display());
+
+            for path in watcher.wait() {
+                files.push(path);
+            }
+        }
+
+        if let Some(newest) = watch::newest(&files) {
+            files.set_position(newest);
+        }
+    }
+
+    if files.is_empty() {
+        eprintln!("No images to show");
+        std::process::exit(1);
+    }
+
+    if let Some(ok) = run_headless(&options, &files) {
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    if options.compare && options.windows {
+        eprintln!("--compare and --windows cannot be combined");
+        std::process::exit(2);
+    }
+
+    if options.compare && files.len() != 2 {
+        eprintln!("--compare needs exactly two images, got {}", files.len());
+        std::process::exit(1);
+    }
+
+    let mut config = match load_config(options.config.take()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
+    // The command line takes precedence over the config file, which takes precedence over the environment
+    config.open_with = options.open_with.take()
+        .or(config.open_with)
+        .or_else(|| std::env::var(launch::EDITOR_VAR).ok());
+
+    apply_decode_flags(&options, &mut config.decode);
+
+    if let Some(scaling) = options.scaling {
+        config.scaling = scaling;
+    }
+
+    config.record_delta |= options.delta;
+
+    if let Some(gain) = options.diff_gain {
+        config.diff_gain = gain;
+    }
+
+    if let Some(kernel) = options.kernel.take() {
+        config.kernel = Some(kernel);
+    }
+
+    if let Some(gamut) = options.gamut {
+        config.output.gamut = gamut;
+    }
+
+    config.output.deep_color |= options.deep_color;
+    gamut::init(config.output);
+
+    let windows = split_windows(files, options.windows);
+    let fps = options.fps.unwrap_or(stream::DEFAULT_FPS);
+
+    // Opened before the windows, so a stream that cannot be played never shows one
+    let streams = windows
+        .iter()
+        .map(|files| {
+            let play_stream = options.stream || files.current().is_some_and(anim::is_animation);
+            play_stream.then(|| open_stream(files, fps, &config).unwrap_or_else(|err| fatal(err)))
+        })
+        .collect::<Vec<_>>();
+
+    let plugins = load_plugins();
+
+    let backdrop = options.backdrop.as_ref().map(|path| {
+        let bitmap = Bitmap::load(&path.to_string_lossy(), &config.decode).unwrap_or_else(|err| fatal(err));
+        let fit = if options.stretch_backdrop { BackdropFit::Stretch } else { BackdropFit::Tile };
 
-    canvas.set_draw_color(Color::RGB(0, 255, 0));
+        (bitmap, fit)
+    });
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut running = true;
+    let sdl_context = sdl2::init().unwrap_or_else(|err| no_display(err));
+
+    let canvases = (0..windows.len())
+        .map(|index| {
+            create_window(&sdl_context, &options, options.windows.then_some(index))
+                .unwrap_or_else(|err| no_display(err))
+                .into_canvas()
+                .build()
+                .unwrap_or_else(|err| fatal(format!("Failed to create a renderer: {}", err)))
+        })
+        .collect::<Vec<_>>();
+
+    let mut event_pump = sdl_context
+        .event_pump()
+        .unwrap_or_else(|err| fatal(format!("Failed to read input events: {}", err)));
+
+    // Textures borrow the creator of their window, so these outlive every app
+    let crts = canvases.iter().map(|canvas| canvas.texture_creator()).collect::<Vec<_>>();
+
+    let mut apps = canvases
+        .into_iter()
+        .zip(&crts)
+        .zip(windows.into_iter().zip(streams))
+        .map(|((canvas, crt), (files, stream))| {
+            let app = match stream {
+                Some((stream, first)) => App::streaming(canvas, crt, config.clone(), files, stream, first),
+                None if options.compare => App::comparing(canvas, crt, config.clone(), files),
+                None => App::new(canvas, crt, config.clone(), files),
+            };
+
+            let mut app = app.unwrap_or_else(|err| fatal(err));
+
+            if let Some((bitmap, fit)) = &backdrop {
+                app.set_backdrop(bitmap, *fit).unwrap_or_else(|err| fatal(err));
+            }
 
-    let crt = canvas.texture_creator();
-    let texture = gen_texture(&crt);
+            app.add_filters(plugins.iter().cloned());
+
+            app
+        })
+        .collect::<Vec<_>>();
+
+    if let (Some(watcher), Some(app)) = (dir_watcher, apps.first_mut()) {
+        app.watch_dir(watcher);
+    }
+
+    if options.watch_clipboard || options.save_clipboard.is_some() {
+        if let Some(app) = apps.first_mut() {
+            app.watch_clipboard(options.save_clipboard.clone())
+                .unwrap_or_else(|err| fatal(format!("Failed to watch the clipboard: {}", err)));
+        }
+    }
+
+    let frame_time = Duration::new(0, 1_000_000_000u32 / 60);
+
+    // Each window closes on its own, the viewer exits with the last one
+    while !apps.is_empty() {
+        for app in &mut apps {
+            app.update();
+        }
 
-    while running {
-        canvas.clear();
         for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape | Keycode::Q), .. } => {
-                    running = false;
-                    break;
-                },
-                _ => {}
+            // Events of a window only go to its app, the others such as quitting to all
+            let window_id = event.get_window_id();
+
+            for app in apps.iter_mut().filter(|app| app.running) {
+                if window_id.is_none_or(|id| id == app.window_id()) {
+                    app.handle_event(event.clone());
+                }
+            }
+
+            if apps.iter().all(|app| !app.running) {
+                break;
             }
         }
 
-        canvas.copy(&texture, None, None).unwrap();
+        apps.retain(|app| app.running);
 
-        canvas.present();
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        for app in &mut apps {
+            app.render(&event_pump.keyboard_state(), &event_pump.mouse_state());
+        }
+
+        // Wakes up early for stream frames that are due sooner
+        let wait = apps
+            .iter()
+            .filter_map(App::next_deadline)
+            .min()
+            .map_or(frame_time, |due| due.saturating_duration_since(Instant::now()).min(frame_time));
+
+        ::std::thread::sleep(wait);
     }
 }
-
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+use crate::decoder::QOI_END_MARKER;
+
+/* Key/value pairs such as author, comment and timestamp, stored after the
+ * end marker, where the specification lets decoders ignore trailing data:
+ *
+ *   QOI image              ending in the end marker
+ *   key=value lines        UTF-8, each ended by a newline
+ *   u32 length             of those lines, big endian
+ *   "qoim"                 magic
+ *
+ * Found from the end of the file, so no pixels have to be decoded first */
+pub const MAGIC: &[u8; 4] = b"qoim";
+
+const FOOTER_SIZE: u64 = 8;
+
+// A block larger than this is taken for something else that ends in the magic
+const MAX_SIZE: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metadata {
+    pub entries: Vec<(String, String)>,
+}
+
+/* One KEY=VALUE pair, as given on the command line */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry(pub String, pub String);
+
+impl FromStr for Entry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid metadata, expected KEY=VALUE: {}", s);
+
+        let (key, value) = s.split_once('=').ok_or_else(invalid)?;
+
+        if key.is_empty() || s.contains('\n') {
+            return Err(invalid());
+        }
+
+        Ok(Entry(key.to_string(), value.to_string()))
+    }
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /* The block to append after the end marker */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self
+            .entries
+            .iter()
+            .flat_map(|(key, value)| format!("{}={}\n", key, value).into_bytes())
+            .collect::<Vec<_>>();
+
+        let length = bytes.len() as u32;
+        bytes.extend(length.to_be_bytes());
+        bytes.extend(MAGIC);
+
+        bytes
+    }
+
+    /* The block at the end of the source, if there is one right after an end
+     * marker. The source is left at an unspecified place */
+    pub fn read<R: Read + Seek>(source: &mut R) -> Result<Option<Metadata>, String> {
+        let io_err = |err: std::io::Error| format!("Failed to read metadata: {}", err);
+
+        let size = source.seek(SeekFrom::End(0)).map_err(io_err)?;
+
+        if size < FOOTER_SIZE {
+            return Ok(None);
+        }
+
+        let mut footer = [0_u8; FOOTER_SIZE as usize];
+        source.seek(SeekFrom::End(-(FOOTER_SIZE as i64))).map_err(io_err)?;
+        source.read_exact(&mut footer).map_err(io_err)?;
+
+        let length = u32::from_be_bytes(footer[..4].try_into().unwrap()) as u64;
+        let marker = QOI_END_MARKER.len() as u64;
+
+        if &footer[4..] != MAGIC || length > MAX_SIZE || length + FOOTER_SIZE + marker > size {
+            return Ok(None);
+        }
+
+        let mut block = vec![0_u8; (marker + length) as usize];
+        source.seek(SeekFrom::End(-((marker + length + FOOTER_SIZE) as i64))).map_err(io_err)?;
+        source.read_exact(&mut block).map_err(io_err)?;
+
+        if block[..marker as usize] != QOI_END_MARKER {
+            return Ok(None);
+        }
+
+        Self::parse(&block[marker as usize..]).map(Some)
+    }
+
+    /* Same for a file in memory */
+    pub fn find(data: &[u8]) -> Result<Option<Metadata>, String> {
+        Self::read(&mut Cursor::new(data))
+    }
+
+    /* The block when it is exactly the last `trailing` bytes of the file,
+     * i.e. all that follows the end marker of the image */
+    pub fn trailing(data: &[u8], trailing: usize) -> Option<Metadata> {
+        Self::find(data)
+            .ok()
+            .flatten()
+            .filter(|metadata| metadata.to_bytes().len() == trailing)
+    }
+
+    fn parse(text: &[u8]) -> Result<Metadata, String> {
+        let text = std::str::from_utf8(text).map_err(|err| format!("Metadata is not UTF-8: {}", err))?;
+
+        let entries = text
+            .split_terminator('\n')
+            .map(|line| line.parse::<Entry>().map(|Entry(key, value)| (key, value)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Metadata { entries })
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (key, value)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "  {:<12} {}", format!("{}:", key), value)?;
+        }
+
+        Ok(())
+    }
+}
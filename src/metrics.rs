@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::decoder::Pixel;
+
+const CHANNEL_NAMES: [&str; 4] = ["R", "G", "B", "A"];
+
+// SSIM is averaged over windows of this many pixels a side
+const SSIM_WINDOW: u32 = 8;
+
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/* How far apart two images of the same size are */
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metrics {
+    pub channels: usize, // Alpha is only compared when either image has it
+    pub max_error: [u8; 4],
+    pub mean_error: [f64; 4],
+    pub psnr: f64, // Infinite for identical images
+    pub ssim: f64, // Of the luma, 1 for identical images
+}
+
+impl Metrics {
+    pub fn compute(a: &Bitmap, b: &Bitmap) -> Result<Self, String> {
+        if a.size() != b.size() {
+            return Err(format!("sizes differ: {}x{} and {}x{}", a.width, a.height, b.width, b.height));
+        }
+
+        let channels = if a.has_alpha() || b.has_alpha() { 4 } else { 3 };
+
+        let mut max_error = [0_u8; 4];
+        let mut sum_error = [0_u64; 4];
+        let mut sum_squared = 0_u64;
+
+        for (p, q) in a.pixels.iter().zip(&b.pixels) {
+            let errors = [p.r.abs_diff(q.r), p.g.abs_diff(q.g), p.b.abs_diff(q.b), p.a.abs_diff(q.a)];
+
+            for (c, &e) in errors.iter().enumerate().take(channels) {
+                max_error[c] = max_error[c].max(e);
+                sum_error[c] += e as u64;
+                sum_squared += e as u64 * e as u64;
+            }
+        }
+
+        let count = a.pixels.len().max(1) as f64;
+        let mse = sum_squared as f64 / (count * channels as f64);
+
+        Ok(Self {
+            channels,
+            max_error,
+            mean_error: sum_error.map(|sum| sum as f64 / count),
+            psnr: 10.0 * (255.0 * 255.0 / mse).log10(),
+            ssim: ssim(a, b),
+        })
+    }
+
+    pub fn identical(&self) -> bool {
+        self.max_error.iter().all(|&e| e == 0)
+    }
+}
+
+fn luma(p: Pixel) -> f64 {
+    0.299 * p.r as f64 + 0.587 * p.g as f64 + 0.114 * p.b as f64
+}
+
+/* Mean SSIM over non overlapping windows. Images smaller than a window are
+ * compared as a whole */
+fn ssim(a: &Bitmap, b: &Bitmap) -> f64 {
+    let (width, height) = a.size();
+
+    let (window_w, window_h) = (SSIM_WINDOW.min(width).max(1), SSIM_WINDOW.min(height).max(1));
+    let (mut total, mut windows) = (0.0, 0);
+
+    for top in (0..=height.saturating_sub(window_h)).step_by(window_h as usize) {
+        for left in (0..=width.saturating_sub(window_w)).step_by(window_w as usize) {
+            let mut values = Vec::with_capacity((window_w * window_h) as usize);
+
+            for y in top..top + window_h {
+                for x in left..left + window_w {
+                    if let (Some(p), Some(q)) = (a.pixel_at(x, y), b.pixel_at(x, y)) {
+                        values.push((luma(p), luma(q)));
+                    }
+                }
+            }
+
+            if values.is_empty() {
+                continue;
+            }
+
+            let n = values.len() as f64;
+            let mean_a = values.iter().map(|v| v.0).sum::<f64>() / n;
+            let mean_b = values.iter().map(|v| v.1).sum::<f64>() / n;
+
+            let (mut var_a, mut var_b, mut covar) = (0.0, 0.0, 0.0);
+
+            for &(va, vb) in &values {
+                var_a += (va - mean_a) * (va - mean_a);
+                var_b += (vb - mean_b) * (vb - mean_b);
+                covar += (va - mean_a) * (vb - mean_b);
+            }
+
+            let (var_a, var_b, covar) = (var_a / n, var_b / n, covar / n);
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covar + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 { 1.0 } else { total / windows as f64 }
+}
+
+fn load(path: &Path, options: &LoadOptions) -> Result<Bitmap, String> {
+    let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options)?;
+
+    match &bitmap.stopped {
+        Some(stop) => Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message)),
+        None => Ok(bitmap),
+    }
+}
+
+/* Prints the metrics of two images, returning whether they decode to the same pixels */
+pub fn print_diff<'p>(mut paths: impl Iterator<Item = &'p Path>, options: &LoadOptions) -> bool {
+    let (a, b) = match (paths.next(), paths.next(), paths.next()) {
+        (Some(a), Some(b), None) => (a, b),
+        _ => {
+            eprintln!("diff needs exactly two images");
+            return false;
+        }
+    };
+
+    let metrics = load(a, options)
+        .and_then(|bitmap_a| load(b, options).map(|bitmap_b| (bitmap_a, bitmap_b)))
+        .and_then(|(bitmap_a, bitmap_b)| Metrics::compute(&bitmap_a, &bitmap_b));
+
+    let metrics = match metrics {
+        Ok(metrics) => metrics,
+        Err(err) => {
+            eprintln!("{} and {}: {}", a.display(), b.display(), err);
+            return false;
+        }
+    };
+
+    println!("{} vs {}", a.display(), b.display());
+    println!("{:<8} {:>10} {:>15}", "channel", "max error", "mean abs error");
+
+    for (c, name) in CHANNEL_NAMES.iter().enumerate().take(metrics.channels) {
+        println!("{:<8} {:>10} {:>15.4}", name, metrics.max_error[c], metrics.mean_error[c]);
+    }
+
+    println!("PSNR     {:.2} dB", metrics.psnr);
+    println!("SSIM     {:.5}", metrics.ssim);
+
+    metrics.identical()
+}
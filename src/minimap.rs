@@ -0,0 +1,73 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+
+use crate::overlay;
+use crate::view::View;
+
+const MAX_SIZE: u32 = 200;
+const MARGIN: i32 = 12;
+
+const VISIBLE_COLOR: Color = Color::RGBA(255, 255, 0, 255);
+
+/* Whether part of the image lies outside the pane, which is when the
+ * minimap is worth showing */
+pub fn needed(view: &View, image: (u32, u32), pane: (u32, u32)) -> bool {
+    let dest = view.dest_rect(image);
+    let pane = Rect::new(0, 0, pane.0, pane.1);
+
+    !pane.contains_rect(dest)
+}
+
+/* How much smaller the minimap shows the image */
+pub fn scale(image: (u32, u32)) -> f32 {
+    MAX_SIZE as f32 / image.0.max(image.1).max(1) as f32
+}
+
+/* The whole image scaled down into the bottom right corner of the pane,
+ * above the `inset` pixels kept free at the bottom */
+fn placement(image: (u32, u32), pane: (u32, u32), inset: u32) -> Rect {
+    let scale = scale(image);
+    let (w, h) = (
+        ((image.0 as f32 * scale).round() as u32).max(1),
+        ((image.1 as f32 * scale).round() as u32).max(1),
+    );
+
+    Rect::new(pane.0 as i32 - w as i32 - MARGIN, pane.1.saturating_sub(inset) as i32 - h as i32 - MARGIN, w, h)
+}
+
+pub fn draw(canvas: &mut WindowCanvas, texture: &Texture, view: &View, image: (u32, u32), pane: (u32, u32), inset: u32) {
+    let map = placement(image, pane, inset);
+    let scale = map.width() as f32 / image.0.max(1) as f32;
+
+    overlay::panel(canvas, Rect::new(map.x() - 4, map.y() - 4, map.width() + 8, map.height() + 8));
+    canvas.copy(texture, None, map).unwrap();
+
+    // The part of the image inside the pane, clipped to the image
+    let (left, top) = view.window_to_image(0, 0);
+    let (right, bottom) = view.window_to_image(pane.0 as i32, pane.1 as i32);
+
+    let to_map = |x: f32, y: f32| (
+        map.x() + (x.clamp(0.0, image.0 as f32) * scale).round() as i32,
+        map.y() + (y.clamp(0.0, image.1 as f32) * scale).round() as i32,
+    );
+
+    let (x0, y0) = to_map(left, top);
+    let (x1, y1) = to_map(right, bottom);
+
+    let visible = Rect::new(x0, y0, (x1 - x0).max(1) as u32, (y1 - y0).max(1) as u32);
+    overlay::draw_rect(canvas, visible, VISIBLE_COLOR);
+}
+
+/* The image point under a click on the minimap, if it hit the minimap */
+pub fn image_point(image: (u32, u32), pane: (u32, u32), inset: u32, x: i32, y: i32) -> Option<(f32, f32)> {
+    let map = placement(image, pane, inset);
+
+    if !map.contains_point((x, y)) {
+        return None;
+    }
+
+    let scale = image.0.max(1) as f32 / map.width() as f32;
+
+    Some(((x - map.x()) as f32 * scale, (y - map.y()) as f32 * scale))
+}
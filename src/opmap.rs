@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use crate::bitmap::Bitmap;
+use crate::decoder::{EvaluatedChunk, ImageDecoder, Op, Pixel};
+use crate::input;
+
+pub const LEGEND: &str = "RGB=red RGBA=magenta INDEX=green DIFF=yellow LUMA=orange RUN=blue";
+
+pub fn op_color(op: Op) -> Pixel {
+    match op {
+        Op::Rgb   => Pixel::new(230, 40, 40, 255),
+        Op::Rgba  => Pixel::new(230, 40, 230, 255),
+        Op::Index => Pixel::new(40, 200, 60, 255),
+        Op::Diff  => Pixel::new(240, 220, 40, 255),
+        Op::Luma  => Pixel::new(250, 140, 20, 255),
+        Op::Run   => Pixel::new(40, 90, 240, 255),
+    }
+}
+
+/* Decodes a QOI file again, coloring each pixel by the op of the chunk that produced it */
+pub fn op_bitmap(path: &Path) -> Result<Bitmap, String> {
+    let data = input::read(path)
+        .map_err(|err| format!("Failed to read \"{}\": {}", path.display(), err))?;
+
+    let dec = ImageDecoder::new(&data[..])
+        .map_err(|err| format!("Failed to decode \"{}\": {}", path.display(), err))?;
+
+    let header = dec.header().clone();
+    let count = header.width as usize * header.height as usize;
+
+    let mut pixels = Vec::with_capacity(count);
+    let mut chunks = dec.chunks_iter();
+
+    while pixels.len() < count {
+        match chunks.next_chunk() {
+            EvaluatedChunk::Ok(_) => pixels.push(op_color(chunks.last_chunk().unwrap().op())),
+            EvaluatedChunk::EndMarker => break,
+            EvaluatedChunk::Faulty(err) => {
+                return Err(format!("Failed to decode \"{}\": {}", path.display(), err));
+            },
+        }
+    }
+
+    // Pixels missing from a short file show up black
+    pixels.resize(count, Pixel::new(0, 0, 0, 255));
+
+    Ok(Bitmap {
+        width: header.width,
+        height: header.height,
+        channels: 3,
+        colorspace: header.colorspace,
+        pixels,
+        recovered: 0,
+        stopped: None,
+    })
+}
@@ -0,0 +1,42 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+const PANEL_BACKGROUND: Color = Color::RGBA(16, 16, 16, 200);
+const PANEL_BORDER: Color = Color::RGBA(255, 255, 255, 64);
+
+/* Runs `draw` with the given blend mode, restoring the draw color and blend
+ * mode of the canvas afterwards so that `canvas.clear()` is unaffected */
+pub fn with_blend_mode<F>(canvas: &mut WindowCanvas, mode: BlendMode, draw: F)
+where
+    F: FnOnce(&mut WindowCanvas)
+{
+    let prev_color = canvas.draw_color();
+    let prev_mode = canvas.blend_mode();
+
+    canvas.set_blend_mode(mode);
+    draw(canvas);
+
+    canvas.set_blend_mode(prev_mode);
+    canvas.set_draw_color(prev_color);
+}
+
+pub fn fill_rect(canvas: &mut WindowCanvas, rect: Rect, color: Color) {
+    with_blend_mode(canvas, BlendMode::Blend, |c| {
+        c.set_draw_color(color);
+        c.fill_rect(rect).unwrap();
+    });
+}
+
+pub fn draw_rect(canvas: &mut WindowCanvas, rect: Rect, color: Color) {
+    with_blend_mode(canvas, BlendMode::Blend, |c| {
+        c.set_draw_color(color);
+        c.draw_rect(rect).unwrap();
+    });
+}
+
+/* Translucent background that overlays are drawn on top of */
+pub fn panel(canvas: &mut WindowCanvas, rect: Rect) {
+    fill_rect(canvas, rect, PANEL_BACKGROUND);
+    draw_rect(canvas, rect, PANEL_BORDER);
+}
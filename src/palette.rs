@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::decoder::Pixel;
+
+pub const DEFAULT_COLORS: usize = 8;
+
+// Larger images are sampled down to about this many pixels first
+const MAX_SAMPLES: usize = 1 << 20;
+
+// Pixels more transparent than this take no part in the palette
+const ALPHA_CUTOFF: u8 = 128;
+
+const SWATCH_SIZE: u32 = 64;
+
+/* A color of the palette and the share of the pixels it stands for */
+pub struct Entry {
+    pub color: [u8; 3],
+    pub share: f64,
+}
+
+/* Box of colors in median cut, split until there are enough of them */
+struct ColorBox(Vec<[u8; 3]>);
+
+impl ColorBox {
+    /* The channel with the widest spread, and that spread */
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..3)
+            .map(|c| {
+                let (lo, hi) = self.0.iter().fold((255, 0), |(lo, hi), p| (p[c].min(lo), p[c].max(hi)));
+                (c, hi.saturating_sub(lo))
+            })
+            .max_by_key(|&(_, spread)| spread)
+            .unwrap()
+    }
+
+    /* Halves the box at the median of its widest channel */
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+
+        self.0.sort_unstable_by_key(|p| p[channel]);
+        let upper = self.0.split_off(self.0.len() / 2);
+
+        (self, ColorBox(upper))
+    }
+
+    fn mean(&self) -> [u8; 3] {
+        let n = self.0.len().max(1) as u64;
+        let sum = self.0.iter().fold([0_u64; 3], |sum, p| [0, 1, 2].map(|c| sum[c] + p[c] as u64));
+
+        sum.map(|s| (s / n) as u8)
+    }
+}
+
+/* Dominant colors by median cut, most common first */
+pub fn extract(bitmap: &Bitmap, colors: usize) -> Vec<Entry> {
+    let step = (bitmap.pixels.len() / MAX_SAMPLES).max(1);
+
+    let samples = bitmap
+        .pixels
+        .iter()
+        .step_by(step)
+        .filter(|p| p.a >= ALPHA_CUTOFF)
+        .map(|p| [p.r, p.g, p.b])
+        .collect::<Vec<_>>();
+
+    let total = samples.len();
+
+    if total == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![ColorBox(samples)];
+
+    while boxes.len() < colors {
+        // Wide boxes of many pixels first, as long as they hold more than one color
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (i, b.widest_channel().1 as usize * b.0.len()))
+            .filter(|&(_, score)| score > 0)
+            .max_by_key(|&(_, score)| score);
+
+        let (lower, upper) = match widest {
+            Some((i, _)) => boxes.swap_remove(i).split(),
+            None => break,
+        };
+
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    // Every box holds the same number of pixels, so the shares come from
+    // which palette color each pixel is closest to instead
+    let palette = boxes.iter().map(ColorBox::mean).collect::<Vec<_>>();
+    let mut counts = vec![0_usize; palette.len()];
+
+    for p in boxes.iter().flat_map(|b| &b.0) {
+        let nearest = (0..palette.len()).min_by_key(|&i| distance(palette[i], *p)).unwrap();
+        counts[nearest] += 1;
+    }
+
+    let mut entries = palette
+        .into_iter()
+        .zip(counts)
+        .map(|(color, count)| Entry { color, share: count as f64 / total as f64 })
+        .collect::<Vec<_>>();
+
+    entries.sort_by(|a, b| b.share.total_cmp(&a.share));
+    entries
+}
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    (0..3).map(|c| (a[c] as i32 - b[c] as i32).pow(2) as u32).sum()
+}
+
+/* A row of squares, one per color */
+pub fn swatch(entries: &[Entry]) -> Bitmap {
+    let width = SWATCH_SIZE * entries.len() as u32;
+    let mut pixels = Vec::with_capacity((width * SWATCH_SIZE) as usize);
+
+    for _ in 0..SWATCH_SIZE {
+        for entry in entries {
+            let [r, g, b] = entry.color;
+            pixels.extend(std::iter::repeat_n(Pixel::new(r, g, b, 255), SWATCH_SIZE as usize));
+        }
+    }
+
+    Bitmap {
+        width,
+        height: SWATCH_SIZE,
+        channels: 3,
+        colorspace: 0,
+        pixels,
+        recovered: 0,
+        stopped: None,
+    }
+}
+
+/* Prints the palette of every file as hex colors, saving a swatch of it to
+ * `output` when given. That only works for a single file */
+pub fn print_all<'p>(
+    paths: impl Iterator<Item = &'p Path>,
+    colors: usize,
+    output: Option<&Path>,
+    options: &LoadOptions,
+) -> bool {
+    let paths = paths.collect::<Vec<_>>();
+
+    if output.is_some() && paths.len() != 1 {
+        eprintln!("--output needs exactly one image to take a palette of, got {}", paths.len());
+        return false;
+    }
+
+    let mut ok = true;
+
+    for path in paths {
+        let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options).and_then(|bitmap| {
+            match &bitmap.stopped {
+                Some(stop) => Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message)),
+                None => Ok(bitmap),
+            }
+        });
+
+        let bitmap = match bitmap {
+            Ok(bitmap) => bitmap,
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+                continue;
+            }
+        };
+
+        let entries = extract(&bitmap, colors);
+
+        println!("{}:", path.display());
+
+        for entry in &entries {
+            let [r, g, b] = entry.color;
+            println!("  #{:02x}{:02x}{:02x} {:>6.2}%", r, g, b, 100.0 * entry.share);
+        }
+
+        if let Some(out) = output {
+            if let Err(err) = swatch(&entries).save(out) {
+                eprintln!("Failed to save {}: {}", out.display(), err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
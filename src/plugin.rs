@@ -0,0 +1,175 @@
+use std::ffi::{c_char, c_void, CStr};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::bitmap::Bitmap;
+use crate::config::Config;
+use crate::decoder::Pixel;
+use crate::filter::Filter;
+
+/* Filters loaded from dynamic libraries in the plugins directory next to
+ * the config file, cycled through after the kernels. The interface is plain
+ * C, so that plugins can be built with any compiler and language:
+ *
+ *   struct qoiviewer_filter {
+ *       uint32_t abi;        // QOIVIEWER_FILTER_ABI, 1
+ *       const char *name;    // shown in the status bar, or NULL for the file name
+ *
+ *       // Fills `output` from `input`, both width * height RGBA pixels of
+ *       // 4 bytes in rows from the top, returning 0 on success
+ *       int32_t (*apply)(const uint8_t *input, uint8_t *output,
+ *                        uint32_t width, uint32_t height);
+ *   };
+ *
+ *   const struct qoiviewer_filter *qoiviewer_filter(void);
+ *
+ * Libraries stay loaded until the viewer exits, so plugins may keep state
+ * between calls. `apply` is only called from the thread drawing windows */
+pub const ABI_VERSION: u32 = 1;
+
+const ENTRY_POINT: &CStr = c"qoiviewer_filter";
+
+#[cfg(windows)]
+const EXTENSION: &str = "dll";
+#[cfg(target_os = "macos")]
+const EXTENSION: &str = "dylib";
+#[cfg(not(any(windows, target_os = "macos")))]
+const EXTENSION: &str = "so";
+
+type Apply = unsafe extern "C" fn(input: *const u8, output: *mut u8, width: u32, height: u32) -> i32;
+type EntryPoint = unsafe extern "C" fn() -> *const FilterTable;
+
+#[repr(C)]
+struct FilterTable {
+    abi: u32,
+    name: *const c_char,
+    apply: Apply,
+}
+
+pub struct Plugin {
+    name: String,
+    apply: Apply,
+}
+
+/* $XDG_CONFIG_HOME/qoiviewer/plugins, or wherever the config file is */
+pub fn default_dir() -> Option<PathBuf> {
+    Config::default_path()?.parent().map(|dir| dir.join("plugins"))
+}
+
+/* Every plugin in `dir` by file name. A missing directory has none, and
+ * libraries that fail to load are reported and skipped */
+pub fn load_all(dir: &Path) -> Vec<Arc<dyn Filter>> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION)))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| match Plugin::load(&path) {
+            Ok(plugin) => Some(Arc::new(plugin) as Arc<dyn Filter>),
+            Err(err) => {
+                eprintln!("Skipping plugin {}: {}", path.display(), err);
+                None
+            },
+        })
+        .collect()
+}
+
+impl Plugin {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        // SAFETY: whatever the library runs as it loads and the entry point
+        // it exports are trusted, as with any plugin. The entry point has
+        // the signature of the interface above
+        let table = unsafe {
+            let symbol = open(path)?;
+            let entry = std::mem::transmute::<*const c_void, EntryPoint>(symbol);
+
+            entry().as_ref().ok_or("qoiviewer_filter() returned no filter")?
+        };
+
+        if table.abi != ABI_VERSION {
+            return Err(format!("built for plugin interface {}, the viewer has {}", table.abi, ABI_VERSION));
+        }
+
+        let name = match table.name.is_null() {
+            // SAFETY: a NUL terminated string that the library keeps
+            false => unsafe { CStr::from_ptr(table.name) }.to_string_lossy().into_owned(),
+            true => path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        };
+
+        Ok(Self { name, apply: table.apply })
+    }
+}
+
+impl Filter for Plugin {
+    fn transform(&self, bitmap: &Bitmap) -> Result<Bitmap, String> {
+        let input = bitmap.pixels.iter().flat_map(|p| [p.r, p.g, p.b, p.a]).collect::<Vec<_>>();
+        let mut output = vec![0_u8; input.len()];
+
+        // SAFETY: both buffers hold width * height pixels of 4 bytes
+        let status = unsafe { (self.apply)(input.as_ptr(), output.as_mut_ptr(), bitmap.width, bitmap.height) };
+
+        if status != 0 {
+            return Err(format!("filter {} failed with status {}", self.name, status));
+        }
+
+        let pixels = output.chunks_exact(4).map(|c| Pixel::new(c[0], c[1], c[2], c[3])).collect();
+
+        // Plugins may make any pixel transparent
+        Ok(Bitmap { channels: 4, pixels, recovered: 0, stopped: None, ..*bitmap })
+    }
+}
+
+impl fmt::Display for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (plugin)", self.name)
+    }
+}
+
+/* Loads the library, never to unload it, and finds its entry point */
+#[cfg(unix)]
+unsafe fn open(path: &Path) -> Result<*const c_void, String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let error = || match libc::dlerror() {
+        err if err.is_null() => "unknown error".to_string(),
+        err => CStr::from_ptr(err).to_string_lossy().into_owned(),
+    };
+
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|_| "path contains NUL".to_string())?;
+    let library = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+
+    if library.is_null() {
+        return Err(error());
+    }
+
+    match libc::dlsym(library, ENTRY_POINT.as_ptr()) {
+        symbol if symbol.is_null() => Err(error()),
+        symbol => Ok(symbol as *const c_void),
+    }
+}
+
+#[cfg(windows)]
+unsafe fn open(path: &Path) -> Result<*const c_void, String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW};
+
+    let wide = path.as_os_str().encode_wide().chain([0]).collect::<Vec<u16>>();
+    let library = LoadLibraryW(wide.as_ptr());
+
+    if library.is_null() {
+        return Err(std::io::Error::last_os_error().to_string());
+    }
+
+    GetProcAddress(library, ENTRY_POINT.as_ptr() as *const u8)
+        .map(|symbol| symbol as *const c_void)
+        .ok_or_else(|| std::io::Error::last_os_error().to_string())
+}
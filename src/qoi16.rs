@@ -0,0 +1,296 @@
+use std::path::Path;
+
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::decoder::{Limits, Pixel, QOIHeader, QOI_END_MARKER, SEEN_ARRAY_SIZE};
+
+/* EXPERIMENTAL and NOT part of the QOI specification: a variant with 16 bits
+ * per channel for HDR and scientific images, which no other QOI decoder
+ * reads. The header and end marker are those of QOI apart from the magic,
+ * and so are the ops apart from their sizes:
+ *
+ *   "qo16"                 magic
+ *   RGB     0xFE           then red, green and blue as big endian u16
+ *   RGBA    0xFF           then red, green, blue and alpha
+ *   INDEX   00iiiiii       as in QOI
+ *   DIFF    01rrrrgg       ggbbbb00, each difference -8..7 biased by 8
+ *   LUMA    10gggggg       ggggrrrr rrbbbbbb, green -512..511 biased by
+ *                          512, red and blue minus green -32..31 by 32
+ *   RUN     11llllll       as in QOI */
+pub const MAGIC: [u8; 4] = *b"qo16";
+pub const EXTENSION: &str = "qoi16";
+
+const HEADER_SIZE: usize = 14;
+const MAX_RUN_LENGTH: u8 = 62;
+
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+
+pub type Pixel16 = Pixel<u16>;
+
+fn hash_index(px: Pixel16) -> usize {
+    (px.r as usize * 3 + px.g as usize * 5 + px.b as usize * 7 + px.a as usize * 11) % SEEN_ARRAY_SIZE
+}
+
+pub fn is_qoi16(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(EXTENSION))
+}
+
+pub fn encode(header: &QOIHeader, pixels: &[Pixel16]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + pixels.len() * 2 + QOI_END_MARKER.len());
+
+    out.extend(MAGIC);
+    out.extend(header.width.to_be_bytes());
+    out.extend(header.height.to_be_bytes());
+    out.extend([header.channels, header.colorspace]);
+
+    let mut prev = Pixel { r: 0, g: 0, b: 0, a: u16::MAX };
+    let mut seen = [Pixel { r: 0, g: 0, b: 0, a: 0 }; SEEN_ARRAY_SIZE];
+    let mut run = 0_u8;
+
+    for &px in pixels {
+        if px == prev {
+            run += 1;
+
+            if run == MAX_RUN_LENGTH {
+                out.push(0xC0 | (run - 1));
+                run = 0;
+            }
+
+            continue;
+        }
+
+        if run > 0 {
+            out.push(0xC0 | (run - 1));
+            run = 0;
+        }
+
+        let index = hash_index(px);
+
+        if seen[index] == px {
+            out.push(index as u8);
+            prev = px;
+            continue;
+        }
+
+        seen[index] = px;
+
+        if px.a != prev.a {
+            out.push(OP_RGBA);
+            [px.r, px.g, px.b, px.a].iter().for_each(|c| out.extend(c.to_be_bytes()));
+            prev = px;
+            continue;
+        }
+
+        let dr = px.r.wrapping_sub(prev.r) as i16;
+        let dg = px.g.wrapping_sub(prev.g) as i16;
+        let db = px.b.wrapping_sub(prev.b) as i16;
+
+        let drdg = dr.wrapping_sub(dg);
+        let dbdg = db.wrapping_sub(dg);
+
+        if [dr, dg, db].iter().all(|d| (-8..=7).contains(d)) {
+            let bits = 0b01 << 14 | ((dr + 8) as u16) << 10 | ((dg + 8) as u16) << 6 | ((db + 8) as u16) << 2;
+            out.extend(bits.to_be_bytes());
+        } else if (-512..=511).contains(&dg) && (-32..=31).contains(&drdg) && (-32..=31).contains(&dbdg) {
+            let bits = 0b10 << 22 | ((dg + 512) as u32) << 12 | ((drdg + 32) as u32) << 6 | (dbdg + 32) as u32;
+            out.extend(&bits.to_be_bytes()[1..]);
+        } else {
+            out.push(OP_RGB);
+            [px.r, px.g, px.b].iter().for_each(|c| out.extend(c.to_be_bytes()));
+        }
+
+        prev = px;
+    }
+
+    if run > 0 {
+        out.push(0xC0 | (run - 1));
+    }
+
+    out.extend(QOI_END_MARKER);
+    out
+}
+
+/* Decodes exactly the pixels the header declares, followed by the end marker */
+pub fn decode(data: &[u8], limits: &Limits) -> Result<(QOIHeader, Vec<Pixel16>), String> {
+    if data.len() < HEADER_SIZE || data[..4] != MAGIC {
+        return Err("Missing \"qo16\" magic at byte 0".to_string());
+    }
+
+    let be_u32 = |at: usize| u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+
+    let header = QOIHeader {
+        width: be_u32(4),
+        height: be_u32(8),
+        channels: data[12],
+        colorspace: data[13],
+    };
+
+    limits.check(header.width, header.height).map_err(|err| err.to_string())?;
+
+    let count = header.width as usize * header.height as usize;
+    let mut pixels = Vec::with_capacity(count);
+
+    let mut prev = Pixel { r: 0, g: 0, b: 0, a: u16::MAX };
+    let mut seen = [Pixel { r: 0, g: 0, b: 0, a: 0 }; SEEN_ARRAY_SIZE];
+    let mut at = HEADER_SIZE;
+
+    let bytes = |at: usize, n: usize| {
+        data.get(at..at + n).ok_or_else(|| format!("Unexpected end of data after byte {}", at))
+    };
+
+    let channels = |bytes: &[u8]| {
+        bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>()
+    };
+
+    while pixels.len() < count {
+        let tag = bytes(at, 1)?[0];
+
+        let px = match tag {
+            OP_RGB => {
+                let c = channels(bytes(at + 1, 6)?);
+                at += 7;
+                Pixel { r: c[0], g: c[1], b: c[2], a: prev.a }
+            },
+            OP_RGBA => {
+                let c = channels(bytes(at + 1, 8)?);
+                at += 9;
+                Pixel { r: c[0], g: c[1], b: c[2], a: c[3] }
+            },
+            _ => match tag >> 6 {
+                0b00 => {
+                    at += 1;
+                    seen[(tag & 0x3F) as usize]
+                },
+                0b01 => {
+                    let bits = u16::from_be_bytes(bytes(at, 2)?.try_into().unwrap());
+                    at += 2;
+
+                    let diff = |shift: u16| ((bits >> shift) & 0x0F).wrapping_sub(8);
+                    Pixel {
+                        r: prev.r.wrapping_add(diff(10)),
+                        g: prev.g.wrapping_add(diff(6)),
+                        b: prev.b.wrapping_add(diff(2)),
+                        a: prev.a,
+                    }
+                },
+                0b10 => {
+                    let b = bytes(at, 3)?;
+                    let bits = u32::from_be_bytes([0, b[0], b[1], b[2]]);
+                    at += 3;
+
+                    let dg = (((bits >> 12) & 0x3FF) as u16).wrapping_sub(512);
+                    let drdg = (((bits >> 6) & 0x3F) as u16).wrapping_sub(32);
+                    let dbdg = ((bits & 0x3F) as u16).wrapping_sub(32);
+
+                    Pixel {
+                        r: prev.r.wrapping_add(dg).wrapping_add(drdg),
+                        g: prev.g.wrapping_add(dg),
+                        b: prev.b.wrapping_add(dg).wrapping_add(dbdg),
+                        a: prev.a,
+                    }
+                },
+                _ => {
+                    let length = (tag & 0x3F) as usize + 1;
+
+                    if pixels.len() + length > count {
+                        return Err(format!("Run at byte {} goes past the last pixel", at));
+                    }
+
+                    pixels.extend(std::iter::repeat_n(prev, length));
+                    at += 1;
+                    continue;
+                },
+            },
+        };
+
+        seen[hash_index(px)] = px;
+        prev = px;
+        pixels.push(px);
+    }
+
+    if bytes(at, QOI_END_MARKER.len())? != QOI_END_MARKER {
+        return Err(format!("No end marker at byte {} after the last pixel", at));
+    }
+
+    Ok((header, pixels))
+}
+
+/* Maps 16 bit channels onto the 8 bits the viewer shows. The brightest
+ * channel of the image becomes white, so that data using only the lower
+ * part of the range is still visible, and linear images get a 2.2 gamma */
+pub fn tonemap(header: &QOIHeader, pixels: &[Pixel16]) -> Vec<Pixel> {
+    let peak = pixels.iter().map(|p| p.r.max(p.g).max(p.b)).max().unwrap_or(0).max(1) as f32;
+
+    let gamma = match header.colorspace {
+        1 => 1.0 / 2.2,
+        _ => 1.0,
+    };
+
+    let map = |v: u16| ((v as f32 / peak).powf(gamma) * 255.0).round().clamp(0.0, 255.0) as u8;
+
+    pixels.iter().map(|p| Pixel::new(map(p.r), map(p.g), map(p.b), (p.a >> 8) as u8)).collect()
+}
+
+/* Decodes and tonemaps a file for the viewer */
+pub fn to_bitmap(path: &str, data: &[u8], options: &LoadOptions) -> Result<Bitmap, String> {
+    let (header, wide) = decode(data, &options.limits)
+        .map_err(|err| format!("Failed to decode \"{}\": {}", path, err))?;
+
+    Ok(Bitmap {
+        width: header.width,
+        height: header.height,
+        channels: header.channels,
+        colorspace: header.colorspace,
+        pixels: tonemap(&header, &wide),
+        recovered: 0,
+        stopped: None,
+    })
+}
+
+/* Encodes a file with its full bit depth, returning its header and size.
+ * QOI files are widened from 8 bits */
+pub fn encode_file(path: &Path, out: &Path, options: &LoadOptions) -> Result<(QOIHeader, u64), String> {
+    let fail = |err: &dyn std::fmt::Display| format!("Failed to encode \"{}\": {}", path.display(), err);
+
+    let is_qoi = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"));
+
+    let (header, pixels) = if is_qoi {
+        let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options)?;
+        let widen = |v: u8| v as u16 * 257;
+
+        let pixels = bitmap
+            .pixels
+            .iter()
+            .map(|p| Pixel { r: widen(p.r), g: widen(p.g), b: widen(p.b), a: widen(p.a) })
+            .collect::<Vec<_>>();
+
+        (bitmap.header(), pixels)
+    } else {
+        let (width, height) = image::image_dimensions(path).map_err(|err| fail(&err))?;
+        options.limits.check(width, height).map_err(|err| fail(&err))?;
+
+        let img = image::open(path).map_err(|err| fail(&err))?;
+
+        let header = QOIHeader {
+            width,
+            height,
+            channels: if img.color().has_alpha() { 4 } else { 3 },
+            colorspace: 0,
+        };
+
+        let pixels = img
+            .to_rgba16()
+            .pixels()
+            .map(|p| Pixel { r: p[0], g: p[1], b: p[2], a: p[3] })
+            .collect::<Vec<_>>();
+
+        (header, pixels)
+    };
+
+    let bytes = encode(&header, &pixels);
+
+    std::fs::write(out, &bytes)
+        .map_err(|err| format!("Failed to write \"{}\": {}", out.display(), err))?;
+
+    Ok((header, bytes.len() as u64))
+}
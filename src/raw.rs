@@ -0,0 +1,244 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::archive;
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::decoder::{ByteSink, ImageDecoder, PixelLayout};
+use crate::index::{self, ChunkIndex};
+use crate::input;
+
+/* Byte layout of the pixels written by `decode` */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RawFormat {
+    Rgb,
+    Four(PixelLayout), // Every pixel with alpha, in this order
+    Rgb565, // 16 bits a pixel, little endian, without alpha
+    Rgb555, // Same, with the top bit unused
+}
+
+impl Default for RawFormat {
+    fn default() -> Self {
+        RawFormat::Four(PixelLayout::Rgba)
+    }
+}
+
+/* Knobs of the `decode` command */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RawOptions {
+    pub format: RawFormat,
+    pub dither: bool, // Only applies to the 16 bit formats
+    pub premultiply: bool, // Colors multiplied by alpha, as compositors expect
+    pub region: Option<(u32, u32, u32, u32)>, // X, Y, width and height of the part to write
+}
+
+// Thresholds of a 4x4 ordered dither, in sixteenths of a quantization step
+const BAYER: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+impl FromStr for RawFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rgb" => Ok(RawFormat::Rgb),
+            "rgba" => Ok(RawFormat::Four(PixelLayout::Rgba)),
+            "bgra" => Ok(RawFormat::Four(PixelLayout::Bgra)),
+            "argb" => Ok(RawFormat::Four(PixelLayout::Argb)),
+            "abgr" => Ok(RawFormat::Four(PixelLayout::Abgr)),
+            "rgb565" => Ok(RawFormat::Rgb565),
+            "rgb555" => Ok(RawFormat::Rgb555),
+            _ => Err(format!("Unknown raw format: {}", s)),
+        }
+    }
+}
+
+/* Reduces a channel to `bits`, rounding to the nearest level or, when
+ * dithering, up or down by the threshold of the pixel position */
+fn reduce(value: u8, bits: u32, threshold: Option<u8>) -> u16 {
+    let levels = ((1 << bits) - 1) as f32;
+    let scaled = value as f32 * levels / 255.0;
+
+    let level = match threshold {
+        Some(t) => (scaled + (t as f32 + 0.5) / 16.0 - 0.5).round(),
+        None => scaled.round(),
+    };
+
+    level.clamp(0.0, levels) as u16
+}
+
+/* Packs every pixel into 16 bits, 5 of red and blue and `green_bits` of green */
+fn packed_bytes(bitmap: &Bitmap, green_bits: u32, dither: bool) -> Vec<u8> {
+    let width = bitmap.width.max(1) as usize;
+
+    bitmap
+        .pixels
+        .iter()
+        .enumerate()
+        .flat_map(|(i, p)| {
+            let threshold = dither.then(|| BAYER[i / width % 4][i % width % 4]);
+
+            let r = reduce(p.r, 5, threshold);
+            let g = reduce(p.g, green_bits, threshold);
+            let b = reduce(p.b, 5, threshold);
+
+            (r << (green_bits + 5) | g << 5 | b).to_le_bytes()
+        })
+        .collect()
+}
+
+/* Whether the file is a QOI file that can be opened and decoded strictly */
+fn is_qoi_on_disk(path: &Path, options: &LoadOptions) -> bool {
+    let is_qoi = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("qoi"));
+
+    is_qoi && !input::is_stdin(path) && !archive::is_entry(path) && !options.lenient
+}
+
+/* QOI files on disk are converted to RGB or a four channel layout while
+ * decoding, premultiplied if asked, without a bitmap in between. None for
+ * anything else */
+fn direct_bytes(path: &Path, raw: &RawOptions, options: &LoadOptions) -> Option<Result<Vec<u8>, String>> {
+    if !is_qoi_on_disk(path, options) || raw.region.is_some() {
+        return None;
+    }
+
+    let layout = match raw.format {
+        RawFormat::Rgb => None,
+        RawFormat::Four(layout) => Some(layout),
+        RawFormat::Rgb565 | RawFormat::Rgb555 => return None,
+    };
+
+    let decode = || {
+        let fail = |err: &dyn fmt::Display| format!("Failed to decode \"{}\": {}", path.display(), err);
+
+        let data = std::fs::read(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path.display(), err))?;
+        let dec = ImageDecoder::with_limits(&data[..], options.limits).map_err(|err| fail(&err))?;
+
+        let count = dec.header().width as u64 * dec.header().height as u64;
+        let mut bytes = Vec::with_capacity(count as usize * if layout.is_some() { 4 } else { 3 });
+
+        let mut sink = match layout {
+            Some(layout) => ByteSink::with_layout(&mut bytes, layout),
+            None => ByteSink::rgb(&mut bytes),
+        }
+        .premultiplied(raw.premultiply);
+
+        let mut chunks = match options.untrusted {
+            true => dec.chunks_iter_bounded(),
+            false => dec.chunks_iter(),
+        };
+
+        chunks.decode_into(&mut sink, count).map_err(|err| fail(&err))?;
+
+        Ok(bytes)
+    };
+
+    Some(decode())
+}
+
+/* A part of the image. QOI files on disk are indexed in a first pass that
+ * keeps no pixels, and then only the rows of the region are decoded */
+fn load_region(path: &Path, (x, y, width, height): (u32, u32, u32, u32), options: &LoadOptions) -> Result<Bitmap, String> {
+    let fail = |err: &dyn fmt::Display| format!("Failed to decode \"{}\": {}", path.display(), err);
+
+    if is_qoi_on_disk(path, options) {
+        let file = File::open(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path.display(), err))?;
+        let dec = ImageDecoder::with_limits(BufReader::new(file), options.limits).map_err(|err| fail(&err))?;
+
+        let mut index = ChunkIndex::build(dec, index::DEFAULT_SPACING).map_err(|err| fail(&err))?;
+        return index.decode_region(x, y, width, height).map_err(|err| fail(&err));
+    }
+
+    let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options)?;
+
+    if let Some(stop) = &bitmap.stopped {
+        return Err(fail(&stop.message));
+    }
+
+    if x.checked_add(width).is_none_or(|right| right > bitmap.width)
+        || y.checked_add(height).is_none_or(|bottom| bottom > bitmap.height)
+    {
+        return Err(fail(&format!(
+            "Region {}x{} at {},{} is outside of the image, which is {}x{}",
+            width, height, x, y, bitmap.width, bitmap.height
+        )));
+    }
+
+    Ok(bitmap.crop(x, y, width, height))
+}
+
+/* The decoded pixels row by row with no header, as read by e.g.
+ * `ffmpeg -f rawvideo -pix_fmt rgba -s WxH -i -` */
+pub fn raw_bytes(path: &Path, raw: &RawOptions, options: &LoadOptions) -> Result<Vec<u8>, String> {
+    if let Some(bytes) = direct_bytes(path, raw, options) {
+        return bytes;
+    }
+
+    let mut bitmap = match raw.region {
+        Some(region) => load_region(path, region, options)?,
+        None => Bitmap::load(path.to_str().unwrap_or_default(), options)?,
+    };
+
+    if let Some(stop) = &bitmap.stopped {
+        return Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message));
+    }
+
+    // Formats without alpha then show the image over black
+    if raw.premultiply {
+        bitmap.pixels.iter_mut().for_each(|p| *p = p.premultiplied());
+    }
+
+    let dither = raw.dither;
+
+    Ok(match raw.format {
+        RawFormat::Rgb => bitmap.to_rgb_bytes(),
+        RawFormat::Four(layout) => bitmap.to_layout_bytes(layout),
+        RawFormat::Rgb565 => packed_bytes(&bitmap, 6, dither),
+        RawFormat::Rgb555 => packed_bytes(&bitmap, 5, dither),
+    })
+}
+
+/* Streams every file to stdout back to back. Stops at the first failed write,
+ * since the reader has most likely gone away */
+pub fn write_all<'p>(paths: impl Iterator<Item = &'p Path>, raw: &RawOptions, options: &LoadOptions) -> bool {
+    let stdout = io::stdout();
+
+    if stdout.is_terminal() {
+        eprintln!("Refusing to write raw pixels to a terminal, redirect the output to a file or pipe");
+        return false;
+    }
+
+    let mut out = BufWriter::new(stdout.lock());
+    let mut ok = true;
+
+    for path in paths {
+        let bytes = match raw_bytes(path, raw, options) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+                continue;
+            }
+        };
+
+        if let Err(err) = out.write_all(&bytes) {
+            eprintln!("Failed to write \"{}\": {}", path.display(), err);
+            return false;
+        }
+    }
+
+    if let Err(err) = out.flush() {
+        eprintln!("Failed to write: {}", err);
+        return false;
+    }
+
+    ok
+}
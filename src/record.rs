@@ -0,0 +1,113 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::WindowCanvas;
+
+use crate::decoder::{Pixel, QOIError, QOIHeader, Seed};
+use crate::encoder::ImageEncoder;
+use crate::stream::DELTA_MAGIC;
+
+// Frames waiting to be encoded. When the encoder falls this far behind,
+// capturing waits for it instead of dropping frames
+const FRAME_QUEUE: usize = 8;
+
+type Capture = (u32, u32, Vec<u8>); // Width, height and RGB24 bytes
+
+/* Appends every captured frame of the window to a file of back to back QOI
+ * images, the format `--stream` plays, optionally as delta frames. Encoding
+ * happens on a background thread */
+pub struct Recorder {
+    pub path: PathBuf,
+    pub frames: u64,
+
+    sender: Option<SyncSender<Capture>>,
+    worker: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, delta: bool) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|err| format!("Failed to create \"{}\": {}", path.display(), err))?;
+
+        let (sender, receiver) = mpsc::sync_channel::<Capture>(FRAME_QUEUE);
+
+        let worker = thread::spawn(move || {
+            let mut sink = BufWriter::new(file);
+            let mut seed = Seed::default();
+
+            if delta {
+                sink.write_all(DELTA_MAGIC).map_err(|err| err.to_string())?;
+            }
+
+            for (width, height, bytes) in receiver {
+                let pixels = encode_frame(&mut sink, width, height, &bytes, seed).map_err(|err| err.to_string())?;
+
+                seed = match delta {
+                    true => Seed::after(&pixels),
+                    false => Seed::default(),
+                };
+            }
+
+            sink.flush().map_err(|err| err.to_string())
+        });
+
+        Ok(Self { path: path.to_path_buf(), frames: 0, sender: Some(sender), worker: Some(worker) })
+    }
+
+    /* Reads back what was drawn to the canvas, so it has to be called before `present` */
+    pub fn capture(&mut self, canvas: &WindowCanvas) -> Result<(), String> {
+        let (width, height) = canvas.output_size()?;
+        let bytes = canvas.read_pixels(None, PixelFormatEnum::RGB24)?;
+
+        // A closed channel means the worker failed, `finish` tells why
+        let sent = self.sender.as_ref().is_some_and(|sender| sender.send((width, height, bytes)).is_ok());
+
+        if !sent {
+            return Err(self.join().err().unwrap_or_else(|| "Recording stopped".to_string()));
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    /* Waits until every captured frame is written */
+    pub fn finish(mut self) -> Result<(), String> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<(), String> {
+        self.sender = None;
+
+        match self.worker.take() {
+            Some(worker) => worker.join().unwrap_or_else(|_| Err("Encoder thread panicked".to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    /* Frames still queued are written even when the viewer quits mid recording */
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+/* Hands back the encoded pixels, which the next delta frame is seeded from */
+fn encode_frame<W: Write>(sink: &mut W, width: u32, height: u32, bytes: &[u8], seed: Seed) -> Result<Vec<Pixel>, QOIError> {
+    let header = QOIHeader { width, height, channels: 3, colorspace: 0 };
+    let mut enc = ImageEncoder::seeded(sink, &header, seed);
+
+    enc.write_pixels(bytes)?;
+    enc.finish()?;
+
+    let pixels = bytes
+        .chunks_exact(3)
+        .map(|rgb| Pixel::new(rgb[0], rgb[1], rgb[2], 255))
+        .collect();
+
+    Ok(pixels)
+}
@@ -0,0 +1,178 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+use crate::bitmap::Bitmap;
+use crate::decoder::Pixel;
+
+/* How pixels are interpolated when an image changes size */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Resampler {
+    Nearest,
+    Bilinear,
+    #[default]
+    Lanczos3,
+}
+
+impl FromStr for Resampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Resampler::Nearest),
+            "bilinear" => Ok(Resampler::Bilinear),
+            "lanczos3" => Ok(Resampler::Lanczos3),
+            _ => Err(format!("Unknown resampling filter: {}", s)),
+        }
+    }
+}
+
+impl Resampler {
+    /* Distance from the center past which the filter is zero */
+    fn support(self) -> f32 {
+        match self {
+            Resampler::Nearest => 0.5,
+            Resampler::Bilinear => 1.0,
+            Resampler::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+
+        match self {
+            Resampler::Nearest => (x < 0.5) as u8 as f32,
+            Resampler::Bilinear => (1.0 - x).max(0.0),
+            Resampler::Lanczos3 if x < 3.0 => sinc(x) * sinc(x / 3.0),
+            Resampler::Lanczos3 => 0.0,
+        }
+    }
+
+    /* For every destination pixel along one axis, the first source pixel it
+     * takes from and the weights of it and those following */
+    fn taps(self, src: u32, dst: u32) -> Vec<(usize, Vec<f32>)> {
+        let scale = dst as f32 / src as f32;
+
+        // Shrinking widens the filter, so that every source pixel counts
+        let stretch = (1.0 / scale).max(1.0);
+        let support = self.support() * stretch;
+
+        (0..dst)
+            .map(|i| {
+                let center = (i as f32 + 0.5) / scale;
+
+                if self == Resampler::Nearest {
+                    return ((center as usize).min(src as usize - 1), vec![1.0]);
+                }
+
+                let first = (center - support).floor().max(0.0) as usize;
+                let end = ((center + support).ceil() as usize).min(src as usize);
+
+                let mut weights = (first..end)
+                    .map(|j| self.weight((j as f32 + 0.5 - center) / stretch))
+                    .collect::<Vec<_>>();
+
+                let sum = weights.iter().sum::<f32>();
+
+                match sum.abs() > f32::EPSILON {
+                    true => weights.iter_mut().for_each(|w| *w /= sum),
+                    false => return ((center as usize).min(src as usize - 1), vec![1.0]),
+                }
+
+                (first, weights)
+            })
+            .collect()
+    }
+
+    /* A copy of exactly `width` by `height`. Colors are weighted by their
+     * alpha, so that transparent pixels do not bleed into their neighbours */
+    pub fn resize(self, bitmap: &Bitmap, width: u32, height: u32) -> Bitmap {
+        let (src_width, src_height) = (bitmap.width as usize, bitmap.height as usize);
+
+        // Nothing to take colors from, headers may declare a zero side
+        if src_width == 0 || src_height == 0 {
+            let pixels = vec![Pixel::zero(); width as usize * height as usize];
+            return Bitmap { width, height, pixels, recovered: 0, stopped: None, ..*bitmap };
+        }
+
+        let premultiplied = bitmap
+            .pixels
+            .iter()
+            .map(|p| {
+                let a = p.a as f32 / 255.0;
+                [p.r as f32 * a, p.g as f32 * a, p.b as f32 * a, p.a as f32]
+            })
+            .collect::<Vec<_>>();
+
+        let convolve = |taps: &[f32], values: &mut dyn Iterator<Item = [f32; 4]>| {
+            taps.iter().zip(values).fold([0.0; 4], |sum, (w, v)| [0, 1, 2, 3].map(|c| sum[c] + w * v[c]))
+        };
+
+        // Rows first, then columns of the narrower result
+        let columns = self.taps(bitmap.width, width);
+        let mut narrow = Vec::with_capacity(width as usize * src_height);
+
+        for row in premultiplied.chunks(src_width) {
+            for (first, taps) in &columns {
+                narrow.push(convolve(taps, &mut row[*first..].iter().copied()));
+            }
+        }
+
+        let rows = self.taps(bitmap.height, height);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+
+        for (first, taps) in &rows {
+            for x in 0..width as usize {
+                let column = &mut narrow[first * width as usize + x..].iter().step_by(width as usize).copied();
+                let [r, g, b, a] = convolve(taps, column).map(|v| v.clamp(0.0, 255.0));
+
+                let unpremultiply = |v: f32| match a > 0.0 {
+                    true => (v * 255.0 / a).round().clamp(0.0, 255.0) as u8,
+                    false => 0,
+                };
+
+                pixels.push(Pixel::new(unpremultiply(r), unpremultiply(g), unpremultiply(b), a.round() as u8));
+            }
+        }
+
+        Bitmap { width, height, pixels, recovered: 0, stopped: None, ..*bitmap }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    match x == 0.0 {
+        true => 1.0,
+        false => (PI * x).sin() / (PI * x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(width: u32, height: u32) -> Bitmap {
+        let pixels = (0..width * height).map(|i| Pixel::new(i as u8, 0, 0, 255)).collect();
+        Bitmap { width, height, channels: 4, colorspace: 0, pixels, recovered: 0, stopped: None }
+    }
+
+    #[test]
+    fn empty_source_gives_blank_image() {
+        for resampler in [Resampler::Nearest, Resampler::Bilinear, Resampler::Lanczos3] {
+            for (width, height) in [(0, 5), (5, 0), (0, 0)] {
+                let resized = resampler.resize(&bitmap(width, height), 2, 3);
+
+                assert_eq!((resized.width, resized.height), (2, 3));
+                assert_eq!(resized.pixels, vec![Pixel::zero(); 6]);
+            }
+        }
+    }
+
+    #[test]
+    fn resize_keeps_flat_color() {
+        let flat = Bitmap { pixels: vec![Pixel::new(10, 20, 30, 255); 12], ..bitmap(4, 3) };
+
+        for resampler in [Resampler::Nearest, Resampler::Bilinear, Resampler::Lanczos3] {
+            let resized = resampler.resize(&flat, 7, 2);
+            assert!(resized.pixels.iter().all(|&p| p == Pixel::new(10, 20, 30, 255)), "{:?}", resampler);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+use crate::overlay;
+use crate::view::View;
+
+const OUTLINE: Color = Color::RGBA(0, 255, 255, 220);
+const FILL: Color = Color::RGBA(0, 255, 255, 40);
+
+/* A measurement between two image pixels, both inclusive */
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+}
+
+impl Measurement {
+    pub fn width(&self) -> u32 {
+        self.start.0.abs_diff(self.end.0) + 1
+    }
+
+    pub fn height(&self) -> u32 {
+        self.start.1.abs_diff(self.end.1) + 1
+    }
+
+    /* Distance between the centers of the two end pixels */
+    pub fn diagonal(&self) -> f32 {
+        let dx = self.start.0.abs_diff(self.end.0) as f32;
+        let dy = self.start.1.abs_diff(self.end.1) as f32;
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /* Top left pixel and size of the spanned rectangle */
+    pub fn bounds(&self) -> (u32, u32, u32, u32) {
+        (
+            self.start.0.min(self.end.0),
+            self.start.1.min(self.end.1),
+            self.width(),
+            self.height(),
+        )
+    }
+
+    pub fn describe(&self) -> String {
+        let (x, y, w, h) = self.bounds();
+        format!("{}x{} px at ({}, {}), diagonal {:.2} px", w, h, x, y, self.diagonal())
+    }
+}
+
+/* Tracks a measurement being dragged out with the mouse */
+#[derive(Default)]
+pub struct Ruler {
+    pub measurement: Option<Measurement>,
+    dragging: bool,
+}
+
+impl Ruler {
+    pub fn begin(&mut self, pixel: (u32, u32)) {
+        self.measurement = Some(Measurement { start: pixel, end: pixel });
+        self.dragging = true;
+    }
+
+    pub fn drag(&mut self, pixel: (u32, u32)) {
+        if let (true, Some(m)) = (self.dragging, self.measurement.as_mut()) {
+            m.end = pixel;
+        }
+    }
+
+    pub fn end(&mut self) {
+        self.dragging = false;
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas, view: &View) {
+        let m = match self.measurement {
+            Some(m) => m,
+            None => return,
+        };
+
+        let (x, y, w, h) = m.bounds();
+        let (x0, y0) = view.image_to_window(x as f32, y as f32);
+        let (x1, y1) = view.image_to_window((x + w) as f32, (y + h) as f32);
+        let rect = Rect::new(x0, y0, (x1 - x0).max(1) as u32, (y1 - y0).max(1) as u32);
+
+        overlay::fill_rect(canvas, rect, FILL);
+        overlay::draw_rect(canvas, rect, OUTLINE);
+
+        // Diagonal between the centers of the two end pixels
+        let center = |p: (u32, u32)| view.image_to_window(p.0 as f32 + 0.5, p.1 as f32 + 0.5);
+
+        overlay::with_blend_mode(canvas, BlendMode::Blend, |c| {
+            c.set_draw_color(OUTLINE);
+            c.draw_line(center(m.start), center(m.end)).unwrap();
+        });
+    }
+}
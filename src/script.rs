@@ -0,0 +1,178 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+
+use crate::bitmap::{Bitmap, LoadOptions};
+use crate::filelist::{FileList, Filter};
+use crate::filter::Kernel;
+use crate::resample::Resampler;
+
+/* Batch scripts for the `run` command are written in Rhai (https://rhai.rs),
+ * with images as values of type Bitmap:
+ *
+ *   for path in files("photos") {
+ *       open(path).trim().resize(512).filter("sharpen").save(`out/${name(path)}.qoi`);
+ *   }
+ *
+ *   open(path), load(path)  decodes an image
+ *   image.trim()            crops away borders the color of the top left pixel
+ *   image.resize(512)       scales the longest side to 512, keeping the aspect
+ *   image.resize(640, 480)  scales to exactly that size
+ *   image.filter("sharpen") convolves with a kernel, as --kernel takes them
+ *   image.gray()            keeps only the luminance
+ *   image.save(path)        writes the image, PNG for .png, QOI otherwise,
+ *                           `encode` does the same
+ *   image.width, .height    its size
+ *   files(path), glob(path) the images a path stands for, as files,
+ *                           directories and archives (filtered by --filter)
+ *                           or patterns, ** standing for any number of
+ *                           directories
+ *   name(path), dir(path)   the file name without extension, the directory
+ *
+ * Images change by returning new ones, so steps chain. The paths given after
+ * the script on the command line are in `ARGS` */
+type Image = Arc<Bitmap>;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn open(path: &str, decode: &LoadOptions) -> ScriptResult<Image> {
+    let bitmap = Bitmap::load(path, decode)?;
+
+    if let Some(stop) = &bitmap.stopped {
+        return Err(format!("Failed to decode \"{}\": {}", path, stop.message).into());
+    }
+
+    Ok(Arc::new(bitmap))
+}
+
+/* The images `path` stands for, as strings */
+fn files(path: &str, filter: &Filter) -> ScriptResult<Array> {
+    let files = FileList::from_args([PathBuf::from(path)], filter)
+        .map_err(|err| format!("Failed to open \"{}\": {}", path, err))?;
+
+    Ok(files.iter().map(|path| Dynamic::from(path.to_string_lossy().into_owned())).collect())
+}
+
+/* The image without the rows and columns at its edges that are all the
+ * color of its top left pixel. A single color image is left as it is */
+fn trim(bitmap: &Bitmap) -> Bitmap {
+    let border = match bitmap.pixel_at(0, 0) {
+        Some(border) => border,
+        None => return bitmap.crop(0, 0, bitmap.width, bitmap.height),
+    };
+
+    let width = bitmap.width.max(1) as usize;
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+
+    for (i, &px) in bitmap.pixels.iter().enumerate() {
+        if px != border {
+            let (x, y) = ((i % width) as u32, (i / width) as u32);
+            (left, top) = (left.min(x), top.min(y));
+            (right, bottom) = (right.max(x), bottom.max(y));
+        }
+    }
+
+    match left <= right {
+        true => bitmap.crop(left, top, right - left + 1, bottom - top + 1),
+        false => bitmap.crop(0, 0, bitmap.width, bitmap.height),
+    }
+}
+
+fn side(n: i64) -> ScriptResult<u32> {
+    u32::try_from(n).ok().filter(|&n| n > 0).ok_or_else(|| format!("Invalid size: {}", n).into())
+}
+
+fn resize_longest(bitmap: &Bitmap, longest: i64) -> ScriptResult<Image> {
+    let scale = side(longest)? as f32 / bitmap.width.max(bitmap.height).max(1) as f32;
+    let scaled = |n: u32| ((n as f32 * scale).round() as u32).max(1);
+
+    Ok(Arc::new(Resampler::default().resize(bitmap, scaled(bitmap.width), scaled(bitmap.height))))
+}
+
+fn resize(bitmap: &Bitmap, width: i64, height: i64) -> ScriptResult<Image> {
+    Ok(Arc::new(Resampler::default().resize(bitmap, side(width)?, side(height)?)))
+}
+
+fn filter(bitmap: &Bitmap, kernel: &str) -> ScriptResult<Image> {
+    Ok(Arc::new(kernel.parse::<Kernel>()?.apply(bitmap)))
+}
+
+/* Writes the image, making the directories it goes in first */
+fn save(bitmap: &Bitmap, out: &str) -> ScriptResult<()> {
+    let out = Path::new(out);
+    let fail = |err: std::io::Error| format!("Failed to write \"{}\": {}", out.display(), err);
+
+    if let Some(dir) = out.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(fail)?;
+    }
+
+    bitmap.save(out).map_err(fail)?;
+    println!("{}: {}x{}", out.display(), bitmap.width, bitmap.height);
+
+    Ok(())
+}
+
+fn engine(patterns: &Filter, decode: &LoadOptions) -> Engine {
+    let mut engine = Engine::new();
+    let (patterns, decode) = (patterns.clone(), *decode);
+
+    engine
+        .register_type_with_name::<Image>("Bitmap")
+        .register_get("width", |image: &mut Image| image.width as i64)
+        .register_get("height", |image: &mut Image| image.height as i64)
+        .register_fn("open", move |path: &str| open(path, &decode))
+        .register_fn("load", move |path: &str| open(path, &decode))
+        .register_fn("trim", |image: &mut Image| Arc::new(trim(image)))
+        .register_fn("resize", |image: &mut Image, longest: i64| resize_longest(image, longest))
+        .register_fn("resize", |image: &mut Image, width: i64, height: i64| resize(image, width, height))
+        .register_fn("filter", |image: &mut Image, kernel: &str| filter(image, kernel))
+        .register_fn("gray", |image: &mut Image| Arc::new(image.to_gray()))
+        .register_fn("save", |image: &mut Image, out: &str| save(image, out))
+        .register_fn("encode", |image: &mut Image, out: &str| save(image, out))
+        .register_fn("name", |path: &str| Path::new(path).file_stem().unwrap_or_default().to_string_lossy().into_owned())
+        .register_fn("dir", |path: &str| {
+            let dir = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty());
+            dir.unwrap_or(Path::new(".")).to_string_lossy().into_owned()
+        });
+
+    let glob = patterns.clone();
+    engine
+        .register_fn("files", move |path: &str| files(path, &patterns))
+        .register_fn("glob", move |path: &str| files(path, &glob));
+
+    engine
+}
+
+/* Runs the script that is the first path, with the rest of the paths in
+ * `ARGS`, returning whether it ran to the end */
+pub fn run_all<'p>(mut paths: impl Iterator<Item = &'p Path>, filter: &Filter, decode: &LoadOptions) -> bool {
+    let path = match paths.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("run needs a script");
+            return false;
+        }
+    };
+
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Failed to read script \"{}\": {}", path.display(), err);
+            return false;
+        }
+    };
+
+    let args = paths.map(|path| Dynamic::from(path.to_string_lossy().into_owned())).collect::<Array>();
+
+    let mut scope = Scope::new();
+    scope.push_constant("ARGS", args);
+
+    match engine(filter, decode).run_with_scope(&mut scope, &text) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            false
+        }
+    }
+}
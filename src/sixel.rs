@@ -0,0 +1,134 @@
+use std::collections::BTreeSet;
+
+use crate::bitmap::Bitmap;
+
+// Levels of each channel in the palette, a 6x6x6 color cube
+const LEVELS: u32 = 6;
+
+// Pixels more transparent than this are left out, showing the terminal behind
+const ALPHA_CUTOFF: u8 = 128;
+
+// Runs at least this long are written as a repeat
+const MIN_RUN: usize = 4;
+
+/* The image as a sixel sequence. Each pixel is mapped to the nearest color of
+ * a fixed palette, diffusing the error to its neighbours when dithering */
+pub fn encode(bitmap: &Bitmap, dither: bool) -> Vec<u8> {
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    let indices = quantize(bitmap, dither);
+
+    // P2 = 1 keeps pixels that are not drawn transparent
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height).into_bytes();
+
+    for index in 0..LEVELS * LEVELS * LEVELS {
+        let [r, g, b] = palette_color(index).map(|v| v * 100 / 255);
+        out.extend(format!("#{};2;{};{};{}", index, r, g, b).bytes());
+    }
+
+    let mut sixels = Vec::with_capacity(width);
+
+    for band in (0..height).step_by(6) {
+        let rows = band..(band + 6).min(height);
+
+        let colors = rows
+            .clone()
+            .flat_map(|y| indices[y * width..(y + 1) * width].iter().flatten())
+            .collect::<BTreeSet<_>>();
+
+        for (i, &color) in colors.iter().enumerate() {
+            sixels.clear();
+            sixels.extend((0..width).map(|x| {
+                let bits = rows
+                    .clone()
+                    .enumerate()
+                    .filter(|&(_, y)| indices[y * width + x] == Some(*color))
+                    .fold(0, |bits, (bit, _)| bits | 1 << bit);
+
+                b'?' + bits
+            }));
+
+            // Columns the color does not reach at the end of the band need no sixels
+            let used = sixels.iter().rposition(|&s| s != b'?').map_or(0, |last| last + 1);
+
+            if i > 0 {
+                out.push(b'$');
+            }
+
+            out.extend(format!("#{}", color).bytes());
+            write_runs(&mut out, &sixels[..used]);
+        }
+
+        out.push(b'-');
+    }
+
+    out.extend(b"\x1b\\");
+    out
+}
+
+fn palette_color(index: u32) -> [u32; 3] {
+    let step = 255 / (LEVELS - 1);
+    [index / (LEVELS * LEVELS), index / LEVELS % LEVELS, index % LEVELS].map(|level| level * step)
+}
+
+/* The palette index of every pixel, None for transparent ones */
+fn quantize(bitmap: &Bitmap, dither: bool) -> Vec<Option<u32>> {
+    let width = bitmap.width as usize;
+
+    let mut values = bitmap
+        .pixels
+        .iter()
+        .map(|p| [p.r as f32, p.g as f32, p.b as f32])
+        .collect::<Vec<_>>();
+
+    let step = 255.0 / (LEVELS - 1) as f32;
+    let mut indices = Vec::with_capacity(values.len());
+
+    for i in 0..values.len() {
+        if bitmap.pixels[i].a < ALPHA_CUTOFF {
+            indices.push(None);
+            continue;
+        }
+
+        let levels = values[i].map(|v| (v / step).round().clamp(0.0, (LEVELS - 1) as f32));
+        indices.push(Some(levels.iter().fold(0, |index, &level| index * LEVELS + level as u32)));
+
+        if !dither {
+            continue;
+        }
+
+        // Floyd-Steinberg, within the row and into the next one
+        let error = [0, 1, 2].map(|c| values[i][c] - levels[c] * step);
+        let x = i % width;
+
+        let mut spread = |j: usize, weight: f32| {
+            if let Some(value) = values.get_mut(j) {
+                for c in 0..3 {
+                    value[c] += error[c] * weight;
+                }
+            }
+        };
+
+        if x + 1 < width {
+            spread(i + 1, 7.0 / 16.0);
+            spread(i + width + 1, 1.0 / 16.0);
+        }
+        if x > 0 {
+            spread(i + width - 1, 3.0 / 16.0);
+        }
+        spread(i + width, 5.0 / 16.0);
+    }
+
+    indices
+}
+
+/* Sixels with repeats of the same one run length encoded */
+fn write_runs(out: &mut Vec<u8>, sixels: &[u8]) {
+    for run in sixels.chunk_by(|a, b| a == b) {
+        if run.len() >= MIN_RUN {
+            out.extend(format!("!{}", run.len()).bytes());
+            out.push(run[0]);
+        } else {
+            out.extend(run);
+        }
+    }
+}
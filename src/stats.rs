@@ -0,0 +1,269 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::decoder::{EvaluatedChunk, ImageDecoder, Op, Pixel, QOIHeader};
+use crate::encoder::EncodeStats;
+use crate::input;
+
+/* Chunk counts of a QOI file, indexed like `Op::ALL` */
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChunkStats {
+    pub header: QOIHeader,
+    pub file_size: u64,
+    pub chunks: [u64; 6],
+    pub bytes: [u64; 6],
+    pub pixels: [u64; 6],
+}
+
+impl ChunkStats {
+    pub fn compute(path: &Path) -> Result<Self, String> {
+        Self::compute_with(path, |_| {})
+    }
+
+    /* Also hands every decoded pixel to `on_pixel`, for statistics gathered
+     * in the same pass */
+    pub fn compute_with(path: &Path, mut on_pixel: impl FnMut(Pixel)) -> Result<Self, String> {
+        let data = input::read(path)
+            .map_err(|err| format!("Failed to read \"{}\": {}", path.display(), err))?;
+
+        let dec = ImageDecoder::new(&data[..])
+            .map_err(|err| format!("Failed to decode \"{}\": {}", path.display(), err))?;
+
+        let mut stats = Self {
+            header: dec.header().clone(),
+            file_size: data.len() as u64,
+            chunks: [0; 6],
+            bytes: [0; 6],
+            pixels: [0; 6],
+        };
+
+        let mut chunks = dec.chunks_iter();
+
+        loop {
+            match chunks.next_chunk() {
+                EvaluatedChunk::Ok(px) => {
+                    on_pixel(px);
+                    let chunk = chunks.last_chunk().unwrap();
+                    let op = chunk.op() as usize;
+
+                    if chunks.starts_chunk() {
+                        stats.chunks[op] += 1;
+                        stats.bytes[op] += chunk.get_size() as u64;
+                    }
+
+                    stats.pixels[op] += 1;
+                },
+                EvaluatedChunk::EndMarker => break,
+                EvaluatedChunk::Faulty(err) => {
+                    return Err(format!("Failed to decode \"{}\": {}", path.display(), err));
+                },
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /* The same figures for a file as it was encoded, without reading it back */
+    pub fn from_encoder(header: QOIHeader, file_size: u64, stats: EncodeStats) -> Self {
+        Self { header, file_size, chunks: stats.chunks, bytes: stats.bytes, pixels: stats.pixels }
+    }
+
+    pub fn average_run_length(&self) -> f64 {
+        let run = Op::Run as usize;
+
+        match self.chunks[run] {
+            0 => 0.0,
+            count => self.pixels[run] as f64 / count as f64,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut lines = vec![
+            format!("  {:<6} {:>10} {:>10} {:>10}", "op", "chunks", "bytes", "pixels"),
+        ];
+
+        for (i, op) in Op::ALL.iter().enumerate() {
+            lines.push(format!(
+                "  {:<6} {:>10} {:>10} {:>10}",
+                op.name(), self.chunks[i], self.bytes[i], self.pixels[i]
+            ));
+        }
+
+        lines.push(format!(
+            "  {:<6} {:>10} {:>10} {:>10}",
+            "total",
+            self.chunks.iter().sum::<u64>(),
+            self.bytes.iter().sum::<u64>(),
+            self.pixels.iter().sum::<u64>()
+        ));
+
+        lines.push(format!("  average run length: {:.2}", self.average_run_length()));
+
+        let decoded = self.header.width as u64 * self.header.height as u64 * self.header.channels as u64;
+        if decoded > 0 {
+            lines.push(format!(
+                "  compression ratio:  {:.1}% ({} of {} bytes)",
+                100.0 * self.file_size as f64 / decoded as f64, self.file_size, decoded
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
+
+/* The distinct colors of an image and how much of it is see-through */
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorStats {
+    unique: HashSet<Pixel>,
+    pixels: u64,
+    transparent: u64, // Alpha of 0
+    semi_transparent: u64, // Alpha between 0 and 255
+}
+
+impl ColorStats {
+    pub fn add(&mut self, px: Pixel) {
+        self.unique.insert(px);
+        self.pixels += 1;
+
+        match px.a {
+            0 => self.transparent += 1,
+            255 => {},
+            _ => self.semi_transparent += 1,
+        }
+    }
+
+    pub fn unique_colors(&self) -> usize {
+        self.unique.len()
+    }
+
+    /* Whether an 8-bit palette holds every color, alpha included */
+    pub fn fits_palette(&self) -> bool {
+        self.unique.len() <= 256
+    }
+
+    pub fn describe(&self) -> String {
+        let percent = |count: u64| 100.0 * count as f64 / self.pixels.max(1) as f64;
+
+        [
+            format!("  unique colors:      {}", self.unique_colors()),
+            format!("  fits 8-bit palette: {}", if self.fits_palette() { "yes" } else { "no" }),
+            format!("  transparent:        {} ({:.2}%)", self.transparent, percent(self.transparent)),
+            format!("  semi-transparent:   {} ({:.2}%)", self.semi_transparent, percent(self.semi_transparent)),
+        ]
+        .join("\n")
+    }
+}
+
+const CHANNEL_NAMES: [&str; 4] = ["R", "G", "B", "A"];
+
+/* Min, max, mean and standard deviation of each channel, in RGBA order */
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelStats {
+    pub min: [u8; 4],
+    pub max: [u8; 4],
+    sum: [u64; 4],
+    sum_squared: [u64; 4],
+    pixels: u64,
+}
+
+impl Default for ChannelStats {
+    fn default() -> Self {
+        Self { min: [255; 4], max: [0; 4], sum: [0; 4], sum_squared: [0; 4], pixels: 0 }
+    }
+}
+
+impl ChannelStats {
+    pub fn add(&mut self, px: Pixel) {
+        for (c, value) in [px.r, px.g, px.b, px.a].into_iter().enumerate() {
+            self.min[c] = self.min[c].min(value);
+            self.max[c] = self.max[c].max(value);
+            self.sum[c] += value as u64;
+            self.sum_squared[c] += value as u64 * value as u64;
+        }
+
+        self.pixels += 1;
+    }
+
+    pub fn mean(&self, channel: usize) -> f64 {
+        self.sum[channel] as f64 / self.pixels.max(1) as f64
+    }
+
+    pub fn stddev(&self, channel: usize) -> f64 {
+        let mean = self.mean(channel);
+        let mean_squared = self.sum_squared[channel] as f64 / self.pixels.max(1) as f64;
+
+        (mean_squared - mean * mean).max(0.0).sqrt()
+    }
+
+    /* Whether every pixel has the same value in the channel, e.g. an alpha
+     * that is opaque throughout */
+    pub fn is_constant(&self, channel: usize) -> bool {
+        self.min[channel] == self.max[channel]
+    }
+
+    /* Alpha is left out of images with 3 channels */
+    pub fn describe(&self, channels: u8) -> String {
+        let mut lines = vec![
+            format!("  {:<6} {:>5} {:>5} {:>8} {:>8}", "chan", "min", "max", "mean", "stddev"),
+        ];
+
+        for (c, name) in CHANNEL_NAMES.iter().enumerate().take(channels as usize) {
+            lines.push(format!(
+                "  {:<6} {:>5} {:>5} {:>8.2} {:>8.2}{}",
+                name, self.min[c], self.max[c], self.mean(c), self.stddev(c),
+                if self.is_constant(c) { "  constant" } else { "" }
+            ));
+        }
+
+        lines.join("\n")
+    }
+}
+
+/* Prints the statistics of every file, returning whether all of them could be
+ * decoded. The color and channel statistics are gathered while decoding, when
+ * asked for */
+pub fn print_all<'p>(paths: impl Iterator<Item = &'p Path>, color_stats: bool, channel_stats: bool) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        let mut colors = ColorStats::default();
+        let mut channels = ChannelStats::default();
+
+        let stats = match color_stats || channel_stats {
+            true => ChunkStats::compute_with(path, |px| {
+                if color_stats {
+                    colors.add(px);
+                }
+                if channel_stats {
+                    channels.add(px);
+                }
+            }),
+            false => ChunkStats::compute(path),
+        };
+
+        match stats {
+            Ok(stats) => {
+                println!(
+                    "{}: {}x{}, {} channels\n{}",
+                    path.display(), stats.header.width, stats.header.height, stats.header.channels,
+                    stats.describe()
+                );
+
+                if color_stats {
+                    println!("{}", colors.describe());
+                }
+
+                if channel_stats {
+                    println!("{}", channels.describe(stats.header.channels));
+                }
+            },
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
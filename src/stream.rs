@@ -0,0 +1,460 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::anim;
+use crate::bitmap::Bitmap;
+use crate::decoder::{EvaluatedChunk, ImageDecoder, Limits, Pixel, QOIError, Seed, QOI_END_MARKER};
+use crate::input;
+
+pub const DEFAULT_FPS: f32 = 30.0;
+
+/* Delta streams start with this, after the animation header if there is one.
+ * Every frame but the first is then coded against the one before it, starting
+ * from the `Seed` that frame ends in. Screen captures, where most colors carry
+ * over from frame to frame, shrink the most */
+pub const DELTA_MAGIC: &[u8; 4] = b"qoid";
+
+// Frames decoded ahead of the one on screen. Keeps memory bounded when the
+// producer is faster than playback, the pipe then simply fills up
+const FRAME_QUEUE: usize = 4;
+
+// Decoded pixels kept around for stepping back and looping
+const HISTORY_BYTES: usize = 256 << 20;
+
+const MIN_SPEED: f32 = 1.0 / 8.0;
+const MAX_SPEED: f32 = 8.0;
+
+// Span of playback that the statistics are taken over
+const STATS_WINDOW: Duration = Duration::from_secs(1);
+
+/* A frame as it came out of the decoder */
+struct Decoded {
+    bitmap: Bitmap,
+    bytes: usize, // Size in the stream, header and end marker included
+    decode_time: Duration,
+    delay: Option<Duration>, // Set for the frames of animations
+}
+
+type Frame = Result<Decoded, String>;
+
+struct Entry {
+    bitmap: Arc<Bitmap>,
+    bytes: usize,
+    decode_time: Duration,
+    delay: Option<Duration>,
+}
+
+/* How playback is keeping up, over the last STATS_WINDOW */
+pub struct StreamStats {
+    pub fps: f32,
+    pub dropped: u64,          // Frame slots that passed without a frame ready, since the start
+    pub decode_time: Duration, // Of the frame on screen
+    pub bitrate: f64,          // Bits per second of the stream, as played
+}
+
+pub enum StreamEvent {
+    Frame(Arc<Bitmap>),
+    Ended(Option<String>), // Why the stream broke off, if it did not end cleanly
+}
+
+/* Back to back QOI images, e.g. from `ffmpeg -f image2pipe -vcodec qoi -`,
+ * decoded on a background thread and handed out at a fixed frame rate.
+ * Animations bring their own frame delays and loop by default. Recent
+ * frames are kept so that playback can step back and loop */
+pub struct FrameStream {
+    path: Option<PathBuf>, // None for standard input, which cannot be read again
+    limits: Limits,
+    receiver: Receiver<Frame>,
+
+    history: VecDeque<Entry>,
+    history_bytes: usize,
+    evicted: u64,    // Frames dropped from the front of the history
+    position: usize, // Index of the frame on screen in the history
+
+    exhausted: bool,       // Nothing more will come from the decoder
+    error: Option<String>, // Why the decoder gave up, if it did
+    reported: bool,        // Whether the end was already handed out
+
+    shown: VecDeque<(Instant, usize)>, // When recent frames went on screen, and their sizes
+    dropped: u64,
+
+    fps: f32,
+    speed: f32,
+    next_due: Instant,
+
+    pub paused: bool,
+    pub looping: bool,
+}
+
+impl FrameStream {
+    /* Starts decoding the file, or standard input for "-" */
+    pub fn open(path: &Path, fps: f32, limits: Limits) -> Result<Self, String> {
+        let receiver = spawn_decoder(path, limits)?;
+
+        Ok(Self {
+            path: (!input::is_stdin(path)).then(|| path.to_path_buf()),
+            limits,
+            receiver,
+
+            history: VecDeque::new(),
+            history_bytes: 0,
+            evicted: 0,
+            position: 0,
+
+            exhausted: false,
+            error: None,
+            reported: false,
+
+            shown: VecDeque::new(),
+            dropped: 0,
+
+            fps: fps.max(0.1),
+            speed: 1.0,
+            next_due: Instant::now(),
+
+            paused: false,
+            looping: false,
+        })
+    }
+
+    /* Waits for the first frame, which the viewer needs before it can open */
+    pub fn first_frame(&mut self) -> Result<Arc<Bitmap>, String> {
+        let frame = self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err("The stream holds no frames".to_string()))?;
+
+        self.looping = frame.delay.is_some();
+
+        self.push(frame);
+        self.next_due = Instant::now() + self.interval();
+
+        Ok(self.current())
+    }
+
+    /* The next frame once it is due, or the end of the stream, which is only
+     * reported once. A frame that arrives late is shown right away, and the
+     * ones after it are timed from there */
+    pub fn poll(&mut self) -> Option<StreamEvent> {
+        let now = Instant::now();
+
+        if self.paused || now < self.next_due {
+            return None;
+        }
+
+        let event = self.advance()?;
+
+        if matches!(event, StreamEvent::Frame(_)) {
+            let interval = self.interval();
+
+            // Every whole interval the frame is late by is a frame that should have been shown
+            let late = now.saturating_duration_since(self.next_due);
+            self.dropped += (late.as_secs_f32() / interval.as_secs_f32()) as u64;
+
+            self.next_due = (self.next_due + interval).max(now);
+        }
+
+        Some(event)
+    }
+
+    /* When the next frame is due, for the main loop to sleep until */
+    pub fn deadline(&self) -> Option<Instant> {
+        let waiting = self.paused || (self.reported && !self.looping);
+        (!waiting).then_some(self.next_due)
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.next_due = Instant::now();
+    }
+
+    /* Single steps pause playback */
+    pub fn step_forward(&mut self) -> Option<StreamEvent> {
+        self.paused = true;
+        self.advance()
+    }
+
+    pub fn step_back(&mut self) -> Option<StreamEvent> {
+        self.paused = true;
+
+        if self.position == 0 {
+            return None;
+        }
+
+        self.position -= 1;
+        Some(StreamEvent::Frame(self.current()))
+    }
+
+    /* Doubles or halves the playback speed */
+    pub fn change_speed(&mut self, faster: bool) {
+        let speed = if faster { self.speed * 2.0 } else { self.speed / 2.0 };
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn toggle_loop(&mut self) {
+        self.looping = !self.looping;
+
+        // An ended stream starts over right away
+        self.reported = false;
+    }
+
+    /* Playback speed and the frame rate it results in, e.g. "2x (60 fps)" */
+    pub fn describe_speed(&self) -> String {
+        format!("{}x ({:.1} fps)", self.speed, self.target_fps())
+    }
+
+    /* 1 based number of the frame on screen, counted from the start of the stream */
+    pub fn frame_number(&self) -> u64 {
+        self.evicted + self.position as u64 + 1
+    }
+
+    /* Frames decoded so far */
+    pub fn decoded(&self) -> u64 {
+        self.evicted + self.history.len() as u64
+    }
+
+    pub fn target_fps(&self) -> f32 {
+        self.fps * self.speed
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        let now = Instant::now();
+        let recent = self.shown.iter().filter(|&&(at, _)| now.duration_since(at) <= STATS_WINDOW);
+
+        let (frames, bytes) = recent.fold((0, 0), |(frames, bytes), (_, size)| (frames + 1, bytes + size));
+        let window = STATS_WINDOW.as_secs_f64();
+
+        StreamStats {
+            fps: (frames as f64 / window) as f32,
+            dropped: self.dropped,
+            decode_time: self.history[self.position].decode_time,
+            bitrate: bytes as f64 * 8.0 / window,
+        }
+    }
+
+    /* How long the frame on screen stays there */
+    fn interval(&self) -> Duration {
+        match self.history.get(self.position).and_then(|entry| entry.delay) {
+            Some(delay) => delay.div_f32(self.speed),
+            None => Duration::from_secs_f32(1.0 / (self.fps * self.speed)),
+        }
+    }
+
+    /* Also counts the frame as shown */
+    fn current(&mut self) -> Arc<Bitmap> {
+        let now = Instant::now();
+        let entry = &self.history[self.position];
+
+        self.shown.push_back((now, entry.bytes));
+
+        while self.shown.front().is_some_and(|&(at, _)| now.duration_since(at) > STATS_WINDOW) {
+            self.shown.pop_front();
+        }
+
+        entry.bitmap.clone()
+    }
+
+    /* Moves on to the next frame: one stepped back over, a newly decoded one,
+     * or the first one again when looping */
+    fn advance(&mut self) -> Option<StreamEvent> {
+        if self.position + 1 < self.history.len() {
+            self.position += 1;
+            return Some(StreamEvent::Frame(self.current()));
+        }
+
+        if !self.exhausted {
+            match self.receiver.try_recv() {
+                Ok(Ok(frame)) => {
+                    self.push(frame);
+                    return Some(StreamEvent::Frame(self.current()));
+                },
+                Ok(Err(err)) => {
+                    self.exhausted = true;
+                    self.error = Some(err);
+                },
+                Err(TryRecvError::Disconnected) => self.exhausted = true,
+                Err(TryRecvError::Empty) => return None,
+            }
+        }
+
+        // A broken stream is not looped, it would break at the same frame again
+        if self.looping && self.error.is_none() {
+            if self.evicted == 0 {
+                self.position = 0;
+                return Some(StreamEvent::Frame(self.current()));
+            }
+
+            // Too long to keep in memory, so files are decoded again
+            if let Some(receiver) = self.path.as_deref().and_then(|path| spawn_decoder(path, self.limits).ok()) {
+                self.receiver = receiver;
+                self.exhausted = false;
+                return None;
+            }
+        }
+
+        if self.reported {
+            return None;
+        }
+
+        self.reported = true;
+        Some(StreamEvent::Ended(self.error.clone()))
+    }
+
+    /* Appends a newly decoded frame and shows it, dropping the oldest frames
+     * while the history is over its budget */
+    fn push(&mut self, frame: Decoded) {
+        self.history_bytes += size_of_frame(&frame.bitmap);
+        self.history.push_back(Entry {
+            bitmap: Arc::new(frame.bitmap),
+            bytes: frame.bytes,
+            decode_time: frame.decode_time,
+            delay: frame.delay,
+        });
+
+        while self.history.len() > 1 && self.history_bytes > HISTORY_BYTES {
+            if let Some(old) = self.history.pop_front() {
+                self.history_bytes -= size_of_frame(&old.bitmap);
+                self.evicted += 1;
+            }
+        }
+
+        self.position = self.history.len() - 1;
+    }
+}
+
+fn size_of_frame(frame: &Bitmap) -> usize {
+    frame.pixels.len() * std::mem::size_of::<Pixel>()
+}
+
+/* Decodes frames on a background thread until the stream ends, fails or
+ * nobody is listening anymore */
+fn spawn_decoder(path: &Path, limits: Limits) -> Result<Receiver<Frame>, String> {
+    let (sender, receiver) = mpsc::sync_channel(FRAME_QUEUE);
+
+    let source: Box<dyn BufRead + Send> = if input::is_stdin(path) {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        let file = File::open(path)
+            .map_err(|err| format!("Failed to open file: \"{}\": {}", path.display(), err))?;
+
+        Box::new(BufReader::new(file))
+    };
+
+    thread::spawn(move || {
+        let mut source = source;
+
+        let delays = match anim::read_header(&mut source) {
+            Ok(delays) => delays,
+            Err(err) => {
+                let _ = sender.send(Err(err));
+                return;
+            }
+        };
+
+        let delta = match read_delta_marker(&mut source) {
+            Ok(delta) => delta,
+            Err(err) => {
+                let _ = sender.send(Err(err));
+                return;
+            }
+        };
+
+        let mut seed = Seed::default();
+
+        for i in 0.. {
+            // Whatever follows the last frame of an animation is not part of it
+            let delay = match &delays {
+                Some(delays) if i >= delays.len() => break,
+                Some(delays) => Some(delays[i]),
+                None => None,
+            };
+
+            let frame = match read_frame(&mut source, limits, seed.clone()) {
+                Ok(Some(decoded)) => {
+                    if delta {
+                        seed = Seed::after(&decoded.bitmap.pixels);
+                    }
+
+                    Ok(Decoded { delay, ..decoded })
+                },
+                Ok(None) if delays.is_some() => {
+                    Err(format!("Animation ended after {} of its {} frames", i, delays.as_ref().map_or(0, Vec::len)))
+                },
+                Ok(None) => break,
+                Err(err) => Err(err),
+            };
+
+            let failed = frame.is_err();
+
+            if sender.send(frame).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
+
+/* Consumes the delta marker, if the stream starts with one */
+fn read_delta_marker<R: BufRead>(source: &mut R) -> Result<bool, String> {
+    let delta = source
+        .fill_buf()
+        .map_err(|err| format!("Failed to read: {}", err))?
+        .starts_with(DELTA_MAGIC);
+
+    if delta {
+        source.consume(DELTA_MAGIC.len());
+    }
+
+    Ok(delta)
+}
+
+/* Decodes one image, leaving the source right after its end marker so that
+ * the next one can be read from there. None once the stream has ended */
+fn read_frame<R: Read>(source: &mut R, limits: Limits, seed: Seed) -> Result<Option<Decoded>, String> {
+    let start = Instant::now();
+
+    let dec = match ImageDecoder::with_limits(&mut *source, limits) {
+        Ok(dec) => dec,
+        Err(QOIError::IO(err)) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(format!("Failed to decode frame: {}", err)),
+    };
+
+    let header = dec.header().clone();
+    let count = header.width as usize * header.height as usize;
+
+    let mut pixels = Vec::with_capacity(count);
+    let mut chunks = dec.chunks_iter_seeded(seed);
+
+    chunks
+        .decode_into(&mut pixels, count as u64)
+        .map_err(|err| format!("Failed to decode frame: {}", err))?;
+
+    // Without this the next frame would start in the middle of this one
+    match chunks.next_chunk() {
+        EvaluatedChunk::EndMarker => {},
+        _ => return Err("No end marker after the last pixel of the frame".to_string()),
+    }
+
+    let bitmap = Bitmap {
+        width: header.width,
+        height: header.height,
+        channels: header.channels,
+        colorspace: header.colorspace,
+        pixels,
+        recovered: 0,
+        stopped: None,
+    };
+
+    Ok(Some(Decoded {
+        bitmap,
+        bytes: chunks.position() as usize + QOI_END_MARKER.len(),
+        decode_time: start.elapsed(),
+        delay: None,
+    }))
+}
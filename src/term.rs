@@ -0,0 +1,57 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::bitmap::{Bitmap, LoadOptions};
+
+/* Largest image printed to the terminal, in pixels */
+pub const DEFAULT_SIZE: (u32, u32) = (800, 600);
+
+/* Decodes the file and scales it down to at most `max_size` */
+pub fn prepare(path: &Path, options: &LoadOptions, max_size: (u32, u32)) -> Result<Bitmap, String> {
+    let bitmap = Bitmap::load(path.to_str().unwrap_or_default(), options)?;
+
+    if let Some(stop) = &bitmap.stopped {
+        return Err(format!("Failed to decode \"{}\": {}", path.display(), stop.message));
+    }
+
+    if bitmap.pixels.is_empty() {
+        return Err(format!("\"{}\" is an empty image, {}x{}", path.display(), bitmap.width, bitmap.height));
+    }
+
+    Ok(bitmap.fit_within(max_size.0, max_size.1))
+}
+
+/* Prints every file to stdout with `encode`, each image on a line of its own */
+pub fn print_all<'p>(
+    paths: impl Iterator<Item = &'p Path>,
+    options: &LoadOptions,
+    max_size: (u32, u32),
+    encode: impl Fn(&Bitmap) -> Vec<u8>,
+) -> bool {
+    let mut ok = true;
+    let mut stdout = std::io::stdout().lock();
+
+    for path in paths {
+        let bitmap = match prepare(path, options, max_size) {
+            Ok(bitmap) => bitmap,
+            Err(err) => {
+                eprintln!("{}", err);
+                ok = false;
+                continue;
+            }
+        };
+
+        let written = stdout
+            .write_all(&encode(&bitmap))
+            .and_then(|_| writeln!(stdout))
+            .and_then(|_| stdout.flush());
+
+        // Nothing more can be shown once the terminal went away
+        if let Err(err) = written {
+            eprintln!("Failed to write to the terminal: {}", err);
+            return false;
+        }
+    }
+
+    ok
+}
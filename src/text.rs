@@ -0,0 +1,147 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+use crate::overlay;
+
+/* A 3x5 pixel font embedded in the binary, so that overlays can show text
+ * without depending on a font library. Letters are upper case only */
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+// Rows top to bottom, the highest of the 3 bits being the leftmost pixel
+const GLYPHS: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]),
+    (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+    ('[', [0b110, 0b100, 0b100, 0b100, 0b110]),
+    (']', [0b011, 0b001, 0b001, 0b001, 0b011]),
+    ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+    ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('*', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('"', [0b101, 0b101, 0b000, 0b000, 0b000]),
+    ('|', [0b010, 0b010, 0b010, 0b010, 0b010]),
+    ('\\', [0b100, 0b100, 0b010, 0b001, 0b001]),
+    (';', [0b000, 0b010, 0b000, 0b010, 0b100]),
+    ('$', [0b011, 0b110, 0b010, 0b011, 0b110]),
+    ('&', [0b010, 0b101, 0b010, 0b101, 0b011]),
+    ('@', [0b111, 0b101, 0b111, 0b100, 0b011]),
+    ('{', [0b011, 0b010, 0b110, 0b010, 0b011]),
+    ('}', [0b110, 0b010, 0b011, 0b010, 0b110]),
+    ('~', [0b000, 0b011, 0b110, 0b000, 0b000]),
+    ('^', [0b010, 0b101, 0b000, 0b000, 0b000]),
+];
+
+fn glyph(c: char) -> [u8; 5] {
+    if c == ' ' {
+        return [0; 5];
+    }
+
+    let c = c.to_ascii_uppercase();
+
+    GLYPHS
+        .iter()
+        .find(|(g, _)| *g == c)
+        .or_else(|| GLYPHS.iter().find(|(g, _)| *g == '?'))
+        .map(|&(_, rows)| rows)
+        .unwrap()
+}
+
+/* Width and height of a line of text, in window pixels */
+pub fn size(text: &str, scale: u32) -> (u32, u32) {
+    let chars = text.chars().count() as u32;
+    let width = (chars * (GLYPH_WIDTH + 1)).saturating_sub(1);
+
+    (width * scale, GLYPH_HEIGHT * scale)
+}
+
+/* Size of several lines drawn `line_height` apart */
+pub fn block_size(lines: &[&str], scale: u32) -> (u32, u32) {
+    let width = lines.iter().map(|line| size(line, scale).0).max().unwrap_or(0);
+    let height = (lines.len() as u32 * line_height(scale)).saturating_sub(2 * scale);
+
+    (width, height)
+}
+
+/* Distance between the tops of two lines */
+pub fn line_height(scale: u32) -> u32 {
+    (GLYPH_HEIGHT + 2) * scale
+}
+
+/* Draws a line of text with its top left corner at `(x, y)`. Every font pixel
+ * becomes a `scale` sized square */
+pub fn draw(canvas: &mut WindowCanvas, text: &str, (x, y): (i32, i32), scale: u32, color: Color) {
+    let step = ((GLYPH_WIDTH + 1) * scale) as i32;
+
+    let mut rects = Vec::new();
+
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as i32 * step;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                    rects.push(Rect::new(
+                        left + (col * scale) as i32,
+                        y + (row as u32 * scale) as i32,
+                        scale,
+                        scale
+                    ));
+                }
+            }
+        }
+    }
+
+    overlay::with_blend_mode(canvas, BlendMode::Blend, |c| {
+        c.set_draw_color(color);
+        c.fill_rects(&rects).unwrap();
+    });
+}
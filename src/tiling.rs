@@ -0,0 +1,78 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+
+use crate::overlay;
+
+const SEAM_COLOR: Color = Color::RGBA(255, 0, 255, 160);
+
+// Tiles smaller than this would take too many copies to fill the window,
+// so only the 3x3 grid around the image is drawn then
+const MIN_TILE_SIZE: u32 = 8;
+
+/* Repeating the image to check that a texture tiles seamlessly */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Tiling {
+    Off,
+    On,
+    Seams, // Also outlines where the copies meet
+}
+
+impl Tiling {
+    pub fn cycle(self) -> Self {
+        match self {
+            Tiling::Off => Tiling::On,
+            Tiling::On => Tiling::Seams,
+            Tiling::Seams => Tiling::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Tiling::Off => "tiling off",
+            Tiling::On => "tiling",
+            Tiling::Seams => "tiling with seams",
+        }
+    }
+}
+
+/* Repeats the texture in every direction from `dest`, where the image itself
+ * is drawn, until the viewport is covered */
+pub fn draw(canvas: &mut WindowCanvas, texture: &Texture, dest: Rect, tiling: Tiling) {
+    let viewport = canvas.viewport();
+    let (w, h) = (dest.width() as i32, dest.height() as i32);
+
+    let (columns, rows) = if dest.width() < MIN_TILE_SIZE || dest.height() < MIN_TILE_SIZE {
+        ((dest.x() - w)..=(dest.x() + w), (dest.y() - h)..=(dest.y() + h))
+    } else {
+        // The first copy that reaches into the viewport from the left and top
+        let first_x = dest.x() - (dest.x() + w - 1).div_euclid(w) * w;
+        let first_y = dest.y() - (dest.y() + h - 1).div_euclid(h) * h;
+
+        (first_x..=viewport.width() as i32, first_y..=viewport.height() as i32)
+    };
+
+    let columns = columns.step_by(w as usize).collect::<Vec<_>>();
+    let rows = rows.step_by(h as usize).collect::<Vec<_>>();
+
+    for &y in &rows {
+        for &x in &columns {
+            canvas.copy(texture, None, Rect::new(x, y, dest.width(), dest.height())).unwrap();
+        }
+    }
+
+    if tiling != Tiling::Seams {
+        return;
+    }
+
+    let (left, top) = (columns[0], rows[0]);
+    let (right, bottom) = (columns[columns.len() - 1] + w, rows[rows.len() - 1] + h);
+
+    for &x in columns.iter().chain([&right]) {
+        overlay::fill_rect(canvas, Rect::new(x, top, 1, (bottom - top) as u32), SEAM_COLOR);
+    }
+
+    for &y in rows.iter().chain([&bottom]) {
+        overlay::fill_rect(canvas, Rect::new(left, y, (right - left) as u32, 1), SEAM_COLOR);
+    }
+}
@@ -0,0 +1,141 @@
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::path::Path;
+
+use crate::decoder::{DecodeChunks, EvaluatedChunk, ImageDecoder, QOIChunk};
+use crate::input;
+
+// Pixels that have to decode after a fault for decoding to be back on track
+const RECOVERY_PIXELS: usize = 16;
+
+/* The payload of a chunk in readable form. Diffs are printed signed */
+fn describe(chunk: &QOIChunk) -> String {
+    let op = chunk.op().name();
+
+    match *chunk {
+        QOIChunk::ColorRGB(p) => format!("{} {} {} {}", op, p.r, p.g, p.b),
+        QOIChunk::ColorRGBA(p) => format!("{} {} {} {} {}", op, p.r, p.g, p.b, p.a),
+        QOIChunk::Index(index) => format!("{} {}", op, index),
+        QOIChunk::Diff(dr, dg, db) => format!("{} {:+} {:+} {:+}", op, dr as i8, dg as i8, db as i8),
+        QOIChunk::Luma { diff_green, drdg, dbdg } => {
+            format!("{} {:+} {:+} {:+}", op, diff_green as i8, drdg as i8, dbdg as i8)
+        },
+        // Stored biased by one
+        QOIChunk::Run(length) => format!("{} x{}", op, length as u32 + 1),
+    }
+}
+
+/* After a fault, the fewest bytes to skip, at most a chunk's worth, from
+ * which decoding carries on without another fault. The decoder is left
+ * where it was */
+fn recovery_skip<R: Read + Seek>(chunks: &mut DecodeChunks<R>) -> Option<u64> {
+    let state = chunks.snapshot();
+
+    let decodes_on = |chunks: &mut DecodeChunks<R>| {
+        for _ in 0..RECOVERY_PIXELS {
+            match chunks.next_chunk() {
+                EvaluatedChunk::Ok(_) => {},
+                EvaluatedChunk::EndMarker => return true,
+                EvaluatedChunk::Faulty(_) => return false,
+            }
+        }
+
+        true
+    };
+
+    (1..=8).find(|&bytes| {
+        chunks.skip_bytes(bytes);
+        let ok = decodes_on(chunks);
+
+        chunks.restore(&state).is_ok() && ok
+    })
+}
+
+/* Prints one line per chunk: file offset, raw bytes, op and the resulting
+ * pixel. Chunks that do not decode are marked as faults and skipped, as long
+ * as decoding can be picked up again after them */
+pub fn trace(path: &Path, out: &mut impl Write) -> Result<(), String> {
+    let data = input::read(path)
+        .map_err(|err| format!("Failed to read \"{}\": {}", path.display(), err))?;
+
+    let dec = ImageDecoder::new(Cursor::new(&data[..]))
+        .map_err(|err| format!("Failed to decode \"{}\": {}", path.display(), err))?;
+
+    let header = dec.header().clone();
+
+    let io_err = |err: io::Error| format!("Failed to write trace: {}", err);
+
+    writeln!(
+        out, "{}: {}x{}, {} channels, colorspace {}",
+        path.display(), header.width, header.height, header.channels, header.colorspace
+    ).map_err(io_err)?;
+    writeln!(out, "{:>10}  {:<15} {:<20} pixel", "offset", "bytes", "op").map_err(io_err)?;
+
+    let mut chunks = dec.chunks_iter();
+    let mut faults = 0;
+
+    loop {
+        match chunks.next_chunk() {
+            // The other pixels of a run have no chunk of their own
+            EvaluatedChunk::Ok(_) if !chunks.starts_chunk() => {},
+            EvaluatedChunk::Ok(pixel) => {
+                let chunk = chunks.last_chunk().unwrap();
+                let offset = chunks.position() - chunk.get_size() as u64;
+
+                let bytes = chunks
+                    .chunk_bytes()
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                writeln!(out, "{:>10}  {:<15} {:<20} {}", offset, bytes, describe(chunk), pixel.to_hex())
+                    .map_err(io_err)?;
+            },
+            EvaluatedChunk::EndMarker => {
+                writeln!(out, "{:>10}  {:<15} END", chunks.position(), "00 00 00 00 00 00 00 01")
+                    .map_err(io_err)?;
+
+                return match faults {
+                    0 => Ok(()),
+                    n => Err(format!("Failed to decode \"{}\": skipped {} faulty chunks", path.display(), n)),
+                };
+            },
+            EvaluatedChunk::Faulty(err) => {
+                let at = chunks.position();
+
+                let skip = match chunks.is_exhausted() {
+                    true => None,
+                    false => recovery_skip(&mut chunks),
+                };
+
+                let Some(skip) = skip else {
+                    return Err(format!("Failed to decode \"{}\": {}", path.display(), err));
+                };
+
+                writeln!(out, "{:>10}  {:<15} FAULT {}, skipping {} bytes", at, "", err, skip).map_err(io_err)?;
+
+                chunks.skip_bytes(skip);
+                faults += 1;
+            },
+        }
+    }
+}
+
+/* Traces every file to stdout, returning whether all of them decoded cleanly */
+pub fn trace_all<'p>(paths: impl Iterator<Item = &'p Path>) -> bool {
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut ok = true;
+
+    for path in paths {
+        if let Err(err) = trace(path, &mut out) {
+            let _ = out.flush();
+            eprintln!("{}", err);
+            ok = false;
+        }
+    }
+
+    let _ = out.flush();
+
+    ok
+}
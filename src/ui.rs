@@ -0,0 +1,72 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use crate::overlay;
+use crate::text;
+
+/* Text drawn inside the window with the embedded font of `text`, on the
+ * translucent panels of `overlay` */
+pub const TEXT_SCALE: u32 = 2;
+pub const TEXT_COLOR: Color = Color::RGBA(230, 230, 230, 255);
+pub const WARNING_COLOR: Color = Color::RGBA(255, 160, 64, 255);
+
+const PADDING: i32 = 8;
+pub const MARGIN: i32 = 16;
+
+/* Size of a panel holding these lines */
+pub fn panel_size(lines: &[(String, Color)]) -> (u32, u32) {
+    let (width, height) = text::block_size(&lines.iter().map(|(line, _)| line.as_str()).collect::<Vec<_>>(), TEXT_SCALE);
+    (width + 2 * PADDING as u32, height + 2 * PADDING as u32)
+}
+
+/* Lines of text on a panel with its top left corner at `at` */
+pub fn text_panel(canvas: &mut WindowCanvas, lines: &[(String, Color)], at: (i32, i32)) {
+    let (width, height) = panel_size(lines);
+    let panel = Rect::new(at.0, at.1, width, height);
+
+    overlay::panel(canvas, panel);
+
+    let line_height = text::line_height(TEXT_SCALE);
+
+    for (i, (line, color)) in lines.iter().enumerate() {
+        let y = panel.y() + PADDING + (i as u32 * line_height) as i32;
+        text::draw(canvas, line, (panel.x() + PADDING, y), TEXT_SCALE, *color);
+    }
+}
+
+/* A strip along the bottom of the window, with the status on the left and
+ * details such as the hovered pixel on the right */
+pub fn status_bar(canvas: &mut WindowCanvas, status: &str, details: &str) {
+    let (width, height) = canvas.output_size().unwrap();
+    let bar_height = status_bar_height();
+
+    let bar = Rect::new(0, height as i32 - bar_height as i32, width, bar_height);
+    overlay::panel(canvas, bar);
+
+    let y = bar.y() + PADDING;
+    let (details_width, _) = text::size(details, TEXT_SCALE);
+
+    // The status gives way to the details when both do not fit
+    let room = (width as i32 - details_width as i32 - 3 * PADDING).max(0) as u32;
+    let status = truncate(status, room);
+
+    text::draw(canvas, &status, (PADDING, y), TEXT_SCALE, TEXT_COLOR);
+    text::draw(canvas, details, (width as i32 - PADDING - details_width as i32, y), TEXT_SCALE, TEXT_COLOR);
+}
+
+pub fn status_bar_height() -> u32 {
+    text::size("", TEXT_SCALE).1 + 2 * PADDING as u32
+}
+
+/* As much of the text as fits in `width` pixels, ending in ".." when cut */
+fn truncate(line: &str, width: u32) -> String {
+    if text::size(line, TEXT_SCALE).0 <= width {
+        return line.to_string();
+    }
+
+    let fits = |n: usize| text::size(&"x".repeat(n), TEXT_SCALE).0 <= width;
+    let keep = (0..line.chars().count()).rev().find(|&n| fits(n + 2)).unwrap_or(0);
+
+    line.chars().take(keep).chain("..".chars()).collect()
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use crate::decoder::{EvaluatedChunk, ImageDecoder, QOIError, QOIHeader};
+use crate::input;
+use crate::metadata::Metadata;
+
+/* Fully decodes a QOI file and checks it against the specification. The
+ * error names the first violation and, where there is one, its byte offset.
+ * A metadata block after the end marker is returned, or with `strict` an
+ * error like any other trailing data */
+pub fn check(path: &Path, strict: bool) -> Result<(QOIHeader, Option<Metadata>), String> {
+    let bytes = input::read(path)
+        .map_err(|err| format!("Failed to read file: {}", err))?;
+
+    let dec = ImageDecoder::new(&bytes[..]).map_err(|err| match err {
+        QOIError::IO(_) => format!("File is {} bytes, shorter than the 14 byte header", bytes.len()),
+        QOIError::IncorrectMagic => "Missing \"qoif\" magic at byte 0".to_string(),
+        err => err.to_string(),
+    })?;
+
+    let header = dec.header().clone();
+
+    // Decodes fine, but only this viewer knows what to make of it
+    if matches!(header.channels, 1 | 2) {
+        return Err(format!("Gray channel count {} at byte 12 is an extension, not standard QOI", header.channels));
+    }
+
+    if !matches!(header.channels, 3 | 4) {
+        return Err(format!("Invalid channel count {} at byte 12, expected 3 or 4", header.channels));
+    }
+
+    if !matches!(header.colorspace, 0 | 1) {
+        return Err(format!("Invalid colorspace {} at byte 13, expected 0 or 1", header.colorspace));
+    }
+
+    let expected = header.width as u64 * header.height as u64;
+    let mut decoded = 0u64;
+
+    let mut chunks = dec.chunks_iter();
+
+    loop {
+        match chunks.next_chunk() {
+            EvaluatedChunk::Ok(_) => {
+                decoded += 1;
+
+                if decoded > expected {
+                    return Err(format!(
+                        "More than the {} pixels of a {}x{} image, chunk ending at byte {} overflows",
+                        expected, header.width, header.height, chunks.position()
+                    ));
+                }
+            },
+            EvaluatedChunk::EndMarker => break,
+            EvaluatedChunk::Faulty(err) => return Err(err),
+        }
+    }
+
+    let marker_at = chunks.position();
+
+    if decoded < expected {
+        return Err(format!(
+            "Only {} of the {} pixels of a {}x{} image before the end marker at byte {}",
+            decoded, expected, header.width, header.height, marker_at
+        ));
+    }
+
+    let trailing = chunks.into_source().len();
+
+    if trailing == 0 {
+        return Ok((header, None));
+    }
+
+    match Metadata::trailing(&bytes, trailing) {
+        Some(_) if strict => Err(format!("{} bytes of metadata after the end marker at byte {}", trailing, marker_at)),
+        Some(metadata) => Ok((header, Some(metadata))),
+        None => Err(format!("{} bytes of trailing data after the end marker at byte {}", trailing, marker_at)),
+    }
+}
+
+/* Checks every file and prints a line for each, returning whether all of them passed */
+pub fn check_all<'p>(paths: impl Iterator<Item = &'p Path>, strict: bool) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        match check(path, strict) {
+            Ok((header, metadata)) => println!(
+                "{}: ok, {}x{}, {} channels{}",
+                path.display(), header.width, header.height, header.channels,
+                if metadata.is_some() { ", with metadata" } else { "" }
+            ),
+            Err(err) => {
+                println!("{}: {}", path.display(), err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use crate::decoder::{ImageDecoder, Pixel, QOIHeader};
+use crate::encoder::ImageEncoder;
+use crate::input;
+
+/* Sizes of a file that survived a round trip through the encoder */
+pub struct RoundTrip {
+    pub header: QOIHeader,
+    pub original_size: usize,
+    pub reencoded_size: usize,
+}
+
+fn decode(data: &[u8]) -> Result<(QOIHeader, Vec<Pixel>), String> {
+    let dec = ImageDecoder::new(data).map_err(|err| err.to_string())?;
+    let header = dec.header().clone();
+
+    let pixels = dec.chunks_iter().collect::<Result<Vec<_>, _>>()?;
+
+    Ok((header, pixels))
+}
+
+fn encode(header: &QOIHeader, pixels: &[Pixel]) -> Result<Vec<u8>, String> {
+    ImageEncoder::new(Vec::new(), header)
+        .encode_from_iter(pixels.iter().copied())
+        .map_err(|err| err.to_string())
+}
+
+/* Decodes, re-encodes and decodes again, requiring the same pixels both times */
+pub fn round_trip(path: &Path) -> Result<RoundTrip, String> {
+    let data = input::read(path)
+        .map_err(|err| format!("Failed to read file: {}", err))?;
+
+    let (header, pixels) = decode(&data).map_err(|err| format!("Failed to decode: {}", err))?;
+
+    let expected = header.width as usize * header.height as usize;
+    if pixels.len() != expected {
+        return Err(format!("Decoded {} pixels, the header declares {}", pixels.len(), expected));
+    }
+
+    let reencoded = encode(&header, &pixels).map_err(|err| format!("Failed to encode: {}", err))?;
+
+    let (_, redecoded) = decode(&reencoded).map_err(|err| format!("Failed to decode the re-encoded image: {}", err))?;
+
+    if let Some(i) = (0..pixels.len().max(redecoded.len())).find(|&i| pixels.get(i) != redecoded.get(i)) {
+        let width = header.width.max(1) as usize;
+        let show = |p: Option<&Pixel>| p.map_or("nothing".to_string(), |p| p.to_hex());
+
+        return Err(format!(
+            "Round trip changed pixel ({}, {}): {} became {}",
+            i % width, i / width, show(pixels.get(i)), show(redecoded.get(i))
+        ));
+    }
+
+    Ok(RoundTrip { header, original_size: data.len(), reencoded_size: reencoded.len() })
+}
+
+/* Verifies every file, returning whether all of them round tripped */
+pub fn verify_all<'p>(paths: impl Iterator<Item = &'p Path>) -> bool {
+    let mut ok = true;
+
+    for path in paths {
+        match round_trip(path) {
+            Ok(trip) => println!(
+                "{}: ok, {}x{}, {} bytes, re-encoded {} bytes",
+                path.display(), trip.header.width, trip.header.height, trip.original_size, trip.reencoded_size
+            ),
+            Err(err) => {
+                println!("{}: {}", path.display(), err);
+                ok = false;
+            }
+        }
+    }
+
+    ok
+}
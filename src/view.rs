@@ -0,0 +1,330 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use sdl2::rect::Rect;
+
+const MIN_ZOOM: f32 = 1.0 / 64.0;
+const MAX_ZOOM: f32 = 64.0;
+
+const TRANSITION_TIME: Duration = Duration::from_millis(120);
+
+// Fraction of its speed a glide keeps after a second
+const GLIDE_DECAY: f32 = 0.03;
+
+// Window pixels per second below which a glide stops, or never starts
+const MIN_GLIDE_SPEED: f32 = 30.0;
+
+// A drag let go of after holding still this long does not glide
+const GLIDE_HOLD: Duration = Duration::from_millis(60);
+
+/* How the image is initially laid out in the window */
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Scaling {
+    Fit,       // As large as fits in the window
+    Actual,    // One image pixel per window pixel
+    Zoom(f32), // A fixed scale factor
+    Integer,   // Largest whole multiple that fits, for pixel art
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(Scaling::Fit),
+            "actual" => Ok(Scaling::Actual),
+            "integer" => Ok(Scaling::Integer),
+            _ => Err(format!("Unknown scaling mode: {}", s)),
+        }
+    }
+}
+
+/* Maps image coordinates to window coordinates and back. The image is
+ * scaled uniformly by `zoom` and its top left corner placed at `offset` */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct View {
+    pub zoom: f32,
+    pub offset: (f32, f32),
+}
+
+impl View {
+    pub fn new(scaling: Scaling, image: (u32, u32), window: (u32, u32)) -> Self {
+        match scaling {
+            Scaling::Fit => Self::fit(image, window),
+            Scaling::Actual => Self::actual_size(image, window),
+            Scaling::Zoom(zoom) => Self::centered(zoom, image, window),
+            Scaling::Integer => Self::integer(image, window),
+        }
+    }
+
+    /* Centered at the largest whole zoom that fits, or unscaled for images
+     * larger than the window, so that every image pixel is equally wide */
+    pub fn integer(image: (u32, u32), window: (u32, u32)) -> Self {
+        let zoom = Self::fit(image, window).zoom.floor().max(1.0);
+        Self::centered(zoom, image, window)
+    }
+
+    /* Largest uniform scale that fits the whole image, centered in the window */
+    pub fn fit(image: (u32, u32), window: (u32, u32)) -> Self {
+        let (iw, ih) = (image.0.max(1) as f32, image.1.max(1) as f32);
+        let (ww, wh) = (window.0 as f32, window.1 as f32);
+
+        let zoom = (ww / iw).min(wh / ih);
+
+        Self {
+            zoom,
+            offset: ((ww - iw * zoom) / 2.0, (wh - ih * zoom) / 2.0),
+        }
+    }
+
+    /* Unscaled, centered in the window */
+    pub fn actual_size(image: (u32, u32), window: (u32, u32)) -> Self {
+        Self::centered(1.0, image, window)
+    }
+
+    /* Scaled by `zoom`, centered in the window */
+    pub fn centered(zoom: f32, image: (u32, u32), window: (u32, u32)) -> Self {
+        Self {
+            zoom,
+            offset: (
+                ((window.0 as f32 - image.0 as f32 * zoom) / 2.0).round(),
+                ((window.1 as f32 - image.1 as f32 * zoom) / 2.0).round(),
+            ),
+        }
+    }
+
+    /* Destination rectangle of the whole image on the window */
+    pub fn dest_rect(&self, image: (u32, u32)) -> Rect {
+        Rect::new(
+            self.offset.0.round() as i32,
+            self.offset.1.round() as i32,
+            ((image.0 as f32) * self.zoom).round().max(1.0) as u32,
+            ((image.1 as f32) * self.zoom).round().max(1.0) as u32,
+        )
+    }
+
+    /* Scales by `factor` around a window position, which stays over the same image point */
+    pub fn zoomed_at(&self, factor: f32, (x, y): (i32, i32)) -> Self {
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let (ix, iy) = self.window_to_image(x, y);
+
+        Self {
+            zoom,
+            offset: (x as f32 - ix * zoom, y as f32 - iy * zoom),
+        }
+    }
+
+    /* The next whole zoom in or out around a window position, or 1/n once
+     * zoomed out past 1, on the pixel grid */
+    pub fn stepped_at(&self, zoom_in: bool, at: (i32, i32)) -> Self {
+        let zoom = match self.zoom >= 1.0 {
+            true => {
+                let n = self.zoom.round();
+
+                match (zoom_in, n > 1.0) {
+                    (true, _) => n + 1.0,
+                    (false, true) => n - 1.0,
+                    (false, false) => 0.5,
+                }
+            },
+            false => {
+                let n = (1.0 / self.zoom).round();
+
+                match (zoom_in, n > 2.0) {
+                    (true, true) => 1.0 / (n - 1.0),
+                    (true, false) => 1.0,
+                    (false, _) => 1.0 / (n + 1.0),
+                }
+            },
+        };
+
+        self.zoomed_at(zoom / self.zoom, at).aligned()
+    }
+
+    /* Moved to whole window pixels, so that pixel edges fall on pixel edges */
+    pub fn aligned(&self) -> Self {
+        Self {
+            zoom: self.zoom,
+            offset: (self.offset.0.round(), self.offset.1.round()),
+        }
+    }
+
+    /* Same zoom, with an image point in the middle of the window */
+    pub fn centered_on(&self, (x, y): (f32, f32), window: (u32, u32)) -> Self {
+        Self {
+            zoom: self.zoom,
+            offset: (window.0 as f32 / 2.0 - x * self.zoom, window.1 as f32 / 2.0 - y * self.zoom),
+        }
+    }
+
+    pub fn panned(&self, dx: i32, dy: i32) -> Self {
+        Self {
+            zoom: self.zoom,
+            offset: (self.offset.0 + dx as f32, self.offset.1 + dy as f32),
+        }
+    }
+
+    /* Panned by fractions of a pixel, but only until the image edges reach
+     * the middle of the window, so that a glide cannot fling the image out
+     * of sight. Also returns along which axes it was stopped */
+    pub fn glided(&self, (dx, dy): (f32, f32), image: (u32, u32), window: (u32, u32)) -> (Self, (bool, bool)) {
+        let axis = |offset: f32, d: f32, image: u32, window: u32| {
+            let middle = window as f32 / 2.0;
+
+            // Never pulled back when a drag already left it past that
+            let low = (middle - image as f32 * self.zoom).min(offset);
+            let high = middle.max(offset);
+
+            let moved = (offset + d).clamp(low, high);
+            (moved, moved != offset + d)
+        };
+
+        let (x, stop_x) = axis(self.offset.0, dx, image.0, window.0);
+        let (y, stop_y) = axis(self.offset.1, dy, image.1, window.1);
+
+        (Self { zoom: self.zoom, offset: (x, y) }, (stop_x, stop_y))
+    }
+
+    pub fn window_to_image(&self, x: i32, y: i32) -> (f32, f32) {
+        (
+            (x as f32 - self.offset.0) / self.zoom,
+            (y as f32 - self.offset.1) / self.zoom,
+        )
+    }
+
+    pub fn image_to_window(&self, x: f32, y: f32) -> (i32, i32) {
+        (
+            (x * self.zoom + self.offset.0).round() as i32,
+            (y * self.zoom + self.offset.1).round() as i32,
+        )
+    }
+
+    /* Integer coordinates of the image pixel under a window position */
+    pub fn pixel_under(&self, x: i32, y: i32, image: (u32, u32)) -> Option<(u32, u32)> {
+        let (ix, iy) = self.window_to_image(x, y);
+
+        if ix < 0.0 || iy < 0.0 || ix >= image.0 as f32 || iy >= image.1 as f32 {
+            None
+        } else {
+            Some((ix as u32, iy as u32))
+        }
+    }
+
+    /* Like `pixel_under`, but positions outside the image snap to its edge */
+    pub fn clamped_pixel_under(&self, x: i32, y: i32, image: (u32, u32)) -> (u32, u32) {
+        let (ix, iy) = self.window_to_image(x, y);
+
+        (
+            (ix.max(0.0) as u32).min(image.0.saturating_sub(1)),
+            (iy.max(0.0) as u32).min(image.1.saturating_sub(1)),
+        )
+    }
+}
+
+/* Eases from one view to another, so that zooming does not snap. The zoom
+ * changes by the same factor every frame, and the offset along with it such
+ * that the point both views agree on stays put, which is where the zoom was
+ * anchored. A transition that starts from the middle of another keeps that
+ * going smoothly */
+#[derive(Debug, Clone, Copy)]
+pub struct Transition {
+    from: View,
+    pub to: View,
+    start: Instant,
+}
+
+impl Transition {
+    pub fn new(from: View, to: View) -> Self {
+        Self { from, to, start: Instant::now() }
+    }
+
+    /* The view to show now, or None once it is `to` */
+    pub fn at(&self, now: Instant) -> Option<View> {
+        let t = now.saturating_duration_since(self.start).as_secs_f32() / TRANSITION_TIME.as_secs_f32();
+
+        if t >= 1.0 {
+            return None;
+        }
+
+        // Fast at first, settling into the target
+        let eased = 1.0 - (1.0 - t).powi(3);
+        let (from, to) = (self.from, self.to);
+
+        let zoom = from.zoom * (to.zoom / from.zoom).powf(eased);
+
+        // Linear in the zoom keeps the fixed point in place, plain panning
+        // has no zoom to follow
+        let s = match (to.zoom - from.zoom).abs() > f32::EPSILON {
+            true => (zoom - from.zoom) / (to.zoom - from.zoom),
+            false => eased,
+        };
+
+        Some(View {
+            zoom,
+            offset: (
+                from.offset.0 + (to.offset.0 - from.offset.0) * s,
+                from.offset.1 + (to.offset.1 - from.offset.1) * s,
+            ),
+        })
+    }
+}
+
+/* Keeps a drag going once it is let go of, slowing down until it stops.
+ * Follows the speed of the drag while that lasts */
+#[derive(Debug, Clone, Copy)]
+pub struct Glide {
+    velocity: (f32, f32), // Window pixels per second
+    last: Instant,
+}
+
+impl Glide {
+    /* Before the first step of a drag */
+    pub fn start() -> Self {
+        Self { velocity: (0.0, 0.0), last: Instant::now() }
+    }
+
+    /* Takes in one step of the drag. Averaged with the steps before, since
+     * single events arrive unevenly */
+    pub fn track(&mut self, dx: i32, dy: i32) {
+        let now = Instant::now();
+        let dt = now.saturating_duration_since(self.last).as_secs_f32().max(0.001);
+
+        let average = |v: f32, d: i32| v * 0.5 + d as f32 / dt * 0.5;
+        self.velocity = (average(self.velocity.0, dx), average(self.velocity.1, dy));
+        self.last = now;
+    }
+
+    /* Where the drag ends: still moving fast enough, it goes on gliding */
+    pub fn release(self) -> Option<Glide> {
+        let (vx, vy) = self.velocity;
+        let moving = self.last.elapsed() < GLIDE_HOLD && vx.hypot(vy) >= MIN_GLIDE_SPEED;
+
+        moving.then(|| Glide { velocity: self.velocity, last: Instant::now() })
+    }
+
+    /* How far to move since the last step, or None once it has stopped */
+    pub fn step(&mut self, now: Instant) -> Option<(f32, f32)> {
+        let dt = now.saturating_duration_since(self.last).as_secs_f32();
+        self.last = now;
+
+        // The distance covered while slowing down exponentially over `dt`
+        let decay = GLIDE_DECAY.powf(dt);
+        let travel = (decay - 1.0) / GLIDE_DECAY.ln();
+
+        let (vx, vy) = self.velocity;
+        self.velocity = (vx * decay, vy * decay);
+
+        (vx.hypot(vy) >= MIN_GLIDE_SPEED).then_some((vx * travel, vy * travel))
+    }
+
+    /* Stops it along the axes the image ran into the edge on */
+    pub fn stop(&mut self, (x, y): (bool, bool)) {
+        if x {
+            self.velocity.0 = 0.0;
+        }
+
+        if y {
+            self.velocity.1 = 0.0;
+        }
+    }
+}
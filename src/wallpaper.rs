@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bitmap::Bitmap;
+
+const FILE_PREFIX: &str = "wallpaper_";
+
+/* Makes the image the desktop wallpaper, returning the file it was saved to.
+ * The desktop reads that file again after a restart, so it is kept in the
+ * data directory rather than a temporary one */
+pub fn set(bitmap: &Bitmap) -> Result<PathBuf, String> {
+    let dir = data_dir().ok_or("no data directory to keep the wallpaper in")?;
+
+    std::fs::create_dir_all(&dir)
+        .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+
+    // A new name every time, as desktops ignore being set to the file they already show
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+
+    let path = dir.join(format!("{}{}.png", FILE_PREFIX, stamp));
+
+    bitmap
+        .save(&path)
+        .map_err(|err| format!("Failed to save {}: {}", path.display(), err))?;
+
+    apply(&path)?;
+    remove_previous(&dir, &path);
+
+    Ok(path)
+}
+
+#[cfg(windows)]
+fn data_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("qoiviewer"))
+}
+
+#[cfg(not(windows))]
+fn data_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))?;
+
+    Some(base.join("qoiviewer"))
+}
+
+#[cfg(windows)]
+fn apply(path: &Path) -> Result<(), String> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPIF_SENDCHANGE, SPIF_UPDATEINIFILE, SPI_SETDESKWALLPAPER,
+    };
+
+    let mut wide = path.as_os_str().encode_wide().chain([0]).collect::<Vec<u16>>();
+
+    // SAFETY: `wide` is a NUL terminated path that outlives the call
+    let ok = unsafe {
+        SystemParametersInfoW(SPI_SETDESKWALLPAPER, 0, wide.as_mut_ptr().cast(), SPIF_UPDATEINIFILE | SPIF_SENDCHANGE)
+    };
+
+    match ok {
+        0 => Err(format!("Failed to set the wallpaper: {}", std::io::Error::last_os_error())),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn apply(path: &Path) -> Result<(), String> {
+    let script = format!(
+        "tell application \"System Events\" to tell every desktop to set picture to {:?}",
+        path.display().to_string()
+    );
+
+    run(Command::new("osascript").arg("-e").arg(script))
+}
+
+/* GNOME and its relatives are set through gsettings, every other desktop
+ * with feh, which also covers bare window managers */
+#[cfg(all(unix, not(target_os = "macos")))]
+fn apply(path: &Path) -> Result<(), String> {
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().to_lowercase();
+    let gnome_like = ["gnome", "unity", "budgie", "pantheon"].iter().any(|name| desktop.contains(name));
+
+    if !gnome_like {
+        return run(Command::new("feh").arg("--bg-fill").arg(path));
+    }
+
+    let uri = format!("file://{}", path.display());
+
+    // The dark style has a wallpaper of its own
+    for key in ["picture-uri", "picture-uri-dark"] {
+        run(Command::new("gsettings").args(["set", "org.gnome.desktop.background", key, &uri]))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run(command: &mut Command) -> Result<(), String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let status = command
+        .status()
+        .map_err(|err| format!("Failed to run {}: {}", program, err))?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("{} failed with {}", program, status)),
+    }
+}
+
+/* Earlier wallpapers are no longer shown once the new one is set */
+fn remove_previous(dir: &Path, current: &Path) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let is_wallpaper = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(FILE_PREFIX));
+
+        if is_wallpaper && path != current {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::filelist::{FileList, Filter};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/* What identifies a version of a file on disk */
+type Stamp = Option<(SystemTime, u64)>;
+
+/* Polls the modification time and size of a file. A change stays reported
+ * until it is acknowledged, so a reload that fails on a half written file
+ * is retried on the next poll */
+pub struct FileWatcher {
+    path: PathBuf,
+    seen: Stamp,
+    current: Stamp,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> Self {
+        let stamp = stamp(path);
+
+        Self {
+            path: path.to_path_buf(),
+            seen: stamp,
+            current: stamp,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() >= POLL_INTERVAL {
+            self.last_poll = Instant::now();
+            self.current = stamp(&self.path);
+        }
+
+        // A file that disappeared is left alone until it comes back
+        self.current.is_some() && self.current != self.seen
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.seen = self.current;
+    }
+}
+
+/* Polls a directory for files that appear in it and pass the filter, as a
+ * renderer or screenshot tool writes them. A new file is only reported once
+ * its size and modification time held still between two polls, so that one
+ * still being written is not opened half way */
+pub struct DirWatcher {
+    dir: PathBuf,
+    filter: Filter,
+    known: HashSet<PathBuf>,
+    pending: HashMap<PathBuf, Stamp>, // New files as they were on the last poll
+    last_poll: Instant,
+}
+
+impl DirWatcher {
+    /* Only files that are not `known` yet count as new */
+    pub fn new(dir: &Path, filter: Filter, known: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            filter,
+            known: known.into_iter().collect(),
+            pending: HashMap::new(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /* The files that settled since the last call, oldest first */
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+
+        self.last_poll = Instant::now();
+
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| !self.known.contains(path) && path.is_file() && self.filter.matches(path))
+                .collect::<Vec<_>>(),
+            Err(_) => return Vec::new(),
+        };
+
+        // Files that went away before they settled are forgotten
+        self.pending.retain(|path, _| entries.contains(path));
+
+        let mut settled = Vec::new();
+
+        for path in entries {
+            let now = stamp(&path);
+
+            if now.is_some() && self.pending.insert(path.clone(), now) == Some(now) {
+                self.pending.remove(&path);
+                self.known.insert(path.clone());
+                settled.push((now, path));
+            }
+        }
+
+        settled.sort();
+        settled.into_iter().map(|(_, path)| path).collect()
+    }
+
+    /* Blocks until the first new files settle */
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        loop {
+            let new = self.poll();
+
+            if !new.is_empty() {
+                return new;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/* Position of the most recently modified file in the list */
+pub fn newest(files: &FileList) -> Option<usize> {
+    files
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, path)| stamp(path))
+        .map(|(i, _)| i)
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}